@@ -98,9 +98,10 @@ class DashboardPage(QDialog):
         self.team_id = selected_team.id
         if(self.activity_state == ActivityState.STARTED):
             self.event_queue.on_next(EventDetail(EventTypes.RESET_ACTIVITY, self.team_id))
-        configs = self.client.get_team_configuration(self.team_id)
-        for app in configs:
-            self.apps_layout.addWidget(ChipButton(app), alignment=QtCore.Qt.AlignmentFlag.AlignLeft)
+        configs = self.client.get_team_configuration(self.team_id) or {}
+        for rule in configs.get("tracked_apps", []):
+            label = rule["app"] if rule.get("title") is None else f'{rule["app"]} ({rule["title"]})'
+            self.apps_layout.addWidget(ChipButton(label), alignment=QtCore.Qt.AlignmentFlag.AlignLeft)
         self.apps_layout.addStretch()
             
                 
@@ -1,13 +1,32 @@
+use aw_models::ApiKey;
+use aw_models::AppRule;
+use aw_models::AuditEntry;
+use aw_models::AuditFilter;
+use aw_models::AuditPage;
 use aw_models::Bucket;
 use aw_models::BucketMetadata;
 use aw_models::Event;
+use aw_models::Invitation;
+use aw_models::LoginAttempts;
 use aw_models::Member;
+use aw_models::MemberSchedule;
+use aw_models::OneTimeToken;
+use aw_models::OutboxMessage;
 use aw_models::PublicUser;
+use aw_models::RefreshToken;
+use aw_models::Role;
 use aw_models::Team;
 use aw_models::TeamConfiguration;
+use aw_models::TeamConfigurationRevision;
 use aw_models::TeamRequestModel;
 use aw_models::TeamUserModel;
+use aw_models::TokenPurpose;
+use aw_models::TwoFactor;
 use aw_models::User;
+use aw_models::UserFilter;
+use aw_models::UserPage;
+use aw_models::UserProfile;
+use aw_models::UserSort;
 use chrono::DateTime;
 use chrono::Duration;
 use chrono::Utc;
@@ -46,8 +65,26 @@ pub fn generate_hash(password: &str) -> String {
  * 2: Added 'data' field to 'buckets' table
  * 3: see: https://github.com/ActivityWatch/aw-server-rust/pull/52
  * 4: Added 'key_value' table for storing key - value pairs
+ * 5: Added users, teams and team configuration tables
+ * 6: Added tables for refresh tokens and revoked access tokens
+ * 7: Added 'role' field to 'TeamsUsers' table
+ * 8: Added 'must_change_password' field to 'Users' table
+ * 9: Added tables for one-time tokens and the notification outbox
+ * 10: Added table for API keys
+ * 11: Added table for failed login attempts
+ * 12: Added tables for two-factor authentication
+ * 13: Added 'active' field to 'Users' table
+ * 14: Added table for team invitations
+ * 15: Added 'email_verified' field to 'Users' table
+ * 16: Added append-only table for the audit log
+ * 17: Added table for identities of users at OpenID Connect providers
+ * 18: Replaced the comma-joined 'apps' field of 'TeamConfiguration' with a JSON 'data' field
+ * 19: Replaced the 'TeamConfiguration' table with a table of configuration revisions
+ * 20: Added 'schedule' field to 'TeamsUsers' table
+ * 21: Made the members of a team unique in the 'TeamsUsers' table
+ * 22: Added 'tokenGeneration' field to 'Users' table
  */
-static NEWEST_DB_VERSION: i32 = 5;
+static NEWEST_DB_VERSION: i32 = 22;
 
 fn _create_tables(conn: &Connection, version: i32) -> bool {
     let mut first_init = false;
@@ -71,6 +108,57 @@ fn _create_tables(conn: &Connection, version: i32) -> bool {
     if version < 5 {
         _migrate_new_version(conn);
     }
+    if version < 6 {
+        _migrate_v5_to_v6(conn);
+    }
+    if version < 7 {
+        _migrate_v6_to_v7(conn);
+    }
+    if version < 8 {
+        _migrate_v7_to_v8(conn);
+    }
+    if version < 9 {
+        _migrate_v8_to_v9(conn);
+    }
+    if version < 10 {
+        _migrate_v9_to_v10(conn);
+    }
+    if version < 11 {
+        _migrate_v10_to_v11(conn);
+    }
+    if version < 12 {
+        _migrate_v11_to_v12(conn);
+    }
+    if version < 13 {
+        _migrate_v12_to_v13(conn);
+    }
+    if version < 14 {
+        _migrate_v13_to_v14(conn);
+    }
+    if version < 15 {
+        _migrate_v14_to_v15(conn);
+    }
+    if version < 16 {
+        _migrate_v15_to_v16(conn);
+    }
+    if version < 17 {
+        _migrate_v16_to_v17(conn);
+    }
+    if version < 18 {
+        _migrate_v17_to_v18(conn);
+    }
+    if version < 19 {
+        _migrate_v18_to_v19(conn);
+    }
+    if version < 20 {
+        _migrate_v19_to_v20(conn);
+    }
+    if version < 21 {
+        _migrate_v20_to_v21(conn);
+    }
+    if version < 22 {
+        _migrate_v21_to_v22(conn);
+    }
     first_init
 }
 
@@ -260,6 +348,468 @@ fn _migrate_new_version(conn: &Connection) {
     conn.pragma_update(None, "user_version", 5)
         .expect("Failed to update database version!");
 }
+
+fn _migrate_v5_to_v6(conn: &Connection) {
+    info!("Upgrading database to v6, adding tables for refresh tokens and token revocation");
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS RefreshTokens (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            userId INTEGER NOT NULL,
+            token TEXT UNIQUE NOT NULL,
+            expires INTEGER NOT NULL,
+            FOREIGN KEY (userId) REFERENCES Users(id)
+        )",
+        [],
+    )
+    .expect("Failed to create RefreshTokens table");
+
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS RevokedTokens (
+            jti TEXT PRIMARY KEY,
+            expires INTEGER NOT NULL
+        )",
+        [],
+    )
+    .expect("Failed to create RevokedTokens table");
+
+    conn.pragma_update(None, "user_version", 6)
+        .expect("Failed to update database version!");
+}
+fn _migrate_v6_to_v7(conn: &Connection) {
+    info!("Upgrading database to v7, adding roles to team memberships");
+    conn.execute(
+        &format!(
+            "ALTER TABLE TeamsUsers ADD COLUMN role INTEGER NOT NULL DEFAULT {};",
+            Role::Member.id()
+        ),
+        [],
+    )
+    .expect("Failed to upgrade database when adding role field to TeamsUsers");
+
+    // Signup used to give every user the admin role, only keep it for the account created on
+    // first start and make the owners of teams team owners
+    conn.execute(
+        "UPDATE Users SET role = ?1 WHERE role = ?2 AND username != 'admin'",
+        params![Role::Member.id(), Role::Admin.id()],
+    )
+    .expect("Failed to upgrade database when updating user roles");
+    conn.execute(
+        "UPDATE Users SET role = ?1 WHERE role = ?2 AND id IN (SELECT ownerId FROM Teams)",
+        params![Role::TeamOwner.id(), Role::Member.id()],
+    )
+    .expect("Failed to upgrade database when updating user roles");
+
+    conn.pragma_update(None, "user_version", 7)
+        .expect("Failed to update database version!");
+}
+
+fn _migrate_v7_to_v8(conn: &Connection) {
+    info!("Upgrading database to v8, adding must_change_password field to users");
+    conn.execute(
+        "ALTER TABLE Users ADD COLUMN must_change_password INTEGER NOT NULL DEFAULT 0;",
+        [],
+    )
+    .expect("Failed to upgrade database when adding must_change_password field to Users");
+
+    // The admin account is created with a default password which has to be changed on first login
+    conn.execute(
+        "UPDATE Users SET must_change_password = 1 WHERE username = 'admin'",
+        [],
+    )
+    .expect("Failed to upgrade database when flagging the admin account");
+
+    conn.pragma_update(None, "user_version", 8)
+        .expect("Failed to update database version!");
+}
+
+fn _migrate_v8_to_v9(conn: &Connection) {
+    info!("Upgrading database to v9, adding tables for one-time tokens and the outbox");
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS OneTimeTokens (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            userId INTEGER NOT NULL,
+            purpose TEXT NOT NULL,
+            token TEXT UNIQUE NOT NULL,
+            expires INTEGER NOT NULL,
+            FOREIGN KEY (userId) REFERENCES Users(id)
+        )",
+        [],
+    )
+    .expect("Failed to create OneTimeTokens table");
+
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS Outbox (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            recipient TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            body TEXT NOT NULL,
+            created INTEGER NOT NULL
+        )",
+        [],
+    )
+    .expect("Failed to create Outbox table");
+
+    conn.pragma_update(None, "user_version", 9)
+        .expect("Failed to update database version!");
+}
+
+fn _migrate_v9_to_v10(conn: &Connection) {
+    info!("Upgrading database to v10, adding table for API keys");
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS ApiKeys (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            userId INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            token TEXT UNIQUE NOT NULL,
+            created INTEGER NOT NULL,
+            lastUsed INTEGER,
+            FOREIGN KEY (userId) REFERENCES Users(id)
+        )",
+        [],
+    )
+    .expect("Failed to create ApiKeys table");
+
+    conn.pragma_update(None, "user_version", 10)
+        .expect("Failed to update database version!");
+}
+
+fn _migrate_v10_to_v11(conn: &Connection) {
+    info!("Upgrading database to v11, adding table for failed login attempts");
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS LoginAttempts (
+            subject TEXT PRIMARY KEY,
+            failures INTEGER NOT NULL,
+            lastFailure INTEGER NOT NULL,
+            lockedUntil INTEGER NOT NULL
+        )",
+        [],
+    )
+    .expect("Failed to create LoginAttempts table");
+
+    conn.pragma_update(None, "user_version", 11)
+        .expect("Failed to update database version!");
+}
+
+fn _migrate_v11_to_v12(conn: &Connection) {
+    info!("Upgrading database to v12, adding tables for two-factor authentication");
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS TwoFactor (
+            userId INTEGER PRIMARY KEY,
+            secret TEXT NOT NULL,
+            enabled INTEGER NOT NULL DEFAULT 0,
+            lastStep INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY (userId) REFERENCES Users(id)
+        )",
+        [],
+    )
+    .expect("Failed to create TwoFactor table");
+
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS RecoveryCodes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            userId INTEGER NOT NULL,
+            code TEXT NOT NULL,
+            FOREIGN KEY (userId) REFERENCES Users(id)
+        )",
+        [],
+    )
+    .expect("Failed to create RecoveryCodes table");
+
+    conn.pragma_update(None, "user_version", 12)
+        .expect("Failed to update database version!");
+}
+
+fn _migrate_v12_to_v13(conn: &Connection) {
+    info!("Upgrading database to v13, adding active field to users");
+    conn.execute("ALTER TABLE Users ADD COLUMN active INTEGER NOT NULL DEFAULT 1", [])
+        .expect("Failed to upgrade database when adding active field to Users");
+
+    conn.pragma_update(None, "user_version", 13)
+        .expect("Failed to update database version!");
+}
+
+fn _migrate_v13_to_v14(conn: &Connection) {
+    info!("Upgrading database to v14, adding table for team invitations");
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS Invitations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            teamId INTEGER NOT NULL,
+            createdBy INTEGER NOT NULL,
+            code TEXT UNIQUE NOT NULL,
+            maxUses INTEGER,
+            uses INTEGER NOT NULL DEFAULT 0,
+            created INTEGER NOT NULL,
+            expires INTEGER NOT NULL,
+            FOREIGN KEY (teamId) REFERENCES Teams(id),
+            FOREIGN KEY (createdBy) REFERENCES Users(id)
+        )",
+        [],
+    )
+    .expect("Failed to create Invitations table");
+
+    conn.pragma_update(None, "user_version", 14)
+        .expect("Failed to update database version!");
+}
+
+fn _migrate_v14_to_v15(conn: &Connection) {
+    info!("Upgrading database to v15, adding email_verified field to users");
+    // Existing accounts are treated as verified, only new signups have to verify their address
+    conn.execute("ALTER TABLE Users ADD COLUMN email_verified INTEGER NOT NULL DEFAULT 1", [])
+        .expect("Failed to upgrade database when adding email_verified field to Users");
+
+    conn.pragma_update(None, "user_version", 15)
+        .expect("Failed to update database version!");
+}
+
+fn _migrate_v15_to_v16(conn: &Connection) {
+    info!("Upgrading database to v16, adding table for the audit log");
+    // The actor has no foreign key, entries stay when the account of the actor is deleted
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS AuditLog (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            actor INTEGER,
+            action TEXT NOT NULL,
+            target TEXT,
+            timestamp INTEGER NOT NULL,
+            address TEXT
+        )",
+        [],
+    )
+    .expect("Failed to create AuditLog table");
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS audit_log_timestamp_index ON AuditLog(timestamp)",
+        [],
+    )
+    .expect("Failed to create audit_log_timestamp_index index");
+    for statement in ["UPDATE", "DELETE"] {
+        conn.execute(
+            &format!(
+                "
+                CREATE TRIGGER IF NOT EXISTS audit_log_no_{} BEFORE {statement} ON AuditLog
+                BEGIN
+                    SELECT RAISE(ABORT, 'The audit log is append-only');
+                END",
+                statement.to_lowercase()
+            ),
+            [],
+        )
+        .expect("Failed to create audit log trigger");
+    }
+
+    conn.pragma_update(None, "user_version", 16)
+        .expect("Failed to update database version!");
+}
+
+fn _migrate_v16_to_v17(conn: &Connection) {
+    info!("Upgrading database to v17, adding table for OpenID Connect identities");
+    // An identity is the subject of an ID token, which is only unique per issuer
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS UserIdentities (
+            issuer TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            userId INTEGER NOT NULL,
+            PRIMARY KEY (issuer, subject),
+            FOREIGN KEY (userId) REFERENCES Users(id)
+        )",
+        [],
+    )
+    .expect("Failed to create UserIdentities table");
+
+    conn.pragma_update(None, "user_version", 17)
+        .expect("Failed to update database version!");
+}
+
+fn _migrate_v17_to_v18(conn: &Connection) {
+    info!("Upgrading database to v18, storing team configurations as JSON");
+    conn.execute(
+        "ALTER TABLE TeamConfiguration RENAME COLUMN apps TO apps_deprecated",
+        [],
+    )
+    .expect("Failed to rename the apps field of TeamConfiguration");
+    conn.execute(
+        "ALTER TABLE TeamConfiguration ADD COLUMN data TEXT NOT NULL DEFAULT '{}'",
+        [],
+    )
+    .expect("Failed to add the data field to TeamConfiguration");
+
+    // The old list was matched against the window titles, every entry becomes a rule matching
+    // the windows whose title contains it
+    let mut stmt = conn
+        .prepare("SELECT id, apps_deprecated FROM TeamConfiguration")
+        .expect("Failed to prepare reading team configurations");
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, i32>(0)?, row.get::<_, Option<String>>(1)?)))
+        .expect("Failed to read team configurations")
+        .collect::<Result<Vec<_>, _>>()
+        .expect("Failed to read team configurations");
+    for (id, apps) in rows {
+        let config = TeamConfiguration {
+            tracked_apps: apps
+                .unwrap_or_default()
+                .split(',')
+                .filter(|app| !app.is_empty())
+                .map(AppRule::title_contains)
+                .collect(),
+            ..Default::default()
+        };
+        conn.execute(
+            "UPDATE TeamConfiguration SET data = ?1 WHERE id = ?2",
+            params![serde_json::to_string(&config).unwrap(), id],
+        )
+        .expect("Failed to convert team configuration");
+    }
+
+    conn.pragma_update(None, "user_version", 18)
+        .expect("Failed to update database version!");
+}
+
+fn _migrate_v18_to_v19(conn: &Connection) {
+    info!("Upgrading database to v19, adding table for team configuration revisions");
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS TeamConfigurationRevisions (
+            teamId INTEGER NOT NULL,
+            revision INTEGER NOT NULL,
+            data TEXT NOT NULL,
+            author INTEGER,
+            created INTEGER NOT NULL,
+            restoredFrom INTEGER,
+            PRIMARY KEY (teamId, revision),
+            FOREIGN KEY (teamId) REFERENCES Teams(id)
+        )",
+        [],
+    )
+    .expect("Failed to create TeamConfigurationRevisions table");
+    // It is unknown since when the existing configurations are in effect, so they apply to all
+    // events recorded before the next revision
+    conn.execute(
+        "INSERT INTO TeamConfigurationRevisions (teamId, revision, data, created)
+            SELECT teamId, 1, data, 0 FROM TeamConfiguration
+            WHERE id IN (SELECT MAX(id) FROM TeamConfiguration GROUP BY teamId)
+                AND teamId IN (SELECT id FROM Teams)",
+        [],
+    )
+    .expect("Failed to copy team configurations");
+    conn.execute("DROP TABLE TeamConfiguration", [])
+        .expect("Failed to drop TeamConfiguration table");
+
+    conn.pragma_update(None, "user_version", 19)
+        .expect("Failed to update database version!");
+}
+
+fn _migrate_v19_to_v20(conn: &Connection) {
+    info!("Upgrading database to v20, adding schedule field to TeamsUsers");
+    // JSON of a MemberSchedule, NULL if the member keeps the working hours of the team
+    conn.execute("ALTER TABLE TeamsUsers ADD COLUMN schedule TEXT", [])
+        .expect("Failed to add the schedule field to TeamsUsers");
+
+    conn.pragma_update(None, "user_version", 20)
+        .expect("Failed to update database version!");
+}
+
+fn _migrate_v20_to_v21(conn: &Connection) {
+    info!("Upgrading database to v21, making team members unique");
+    // Users added more than once keep their first membership
+    conn.execute(
+        "DELETE FROM TeamsUsers WHERE id NOT IN
+            (SELECT MIN(id) FROM TeamsUsers GROUP BY teamId, userId)",
+        [],
+    )
+    .expect("Failed to remove duplicate team members");
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS teams_users_member_index ON TeamsUsers(teamId, userId)",
+        [],
+    )
+    .expect("Failed to create teams_users_member index");
+
+    conn.pragma_update(None, "user_version", 21)
+        .expect("Failed to update database version!");
+}
+
+fn _migrate_v21_to_v22(conn: &Connection) {
+    info!("Upgrading database to v22, adding tokenGeneration field to Users");
+    // Access tokens carry the generation they were issued in, raising it revokes all of them
+    conn.execute(
+        "ALTER TABLE Users ADD COLUMN tokenGeneration INTEGER NOT NULL DEFAULT 0",
+        [],
+    )
+    .expect("Failed to add the tokenGeneration field to Users");
+
+    conn.pragma_update(None, "user_version", 22)
+        .expect("Failed to update database version!");
+}
+
+// Team memberships of users who have not verified their email address yet have no effect
+const VERIFIED_USERS: &str = "(SELECT id FROM Users WHERE email_verified = 1)";
+
+// Column expression for PublicUser.two_factor_enabled in queries on the Users table
+const TWO_FACTOR_ENABLED: &str =
+    "EXISTS(SELECT 1 FROM TwoFactor WHERE userId = Users.id AND enabled = 1)";
+
+// Conditions of the user directory, parameters that are NULL disable their filter
+const USER_FILTER: &str = "
+    (?1 IS NULL OR name LIKE ?1 ESCAPE '\\' OR lastname LIKE ?1 ESCAPE '\\'
+        OR email LIKE ?1 ESCAPE '\\' OR username LIKE ?1 ESCAPE '\\')
+    AND (?2 IS NULL OR role = ?2)
+    AND (?3 IS NULL OR id IN (SELECT userId FROM TeamsUsers WHERE teamId = ?3)
+        OR id IN (SELECT ownerId FROM Teams WHERE id = ?3))
+    AND (?4 IS NULL OR active = ?4)";
+
+const AUDIT_FILTER: &str = "
+    (?1 IS NULL OR actor = ?1)
+    AND (?2 IS NULL OR action = ?2 OR action LIKE ?2 || '.%')
+    AND (?3 IS NULL OR target = ?3 OR target LIKE ?3 || '/%')
+    AND (?4 IS NULL OR timestamp >= ?4)
+    AND (?5 IS NULL OR timestamp < ?5)";
+
+fn _role_from_id(id: i8) -> Role {
+    match Role::from_id(id) {
+        Some(role) => role,
+        None => {
+            warn!("Unknown role {}, treating it as member", id);
+            Role::Member
+        }
+    }
+}
+
+/// Runs `f` in a savepoint, so either all of its changes are kept or none of them
+///
+/// The worker batches many requests into one transaction, without a savepoint the statements
+/// which succeeded before an error would be committed together with the following requests.
+fn _in_savepoint<T>(
+    conn: &Connection,
+    name: &str,
+    f: impl FnOnce() -> Result<T, DatastoreError>,
+) -> Result<T, DatastoreError> {
+    if let Err(err) = conn.execute_batch(&format!("SAVEPOINT {name}")) {
+        return Err(DatastoreError::InternalError(format!(
+            "Failed to start savepoint {name}: {err}"
+        )));
+    }
+    let result = f();
+    let end = match result {
+        Ok(_) => format!("RELEASE {name}"),
+        Err(_) => format!("ROLLBACK TO {name}; RELEASE {name}"),
+    };
+    match conn.execute_batch(&end) {
+        Ok(()) => result,
+        Err(err) => Err(DatastoreError::InternalError(format!(
+            "Failed to end savepoint {name}: {err}"
+        ))),
+    }
+}
+
 pub struct DatastoreInstance {
     buckets_cache: HashMap<String, Bucket>,
     first_init: bool,
@@ -573,6 +1123,10 @@ impl DatastoreInstance {
         }
     }
 
+    pub fn get_all_buckets(&self) -> HashMap<String, Bucket> {
+        self.buckets_cache.clone()
+    }
+
     pub fn get_buckets(&mut self, conn: &Connection, user_id: i32) -> HashMap<String, Bucket> {
         let user_bucket_ids = self.get_user_bucket_ids(conn, user_id).unwrap();
         let mut user_buckets: HashMap<String, Bucket> = HashMap::new();
@@ -604,6 +1158,25 @@ impl DatastoreInstance {
             }
         };
         for event in &mut events {
+            // Event ids are unique across all buckets, an id may only replace an event of this
+            // bucket
+            if let Some(id) = event.id {
+                match conn.query_row(
+                    "SELECT 1 FROM events WHERE id = ?1 AND bucketrow = ?2",
+                    params![id, bucket.bid],
+                    |_| Ok(()),
+                ) {
+                    Ok(()) => (),
+                    Err(rusqlite::Error::QueryReturnedNoRows) => {
+                        return Err(DatastoreError::NoSuchEvent(id))
+                    }
+                    Err(err) => {
+                        return Err(DatastoreError::InternalError(format!(
+                            "Failed to query event {id}: {err}"
+                        )))
+                    }
+                }
+            }
             let starttime_nanos = event.timestamp.timestamp_nanos_opt().unwrap();
             let duration_nanos = match event.duration.num_nanoseconds() {
                 Some(nanos) => nanos,
@@ -814,7 +1387,7 @@ impl DatastoreInstance {
 
         let mut stmt = match conn.prepare(
             "
-                SELECT id, starttime, endtime, data
+                SELECT id, starttime, endtime, data, team_id
                 FROM events
                 WHERE bucketrow = ?1
                     AND id = ?2
@@ -847,7 +1420,7 @@ impl DatastoreInstance {
                 timestamp: DateTime::from_timestamp(time_seconds, time_subnanos).unwrap(),
                 duration: Duration::nanoseconds(duration_ns),
                 data,
-                team_id: 1,
+                team_id: row.get(4)?,
             })
         }) {
             Ok(rows) => rows,
@@ -892,7 +1465,7 @@ impl DatastoreInstance {
 
         let mut stmt = match conn.prepare(
             "
-                SELECT id, starttime, endtime, data
+                SELECT id, starttime, endtime, data, team_id
                 FROM events
                 WHERE bucketrow = ?1
                     AND endtime >= ?2
@@ -940,7 +1513,7 @@ impl DatastoreInstance {
                     timestamp: DateTime::from_timestamp(time_seconds, time_subnanos).unwrap(),
                     duration: Duration::nanoseconds(duration_ns),
                     data,
-                    team_id: 1,
+                    team_id: row.get(4)?,
                 })
             },
         ) {
@@ -998,7 +1571,7 @@ impl DatastoreInstance {
 
         let mut stmt = match conn.prepare(
             "
-                SELECT id, starttime, endtime, data
+                SELECT id, starttime, endtime, data, team_id
                 FROM events
                 WHERE bucketrow = ?1
                     AND endtime >= ?2
@@ -1048,7 +1621,7 @@ impl DatastoreInstance {
                     timestamp: DateTime::from_timestamp(time_seconds, time_subnanos).unwrap(),
                     duration: Duration::nanoseconds(duration_ns),
                     data,
-                    team_id: 1,
+                    team_id: row.get(4)?,
                 })
             },
         ) {
@@ -1226,7 +1799,8 @@ impl DatastoreInstance {
         email: String,
     ) -> Result<User, DatastoreError> {
         let mut stmt = match conn.prepare(
-            "SELECT id, username, email, name, lastname, role, password FROM Users WHERE email = ?1 LIMIT 1",
+            "SELECT id, username, email, name, lastname, role, password, must_change_password,
+                email_verified FROM Users WHERE email = ?1 LIMIT 1",
         ) {
             Ok(stmt) => stmt,
             Err(err) => {
@@ -1242,8 +1816,10 @@ impl DatastoreInstance {
                 email: row.get(2)?,
                 name: row.get(3)?,
                 lastname: row.get(4)?,
-                role: row.get(5)?,
+                role: _role_from_id(row.get(5)?),
                 password: row.get(6)?,
+                must_change_password: row.get(7)?,
+                email_verified: row.get(8)?,
             })
         }) {
             Ok(rows) => rows,
@@ -1253,9 +1829,11 @@ impl DatastoreInstance {
     }
 
     pub fn get_user(&self, conn: &Connection, userId: i32) -> Result<PublicUser, DatastoreError> {
-        let mut stmt = match conn
-            .prepare("SELECT id, email, name, lastname, role FROM Users WHERE id = ?1 LIMIT 1")
-        {
+        let mut stmt = match conn.prepare(&format!(
+            "SELECT id, email, username, name, lastname, role, must_change_password,
+                {TWO_FACTOR_ENABLED}, active, email_verified
+                FROM Users WHERE id = ?1 LIMIT 1"
+        )) {
             Ok(stmt) => stmt,
             Err(err) => {
                 return Err(DatastoreError::InternalError(format!(
@@ -1267,9 +1845,14 @@ impl DatastoreInstance {
             Ok(PublicUser {
                 id: row.get(0)?,
                 email: row.get(1)?,
-                name: row.get(2)?,
-                lastname: row.get(3)?,
-                role: row.get(4)?,
+                username: row.get(2)?,
+                name: row.get(3)?,
+                lastname: row.get(4)?,
+                role: _role_from_id(row.get(5)?),
+                must_change_password: row.get(6)?,
+                two_factor_enabled: row.get(7)?,
+                active: row.get(8)?,
+                email_verified: row.get(9)?,
             })
         }) {
             Ok(rows) => rows,
@@ -1279,34 +1862,88 @@ impl DatastoreInstance {
     }
 
     pub fn signup(&self, conn: &Connection, user: User) -> Result<PublicUser, DatastoreError> {
-        conn.execute(
-            "INSERT INTO Users (email, name, lastname, password, role, username) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![user.email, user.name, user.lastname, user.password, 2, user.username],
-        )
-        .expect("Could not insert");
+        if let Err(err) = conn.execute(
+            "INSERT INTO Users (email, name, lastname, password, role, username, must_change_password, email_verified) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![user.email, user.name, user.lastname, user.password, user.role.id(), user.username, user.must_change_password, user.email_verified],
+        ) {
+            return Err(match err {
+                rusqlite::Error::SqliteFailure(sqlerr, _)
+                    if sqlerr.code == rusqlite::ErrorCode::ConstraintViolation =>
+                {
+                    DatastoreError::UserAlreadyExists("Email or username is used".to_string())
+                }
+                _ => DatastoreError::InternalError(format!("Failed to insert user: {err}")),
+            });
+        }
         Ok(PublicUser {
-            id: user.id,
+            id: conn.last_insert_rowid() as i32,
             email: user.email,
+            username: user.username,
             name: user.name,
             lastname: user.lastname,
             role: user.role,
+            must_change_password: user.must_change_password,
+            two_factor_enabled: false,
+            active: true,
+            email_verified: user.email_verified,
         })
     }
 
-    pub fn get_teams(&self, conn: &Connection, ownerId: i32) -> Result<Vec<Team>, DatastoreError> {
-        let mut stmt = match conn.prepare("SELECT * FROM Teams WHERE ownerId = ?1") {
-            Ok(stmt) => stmt,
-            Err(err) => {
-                return Err(DatastoreError::InternalError(format!(
-                    "Failed to prepare get_value SQL statement: {err}"
-                )))
-            }
-        };
-        let rows = match stmt.query_map(params![ownerId], |row| {
-            Ok(Team {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                description: row.get(2)?,
+    pub fn set_password(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+        password_hash: &str,
+        must_change_password: bool,
+    ) -> Result<(), DatastoreError> {
+        match conn.execute(
+            "UPDATE Users SET password = ?1, must_change_password = ?2 WHERE id = ?3",
+            params![password_hash, must_change_password, user_id],
+        ) {
+            Ok(0) => Err(DatastoreError::NoUser()),
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to set password: {err}"
+            ))),
+        }
+    }
+
+    /// Replaces the default credentials of the admin account created on first start
+    ///
+    /// Does nothing once the admin has changed the default password, returns whether the
+    /// credentials were set.
+    pub fn set_initial_admin(
+        &self,
+        conn: &Connection,
+        email: &str,
+        password: &str,
+    ) -> Result<bool, DatastoreError> {
+        match conn.execute(
+            "UPDATE Users SET email = ?1, password = ?2, must_change_password = 0
+                WHERE username = 'admin' AND must_change_password = 1",
+            params![email, generate_hash(password)],
+        ) {
+            Ok(updated) => Ok(updated > 0),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to set initial admin credentials: {err}"
+            ))),
+        }
+    }
+
+    pub fn get_teams(&self, conn: &Connection, ownerId: i32) -> Result<Vec<Team>, DatastoreError> {
+        let mut stmt = match conn.prepare("SELECT * FROM Teams WHERE ownerId = ?1") {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to prepare get_value SQL statement: {err}"
+                )))
+            }
+        };
+        let rows = match stmt.query_map(params![ownerId], |row| {
+            Ok(Team {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                description: row.get(2)?,
                 ownerId: row.get(3)?,
             })
         }) {
@@ -1342,18 +1979,17 @@ impl DatastoreInstance {
                 )))
             }
         };
-        let team = match stmt.query_row([team.name, team.description, ownerId.to_string()], |row| {
-            Ok(Team {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                description: row.get(2)?,
-                ownerId: row.get(3)?,
-            })
-        }) {
-            Ok(rows) => rows,
-            Err(err) => return Err(DatastoreError::NoUser()),
-        };
-        Ok(team)
+        if let Err(err) = stmt.execute(params![team.name, team.description, ownerId]) {
+            return Err(DatastoreError::InternalError(format!(
+                "Failed to insert team: {err}"
+            )));
+        }
+        Ok(Team {
+            id: conn.last_insert_rowid() as i32,
+            name: team.name,
+            description: team.description,
+            ownerId,
+        })
     }
 
     pub fn get_team_members_count(
@@ -1361,7 +1997,9 @@ impl DatastoreInstance {
         conn: &Connection,
         team_id: i32,
     ) -> Result<i64, DatastoreError> {
-        let mut stmt = match conn.prepare("SELECT COUNT(*) FROM TeamsUsers WHERE teamId = ?1") {
+        let mut stmt = match conn.prepare(&format!(
+            "SELECT COUNT(*) FROM TeamsUsers WHERE teamId = ?1 AND userId IN {VERIFIED_USERS}"
+        )) {
             Ok(stmt) => stmt,
             Err(err) => {
                 return Err(DatastoreError::InternalError(format!(
@@ -1401,9 +2039,11 @@ impl DatastoreInstance {
         Ok(team)
     }
     pub fn get_all_users(&self, conn: &Connection) -> Result<Vec<PublicUser>, DatastoreError> {
-        let mut stmt = match conn
-            .prepare("SELECT id, name, lastname, email, role FROM Users WHERE role = 2")
-        {
+        let mut stmt = match conn.prepare(&format!(
+            "SELECT id, name, lastname, email, role, must_change_password, {TWO_FACTOR_ENABLED},
+                username, active, email_verified
+                FROM Users WHERE role != ?1 AND email_verified = 1"
+        )) {
             Ok(stmt) => stmt,
             Err(err) => {
                 return Err(DatastoreError::InternalError(format!(
@@ -1411,13 +2051,18 @@ impl DatastoreInstance {
                 )))
             }
         };
-        let rows = match stmt.query_map(params![], |row| {
+        let rows = match stmt.query_map(params![Role::Admin.id()], |row| {
             Ok(PublicUser {
                 id: row.get(0)?,
                 name: row.get(1)?,
                 lastname: row.get(2)?,
                 email: row.get(3)?,
-                role: row.get(4)?,
+                role: _role_from_id(row.get(4)?),
+                must_change_password: row.get(5)?,
+                two_factor_enabled: row.get(6)?,
+                username: row.get(7)?,
+                active: row.get(8)?,
+                email_verified: row.get(9)?,
             })
         }) {
             Ok(users) => users,
@@ -1443,9 +2088,9 @@ impl DatastoreInstance {
         team_id: i32,
     ) -> Result<Vec<Member>, DatastoreError> {
         let mut stmt = match conn.prepare(
-            "SELECT tu.id as id, u.id as userId, u.name, u.lastname, u.email FROM TeamsUsers tu
+            "SELECT tu.id as id, u.id as userId, u.name, u.lastname, u.email, tu.role, tu.schedule FROM TeamsUsers tu
         INNER Join Users u on tu.userId = u.id
-        where tu.teamId=?1
+        where tu.teamId=?1 AND u.email_verified = 1
         ",
         ) {
             Ok(stmt) => stmt,
@@ -1462,6 +2107,11 @@ impl DatastoreInstance {
                 name: row.get(2)?,
                 lastname: row.get(3)?,
                 email: row.get(4)?,
+                role: _role_from_id(row.get(5)?),
+                schedule: match row.get::<_, Option<String>>(6)? {
+                    Some(schedule) => serde_json::from_str(&schedule).ok(),
+                    None => None,
+                },
             })
         }) {
             Ok(members) => members,
@@ -1481,32 +2131,56 @@ impl DatastoreInstance {
         Ok(members)
     }
 
+    /// Returns the user a membership of the team belongs to, if the membership exists
+    pub fn get_member_user(
+        &self,
+        conn: &Connection,
+        team_id: i32,
+        member_id: i32,
+    ) -> Result<Option<i32>, DatastoreError> {
+        let mut stmt =
+            match conn.prepare("SELECT userId FROM TeamsUsers WHERE id = ?1 AND teamId = ?2") {
+                Ok(stmt) => stmt,
+                Err(err) => {
+                    return Err(DatastoreError::InternalError(format!(
+                        "Failed to prepare get_member_user SQL statement: {err}"
+                    )))
+                }
+            };
+        match stmt.query_row(params![member_id, team_id], |row| row.get::<usize, i32>(0)) {
+            Ok(user_id) => Ok(Some(user_id)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to query get_member_user SQL statement: {err}"
+            ))),
+        }
+    }
+
     pub fn add_members(
         &self,
         conn: &Connection,
         team_id: i32,
         members: Vec<i32>,
     ) -> Result<bool, DatastoreError> {
-        let mut stmt = match conn.prepare("INSERT INTO TeamsUsers (teamId, userId) VALUES (?1, ?2)")
-        {
+        // Users who already are members keep their membership and role
+        let mut stmt = match conn.prepare(
+            "INSERT INTO TeamsUsers (teamId, userId) VALUES (?1, ?2)
+                ON CONFLICT (teamId, userId) DO NOTHING",
+        ) {
             Ok(stmt) => stmt,
             Err(err) => {
                 return Err(DatastoreError::InternalError(format!(
-                    "Failed to prepare get_value SQL statement: {err}"
+                    "Failed to prepare add_members SQL statement: {err}"
                 )))
             }
         };
-        // let transaction = conn.transaction().unwrap();
-
         for user_id in members {
-            let _ = match stmt.execute(params![team_id, user_id]) {
-                Ok(r) => Ok(true),
-                Err(err) => Err(DatastoreError::InternalError(
-                    ("Faild to insert data").to_string(),
-                )),
-            };
+            if let Err(err) = stmt.execute(params![team_id, user_id]) {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to add team member: {err}"
+                )));
+            }
         }
-        // transaction.commit().unwrap();
         Ok(true)
     }
 
@@ -1516,16 +2190,20 @@ impl DatastoreInstance {
         team_id: i32,
         member_id: i32,
     ) -> Result<bool, DatastoreError> {
-        let mut stmt = match conn.prepare("DELETE FROM TeamsUsers WHERE id = ?1") {
+        let mut stmt = match conn.prepare("DELETE FROM TeamsUsers WHERE id = ?1 AND teamId = ?2") {
             Ok(stmt) => stmt,
             Err(err) => {
                 return Err(DatastoreError::InternalError(format!(
-                    "Failed to prepare get_value SQL statement: {err}"
+                    "Failed to prepare remove_member SQL statement: {err}"
                 )))
             }
         };
-        stmt.execute(params![member_id]).unwrap();
-        Ok(true)
+        match stmt.execute(params![member_id, team_id]) {
+            Ok(_) => Ok(true),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to remove team member: {err}"
+            ))),
+        }
     }
 
     pub fn get_user_teams(
@@ -1534,10 +2212,12 @@ impl DatastoreInstance {
         user_id: i32,
     ) -> Result<Vec<TeamUserModel>, DatastoreError> {
         let mut stmt = match conn.prepare(
-            "SELECT t.id, t.name, t.description FROM TeamsUsers tu
+            &format!(
+                "SELECT t.id, t.name, t.description FROM TeamsUsers tu
                     INNER Join Teams t on tu.teamId = t.id
-                    where tu.userId=?1
-        ",
+                    where tu.userId=?1 AND tu.userId IN {VERIFIED_USERS}
+        "
+            ),
         ) {
             Ok(stmt) => stmt,
             Err(err) => {
@@ -1570,92 +2250,1597 @@ impl DatastoreInstance {
         Ok(teams)
     }
 
-    pub fn update_configuration(
+    /// The role of the user in the team, `None` if they are not a member
+    pub fn get_team_role(
         &self,
         conn: &Connection,
         team_id: i32,
-        apps: String,
-    ) -> Result<bool, DatastoreError> {
-        let mut stmt =
-            match conn.prepare("UPDATE TeamConfiguration set apps = ?1 where teamId = ?2") {
-                Ok(stmt) => stmt,
-                Err(err) => {
-                    return Err(DatastoreError::InternalError(format!(
-                        "Failed to prepare get_value SQL statement: {err}"
-                    )))
-                }
-            };
-        match stmt.execute(params![apps, team_id]) {
-            Ok(r) => Ok(true),
-            Err(err) => Err(DatastoreError::InternalError(
-                ("Faild to insert data").to_string(),
-            )),
+        user_id: i32,
+    ) -> Result<Option<Role>, DatastoreError> {
+        let mut stmt = match conn.prepare(
+            &format!(
+                "SELECT CASE WHEN t.ownerId = ?2 THEN ?3 ELSE tu.role END FROM Teams t
+                    LEFT JOIN TeamsUsers tu ON tu.teamId = t.id AND tu.userId = ?2
+                        AND tu.userId IN {VERIFIED_USERS}
+                    WHERE t.id = ?1 AND (t.ownerId = ?2 OR tu.userId IS NOT NULL)"
+            ),
+        ) {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to prepare get_team_role SQL statement: {err}"
+                )))
+            }
+        };
+        match stmt.query_row(params![team_id, user_id, Role::TeamOwner.id()], |row| {
+            row.get::<usize, i8>(0)
+        }) {
+            Ok(role) => Ok(Some(_role_from_id(role))),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to query get_team_role SQL statement: {err}"
+            ))),
+        }
+    }
+
+    /// The roles of the user in all the teams `member_id` is a member of
+    pub fn get_shared_team_roles(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+        member_id: i32,
+    ) -> Result<Vec<Role>, DatastoreError> {
+        let mut stmt = match conn.prepare(
+            &format!(
+                "SELECT CASE WHEN t.ownerId = ?1 THEN ?3 ELSE tu.role END FROM Teams t
+                    INNER JOIN TeamsUsers m ON m.teamId = t.id AND m.userId = ?2
+                        AND m.userId IN {VERIFIED_USERS}
+                    LEFT JOIN TeamsUsers tu ON tu.teamId = t.id AND tu.userId = ?1
+                        AND tu.userId IN {VERIFIED_USERS}
+                    WHERE t.ownerId = ?1 OR tu.userId IS NOT NULL"
+            ),
+        ) {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to prepare get_shared_team_roles SQL statement: {err}"
+                )))
+            }
+        };
+        let rows = match stmt.query_map(params![user_id, member_id, Role::TeamOwner.id()], |row| {
+            row.get::<usize, i8>(0)
+        }) {
+            Ok(rows) => rows,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to query get_shared_team_roles SQL statement: {err}"
+                )))
+            }
+        };
+        let mut roles = Vec::new();
+        for role in rows {
+            match role {
+                Ok(role) => roles.push(_role_from_id(role)),
+                Err(err) => warn!("Bad data: {}", err),
+            }
+        }
+        Ok(roles)
+    }
+
+    pub fn set_user_role(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+        role: Role,
+    ) -> Result<(), DatastoreError> {
+        match conn.execute(
+            "UPDATE Users SET role = ?1 WHERE id = ?2",
+            params![role.id(), user_id],
+        ) {
+            Ok(0) => Err(DatastoreError::NoUser()),
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to set user role: {err}"
+            ))),
         }
     }
 
-    pub fn add_configuration(
+    pub fn set_member_role(
         &self,
         conn: &Connection,
         team_id: i32,
-        apps: String,
-    ) -> Result<bool, DatastoreError> {
-        let mut stmt =
-            match conn.prepare("INSERT INTO TeamConfiguration (teamId, apps) VALUES (?1, ?2)") {
-                Ok(stmt) => stmt,
-                Err(err) => {
-                    return Err(DatastoreError::InternalError(format!(
-                        "Failed to prepare get_value SQL statement: {err}"
-                    )))
-                }
-            };
-        match stmt.execute(params![team_id, apps]) {
-            Ok(r) => Ok(true),
-            Err(err) => Err(DatastoreError::InternalError(
-                ("Faild to insert data").to_string(),
-            )),
+        member_id: i32,
+        role: Role,
+    ) -> Result<(), DatastoreError> {
+        match conn.execute(
+            "UPDATE TeamsUsers SET role = ?1 WHERE id = ?2 AND teamId = ?3",
+            params![role.id(), member_id, team_id],
+        ) {
+            Ok(0) => Err(DatastoreError::NoUser()),
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to set member role: {err}"
+            ))),
+        }
+    }
+
+    /// Sets the working hours of a member which differ from those of the team, `None` resets
+    /// them to those of the team
+    pub fn set_member_schedule(
+        &self,
+        conn: &Connection,
+        team_id: i32,
+        member_id: i32,
+        schedule: Option<&MemberSchedule>,
+    ) -> Result<(), DatastoreError> {
+        let data = schedule.map(|schedule| serde_json::to_string(schedule).unwrap());
+        match conn.execute(
+            "UPDATE TeamsUsers SET schedule = ?1 WHERE id = ?2 AND teamId = ?3",
+            params![data, member_id, team_id],
+        ) {
+            Ok(0) => Err(DatastoreError::NoUser()),
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to set member schedule: {err}"
+            ))),
         }
     }
 
+    /// Stores the configuration as the newest revision of the configuration of the team
+    pub fn set_configuration(
+        &self,
+        conn: &Connection,
+        team_id: i32,
+        config: &TeamConfiguration,
+        author: Option<i32>,
+        restored_from: Option<i32>,
+    ) -> Result<TeamConfigurationRevision, DatastoreError> {
+        let revision = TeamConfigurationRevision {
+            revision: conn
+                .query_row(
+                    "SELECT COALESCE(MAX(revision), 0) + 1 FROM TeamConfigurationRevisions
+                        WHERE teamId = ?1",
+                    params![team_id],
+                    |row| row.get(0),
+                )
+                .map_err(|err| {
+                    DatastoreError::InternalError(format!("Failed to get revision: {err}"))
+                })?,
+            author,
+            created: DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap(),
+            restored_from,
+            configuration: config.clone(),
+        };
+        conn.execute(
+            "INSERT INTO TeamConfigurationRevisions
+                (teamId, revision, data, author, created, restoredFrom)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                team_id,
+                revision.revision,
+                serde_json::to_string(config).unwrap(),
+                author,
+                revision.created.timestamp(),
+                restored_from
+            ],
+        )
+        .map_err(|err| {
+            DatastoreError::InternalError(format!("Failed to insert configuration: {err}"))
+        })?;
+        Ok(revision)
+    }
+
+    /// The newest configuration of a team, the default one if it has never been set
     pub fn get_configuration(
         &self,
         conn: &Connection,
         team_id: i32,
     ) -> Result<TeamConfiguration, DatastoreError> {
-        let mut stmt = match conn.prepare(
-            "SELECT tc.id, tc.teamId, tc.apps FROM TeamConfiguration tc
-                    where tc.teamId=?1
-        ",
+        let data = match conn.query_row(
+            "SELECT data FROM TeamConfigurationRevisions WHERE teamId = ?1
+                ORDER BY revision DESC LIMIT 1",
+            params![team_id],
+            |row| row.get::<_, String>(0),
+        ) {
+            Ok(data) => data,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(TeamConfiguration::default()),
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to get configuration: {err}"
+                )))
+            }
+        };
+        serde_json::from_str(&data).map_err(|err| {
+            DatastoreError::InternalError(format!("Failed to parse configuration: {err}"))
+        })
+    }
+
+    /// All revisions of the configuration of a team, newest first
+    pub fn get_configuration_revisions(
+        &self,
+        conn: &Connection,
+        team_id: i32,
+    ) -> Result<Vec<TeamConfigurationRevision>, DatastoreError> {
+        let mut stmt = conn
+            .prepare(
+                "SELECT revision, data, author, created, restoredFrom
+                    FROM TeamConfigurationRevisions WHERE teamId = ?1 ORDER BY revision DESC",
+            )
+            .map_err(|err| {
+                DatastoreError::InternalError(format!(
+                    "Failed to prepare get_configuration_revisions SQL statement: {err}"
+                ))
+            })?;
+        let rows = stmt
+            .query_map(params![team_id], |row| {
+                let data: String = row.get(1)?;
+                let created: i64 = row.get(3)?;
+                Ok((
+                    TeamConfigurationRevision {
+                        revision: row.get(0)?,
+                        author: row.get(2)?,
+                        created: DateTime::from_timestamp(created, 0).unwrap(),
+                        restored_from: row.get(4)?,
+                        configuration: TeamConfiguration::default(),
+                    },
+                    data,
+                ))
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|err| {
+                DatastoreError::InternalError(format!(
+                    "Failed to get configuration revisions: {err}"
+                ))
+            })?;
+        let mut revisions = Vec::new();
+        for (mut revision, data) in rows {
+            revision.configuration = serde_json::from_str(&data).map_err(|err| {
+                DatastoreError::InternalError(format!("Failed to parse configuration: {err}"))
+            })?;
+            revisions.push(revision);
+        }
+        Ok(revisions)
+    }
+
+    pub fn add_refresh_token(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+        token: &str,
+        expires: DateTime<Utc>,
+    ) -> Result<(), DatastoreError> {
+        match conn.execute(
+            "INSERT INTO RefreshTokens (userId, token, expires) VALUES (?1, ?2, ?3)",
+            params![user_id, token, expires.timestamp()],
         ) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to insert refresh token: {err}"
+            ))),
+        }
+    }
+
+    pub fn get_refresh_token(
+        &self,
+        conn: &Connection,
+        token: &str,
+    ) -> Result<RefreshToken, DatastoreError> {
+        let mut stmt = match conn
+            .prepare("SELECT id, userId, expires FROM RefreshTokens WHERE token = ?1 LIMIT 1")
+        {
             Ok(stmt) => stmt,
             Err(err) => {
                 return Err(DatastoreError::InternalError(format!(
-                    "Failed to prepare get_value SQL statement: {err}"
+                    "Failed to prepare get_refresh_token SQL statement: {err}"
                 )))
             }
         };
-        let config = match stmt.query_row(params![team_id], |row| {
-            let id = row.get(0)?;
-            let team_id = row.get(1)?;
-            let apps_string = row.get::<usize, String>(2)?;
-            let apps: Vec<String>;
-            if apps_string.len() > 0 {
-                apps = apps_string.split(',').map(|s| s.to_string()).collect();
-            } else {
-                apps = Vec::new();
-            }
-            return Ok(TeamConfiguration {
-                id: id,
-                team_id: team_id,
-                apps: apps,
-            });
+        match stmt.query_row([token], |row| {
+            let expires: i64 = row.get(2)?;
+            Ok(RefreshToken {
+                id: row.get(0)?,
+                user_id: row.get(1)?,
+                expires: DateTime::from_timestamp(expires, 0).unwrap(),
+            })
         }) {
-            Ok(config) => config,
+            Ok(refresh_token) => Ok(refresh_token),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(DatastoreError::NoSuchKey("refresh token".to_string()))
+            }
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to query get_refresh_token SQL statement: {err}"
+            ))),
+        }
+    }
+
+    pub fn delete_refresh_token(&self, conn: &Connection, token: &str) -> Result<(), DatastoreError> {
+        match conn.execute("DELETE FROM RefreshTokens WHERE token = ?1", [token]) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(err.to_string())),
+        }
+    }
+
+    /// Ends all sessions of a user, deleting their refresh tokens and revoking the access tokens
+    /// issued so far
+    pub fn revoke_user_tokens(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+    ) -> Result<(), DatastoreError> {
+        if let Err(err) = conn.execute("DELETE FROM RefreshTokens WHERE userId = ?1", [user_id]) {
+            return Err(DatastoreError::InternalError(err.to_string()));
+        }
+        match conn.execute(
+            "UPDATE Users SET tokenGeneration = tokenGeneration + 1 WHERE id = ?1",
+            [user_id],
+        ) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(err.to_string())),
+        }
+    }
+
+    /// The generation of the access tokens of a user, tokens of older generations are revoked
+    pub fn get_token_generation(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+    ) -> Result<i64, DatastoreError> {
+        match conn.query_row(
+            "SELECT tokenGeneration FROM Users WHERE id = ?1",
+            [user_id],
+            |row| row.get(0),
+        ) {
+            Ok(generation) => Ok(generation),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Err(DatastoreError::NoUser()),
+            Err(err) => Err(DatastoreError::InternalError(err.to_string())),
+        }
+    }
+
+    pub fn revoke_token(
+        &self,
+        conn: &Connection,
+        jti: &str,
+        expires: DateTime<Utc>,
+    ) -> Result<(), DatastoreError> {
+        // Revocations are only needed until the token would have expired anyway, so this is a
+        // good time to clean out the old ones
+        let now = Utc::now().timestamp();
+        if let Err(err) = conn.execute("DELETE FROM RevokedTokens WHERE expires < ?1", [now]) {
+            return Err(DatastoreError::InternalError(err.to_string()));
+        }
+        if let Err(err) = conn.execute("DELETE FROM RefreshTokens WHERE expires < ?1", [now]) {
+            return Err(DatastoreError::InternalError(err.to_string()));
+        }
+        match conn.execute(
+            "INSERT OR REPLACE INTO RevokedTokens (jti, expires) VALUES (?1, ?2)",
+            params![jti, expires.timestamp()],
+        ) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to revoke token: {err}"
+            ))),
+        }
+    }
+
+    pub fn is_token_revoked(&self, conn: &Connection, jti: &str) -> Result<bool, DatastoreError> {
+        let mut stmt = match conn.prepare("SELECT COUNT(*) FROM RevokedTokens WHERE jti = ?1") {
+            Ok(stmt) => stmt,
             Err(err) => {
                 return Err(DatastoreError::InternalError(format!(
-                    "Failed to prepare get_value SQL statement: {err}"
+                    "Failed to prepare is_token_revoked SQL statement: {err}"
+                )))
+            }
+        };
+        match stmt.query_row([jti], |row| row.get::<usize, i64>(0)) {
+            Ok(count) => Ok(count > 0),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to query is_token_revoked SQL statement: {err}"
+            ))),
+        }
+    }
+
+    /// Stores a one-time token, replacing any earlier token of the user for the same purpose
+    pub fn add_one_time_token(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+        purpose: TokenPurpose,
+        token: &str,
+        expires: DateTime<Utc>,
+    ) -> Result<(), DatastoreError> {
+        let now = Utc::now().timestamp();
+        if let Err(err) = conn.execute(
+            "DELETE FROM OneTimeTokens WHERE expires < ?1 OR (userId = ?2 AND purpose = ?3)",
+            params![now, user_id, purpose.as_str()],
+        ) {
+            return Err(DatastoreError::InternalError(err.to_string()));
+        }
+        match conn.execute(
+            "INSERT INTO OneTimeTokens (userId, purpose, token, expires) VALUES (?1, ?2, ?3, ?4)",
+            params![user_id, purpose.as_str(), token, expires.timestamp()],
+        ) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to insert one-time token: {err}"
+            ))),
+        }
+    }
+
+    /// Looks up a one-time token and deletes it so it can not be used again
+    ///
+    /// Expired tokens are returned as well, checking the expiry is up to the caller.
+    pub fn take_one_time_token(
+        &self,
+        conn: &Connection,
+        purpose: TokenPurpose,
+        token: &str,
+    ) -> Result<OneTimeToken, DatastoreError> {
+        let one_time_token = match conn.query_row(
+            "SELECT id, userId, expires FROM OneTimeTokens WHERE purpose = ?1 AND token = ?2",
+            params![purpose.as_str(), token],
+            |row| {
+                let expires: i64 = row.get(2)?;
+                Ok(OneTimeToken {
+                    id: row.get(0)?,
+                    user_id: row.get(1)?,
+                    purpose,
+                    expires: DateTime::from_timestamp(expires, 0).unwrap(),
+                })
+            },
+        ) {
+            Ok(one_time_token) => one_time_token,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                return Err(DatastoreError::NoSuchKey("one-time token".to_string()))
+            }
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to query take_one_time_token SQL statement: {err}"
                 )))
             }
         };
-        Ok(config)
+        match conn.execute(
+            "DELETE FROM OneTimeTokens WHERE id = ?1",
+            [one_time_token.id],
+        ) {
+            Ok(_) => Ok(one_time_token),
+            Err(err) => Err(DatastoreError::InternalError(err.to_string())),
+        }
+    }
+
+    pub fn add_outbox_message(
+        &self,
+        conn: &Connection,
+        recipient: &str,
+        subject: &str,
+        body: &str,
+    ) -> Result<(), DatastoreError> {
+        match conn.execute(
+            "INSERT INTO Outbox (recipient, subject, body, created) VALUES (?1, ?2, ?3, ?4)",
+            params![recipient, subject, body, Utc::now().timestamp()],
+        ) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to insert outbox message: {err}"
+            ))),
+        }
+    }
+
+    pub fn get_outbox_messages(
+        &self,
+        conn: &Connection,
+    ) -> Result<Vec<OutboxMessage>, DatastoreError> {
+        let mut stmt = match conn
+            .prepare("SELECT id, recipient, subject, body, created FROM Outbox ORDER BY id DESC")
+        {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to prepare get_outbox_messages SQL statement: {err}"
+                )))
+            }
+        };
+        let rows = match stmt.query_map([], |row| {
+            let created: i64 = row.get(4)?;
+            Ok(OutboxMessage {
+                id: row.get(0)?,
+                recipient: row.get(1)?,
+                subject: row.get(2)?,
+                body: row.get(3)?,
+                created: DateTime::from_timestamp(created, 0).unwrap(),
+            })
+        }) {
+            Ok(rows) => rows,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to query get_outbox_messages SQL statement: {err}"
+                )))
+            }
+        };
+        let mut messages = Vec::new();
+        for row in rows {
+            match row {
+                Ok(message) => messages.push(message),
+                Err(err) => return Err(DatastoreError::InternalError(err.to_string())),
+            }
+        }
+        Ok(messages)
+    }
+
+    pub fn add_api_key(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+        name: &str,
+        token: &str,
+    ) -> Result<ApiKey, DatastoreError> {
+        let created = Utc::now();
+        match conn.execute(
+            "INSERT INTO ApiKeys (userId, name, token, created) VALUES (?1, ?2, ?3, ?4)",
+            params![user_id, name, token, created.timestamp()],
+        ) {
+            Ok(_) => Ok(ApiKey {
+                id: conn.last_insert_rowid(),
+                user_id,
+                name: name.to_string(),
+                created: DateTime::from_timestamp(created.timestamp(), 0).unwrap(),
+                last_used: None,
+            }),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to insert API key: {err}"
+            ))),
+        }
+    }
+
+    fn _api_key_from_row(row: &rusqlite::Row) -> Result<ApiKey, rusqlite::Error> {
+        let created: i64 = row.get(3)?;
+        let last_used: Option<i64> = row.get(4)?;
+        Ok(ApiKey {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            name: row.get(2)?,
+            created: DateTime::from_timestamp(created, 0).unwrap(),
+            last_used: last_used.map(|last_used| DateTime::from_timestamp(last_used, 0).unwrap()),
+        })
+    }
+
+    pub fn get_api_keys(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+    ) -> Result<Vec<ApiKey>, DatastoreError> {
+        let mut stmt = match conn.prepare(
+            "SELECT id, userId, name, created, lastUsed FROM ApiKeys WHERE userId = ?1 ORDER BY id",
+        ) {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to prepare get_api_keys SQL statement: {err}"
+                )))
+            }
+        };
+        let rows = match stmt.query_map([user_id], Self::_api_key_from_row) {
+            Ok(rows) => rows,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to query get_api_keys SQL statement: {err}"
+                )))
+            }
+        };
+        let mut api_keys = Vec::new();
+        for row in rows {
+            match row {
+                Ok(api_key) => api_keys.push(api_key),
+                Err(err) => return Err(DatastoreError::InternalError(err.to_string())),
+            }
+        }
+        Ok(api_keys)
+    }
+
+    pub fn delete_api_key(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+        key_id: i64,
+    ) -> Result<(), DatastoreError> {
+        match conn.execute(
+            "DELETE FROM ApiKeys WHERE id = ?1 AND userId = ?2",
+            params![key_id, user_id],
+        ) {
+            Ok(0) => Err(DatastoreError::NoSuchKey(format!("API key {key_id}"))),
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(err.to_string())),
+        }
+    }
+
+    /// Looks up an API key by its hash and records that it was used
+    pub fn use_api_key(&self, conn: &Connection, token: &str) -> Result<ApiKey, DatastoreError> {
+        let mut api_key = match conn.query_row(
+            "SELECT id, userId, name, created, lastUsed FROM ApiKeys WHERE token = ?1",
+            [token],
+            Self::_api_key_from_row,
+        ) {
+            Ok(api_key) => api_key,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                return Err(DatastoreError::NoSuchKey("API key".to_string()))
+            }
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to query use_api_key SQL statement: {err}"
+                )))
+            }
+        };
+        let now = Utc::now().timestamp();
+        match conn.execute(
+            "UPDATE ApiKeys SET lastUsed = ?1 WHERE id = ?2",
+            params![now, api_key.id],
+        ) {
+            Ok(_) => {
+                api_key.last_used = DateTime::from_timestamp(now, 0);
+                Ok(api_key)
+            }
+            Err(err) => Err(DatastoreError::InternalError(err.to_string())),
+        }
+    }
+
+    fn _login_attempts_from_row(row: &rusqlite::Row) -> Result<LoginAttempts, rusqlite::Error> {
+        let last_failure: i64 = row.get(2)?;
+        let locked_until: i64 = row.get(3)?;
+        Ok(LoginAttempts {
+            subject: row.get(0)?,
+            failures: row.get(1)?,
+            last_failure: DateTime::from_timestamp(last_failure, 0).unwrap(),
+            locked_until: DateTime::from_timestamp(locked_until, 0).unwrap(),
+        })
+    }
+
+    pub fn get_login_attempts(
+        &self,
+        conn: &Connection,
+        subject: &str,
+    ) -> Result<Option<LoginAttempts>, DatastoreError> {
+        match conn.query_row(
+            "SELECT subject, failures, lastFailure, lockedUntil FROM LoginAttempts
+                WHERE subject = ?1",
+            [subject],
+            Self::_login_attempts_from_row,
+        ) {
+            Ok(attempts) => Ok(Some(attempts)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to query get_login_attempts SQL statement: {err}"
+            ))),
+        }
+    }
+
+    /// Lists all subjects with failed login attempts, most recent failures first
+    pub fn get_all_login_attempts(
+        &self,
+        conn: &Connection,
+    ) -> Result<Vec<LoginAttempts>, DatastoreError> {
+        let mut stmt = match conn.prepare(
+            "SELECT subject, failures, lastFailure, lockedUntil FROM LoginAttempts
+                ORDER BY lastFailure DESC",
+        ) {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to prepare get_all_login_attempts SQL statement: {err}"
+                )))
+            }
+        };
+        let rows = match stmt.query_map([], Self::_login_attempts_from_row) {
+            Ok(rows) => rows,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to query get_all_login_attempts SQL statement: {err}"
+                )))
+            }
+        };
+        let mut all_attempts = Vec::new();
+        for row in rows {
+            match row {
+                Ok(attempts) => all_attempts.push(attempts),
+                Err(err) => return Err(DatastoreError::InternalError(err.to_string())),
+            }
+        }
+        Ok(all_attempts)
+    }
+
+    pub fn set_login_attempts(
+        &self,
+        conn: &Connection,
+        attempts: &LoginAttempts,
+    ) -> Result<(), DatastoreError> {
+        match conn.execute(
+            "INSERT OR REPLACE INTO LoginAttempts (subject, failures, lastFailure, lockedUntil)
+                VALUES (?1, ?2, ?3, ?4)",
+            params![
+                attempts.subject,
+                attempts.failures,
+                attempts.last_failure.timestamp(),
+                attempts.locked_until.timestamp()
+            ],
+        ) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to store login attempts: {err}"
+            ))),
+        }
+    }
+
+    pub fn clear_login_attempts(
+        &self,
+        conn: &Connection,
+        subject: &str,
+    ) -> Result<(), DatastoreError> {
+        match conn.execute("DELETE FROM LoginAttempts WHERE subject = ?1", [subject]) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(err.to_string())),
+        }
+    }
+
+    pub fn get_two_factor(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+    ) -> Result<Option<TwoFactor>, DatastoreError> {
+        match conn.query_row(
+            "SELECT userId, secret, enabled, lastStep FROM TwoFactor WHERE userId = ?1",
+            [user_id],
+            |row| {
+                Ok(TwoFactor {
+                    user_id: row.get(0)?,
+                    secret: row.get(1)?,
+                    enabled: row.get(2)?,
+                    last_step: row.get(3)?,
+                })
+            },
+        ) {
+            Ok(two_factor) => Ok(Some(two_factor)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to query get_two_factor SQL statement: {err}"
+            ))),
+        }
+    }
+
+    /// Stores a new, not yet enabled, secret for the user
+    pub fn set_two_factor_secret(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+        secret: &str,
+    ) -> Result<(), DatastoreError> {
+        match conn.execute(
+            "INSERT OR REPLACE INTO TwoFactor (userId, secret, enabled, lastStep)
+                VALUES (?1, ?2, 0, 0)",
+            params![user_id, secret],
+        ) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to store two-factor secret: {err}"
+            ))),
+        }
+    }
+
+    /// Enables the second factor and replaces the recovery codes of the user
+    pub fn enable_two_factor(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+        last_step: i64,
+        recovery_codes: &[String],
+    ) -> Result<(), DatastoreError> {
+        match conn.execute(
+            "UPDATE TwoFactor SET enabled = 1, lastStep = ?1 WHERE userId = ?2",
+            params![last_step, user_id],
+        ) {
+            Ok(0) => return Err(DatastoreError::NoSuchKey("two-factor secret".to_string())),
+            Ok(_) => (),
+            Err(err) => return Err(DatastoreError::InternalError(err.to_string())),
+        };
+        if let Err(err) = conn.execute("DELETE FROM RecoveryCodes WHERE userId = ?1", [user_id]) {
+            return Err(DatastoreError::InternalError(err.to_string()));
+        }
+        for code in recovery_codes {
+            if let Err(err) = conn.execute(
+                "INSERT INTO RecoveryCodes (userId, code) VALUES (?1, ?2)",
+                params![user_id, code],
+            ) {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to insert recovery code: {err}"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_two_factor_step(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+        last_step: i64,
+    ) -> Result<(), DatastoreError> {
+        match conn.execute(
+            "UPDATE TwoFactor SET lastStep = ?1 WHERE userId = ?2",
+            params![last_step, user_id],
+        ) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(err.to_string())),
+        }
+    }
+
+    /// Deletes a recovery code of the user, returns whether it existed
+    pub fn use_recovery_code(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+        code: &str,
+    ) -> Result<bool, DatastoreError> {
+        match conn.execute(
+            "DELETE FROM RecoveryCodes WHERE userId = ?1 AND code = ?2",
+            params![user_id, code],
+        ) {
+            Ok(deleted) => Ok(deleted > 0),
+            Err(err) => Err(DatastoreError::InternalError(err.to_string())),
+        }
+    }
+
+    pub fn delete_two_factor(&self, conn: &Connection, user_id: i32) -> Result<(), DatastoreError> {
+        if let Err(err) = conn.execute("DELETE FROM RecoveryCodes WHERE userId = ?1", [user_id]) {
+            return Err(DatastoreError::InternalError(err.to_string()));
+        }
+        match conn.execute("DELETE FROM TwoFactor WHERE userId = ?1", [user_id]) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(err.to_string())),
+        }
+    }
+
+    /// Changes the name, username and email of a user
+    ///
+    /// Returns false without changing anything when the email or username is used by another
+    /// account. A changed email address has to be verified again.
+    pub fn update_profile(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+        profile: &UserProfile,
+    ) -> Result<bool, DatastoreError> {
+        match conn.execute(
+            "UPDATE Users SET name = ?1, lastname = ?2, username = ?3,
+                email_verified = CASE WHEN email = ?4 THEN email_verified ELSE 0 END, email = ?4
+                WHERE id = ?5",
+            params![
+                profile.name,
+                profile.lastname,
+                profile.username,
+                profile.email,
+                user_id
+            ],
+        ) {
+            Ok(0) => Err(DatastoreError::NoUser()),
+            Ok(_) => Ok(true),
+            Err(rusqlite::Error::SqliteFailure(err, _))
+                if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                Ok(false)
+            }
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to update profile: {err}"
+            ))),
+        }
+    }
+
+    pub fn set_email_verified(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+    ) -> Result<(), DatastoreError> {
+        match conn.execute("UPDATE Users SET email_verified = 1 WHERE id = ?1", [user_id]) {
+            Ok(0) => Err(DatastoreError::NoUser()),
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(err.to_string())),
+        }
+    }
+
+    pub fn set_user_active(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+        active: bool,
+    ) -> Result<(), DatastoreError> {
+        match conn.execute(
+            "UPDATE Users SET active = ?1 WHERE id = ?2",
+            params![active, user_id],
+        ) {
+            Ok(0) => Err(DatastoreError::NoUser()),
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(err.to_string())),
+        }
+    }
+
+    /// Deletes a user together with all data stored about them
+    ///
+    /// Removes the buckets and events of the user, their team memberships, their sessions, tokens
+    /// and API keys, their two-factor authentication and the notifications sent to them. Teams
+    /// are shared with their members, so users who own teams have to hand them over first.
+    pub fn delete_user(&mut self, conn: &Connection, user_id: i32) -> Result<(), DatastoreError> {
+        let user = self.get_user(conn, user_id)?;
+        if !self.get_teams(conn, user_id)?.is_empty() {
+            return Err(DatastoreError::UserOwnsTeams(user_id));
+        }
+        // Erases all data of the user or, on an error, none of it
+        let result = _in_savepoint(conn, "delete_user", || {
+            for bucket_id in self.get_user_bucket_ids(conn, user_id)? {
+                self.delete_bucket(conn, bucket_id)?;
+            }
+            let statements = [
+                "DELETE FROM Invitations WHERE createdBy = ?1",
+                "DELETE FROM TeamsUsers WHERE userId = ?1",
+                "DELETE FROM RefreshTokens WHERE userId = ?1",
+                "DELETE FROM OneTimeTokens WHERE userId = ?1",
+                "DELETE FROM ApiKeys WHERE userId = ?1",
+                "DELETE FROM RecoveryCodes WHERE userId = ?1",
+                "DELETE FROM TwoFactor WHERE userId = ?1",
+                "DELETE FROM UserIdentities WHERE userId = ?1",
+                "DELETE FROM Users WHERE id = ?1",
+            ];
+            for statement in statements {
+                if let Err(err) = conn.execute(statement, [user_id]) {
+                    return Err(DatastoreError::InternalError(format!(
+                        "Failed to delete user: {err}"
+                    )));
+                }
+            }
+            match conn.execute("DELETE FROM Outbox WHERE recipient = ?1", [&user.email]) {
+                Ok(_) => Ok(()),
+                Err(err) => Err(DatastoreError::InternalError(format!(
+                    "Failed to delete user: {err}"
+                ))),
+            }
+        });
+        if result.is_err() {
+            // The rollback restored the deleted buckets
+            self.get_stored_buckets(conn)?;
+        }
+        result
+    }
+
+    /// Returns a page of the users matching the filter together with the number of all matches
+    pub fn get_users_page(
+        &self,
+        conn: &Connection,
+        filter: &UserFilter,
+    ) -> Result<UserPage, DatastoreError> {
+        // The search is a substring match, so the wildcards of LIKE are escaped
+        let search = filter.search.as_ref().map(|search| {
+            let escaped = search
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{escaped}%")
+        });
+        let role = filter.role.map(|role| role.id());
+        let filter_params = params![search, role, filter.team_id, filter.active];
+
+        let total = match conn.query_row(
+            &format!("SELECT COUNT(*) FROM Users WHERE {USER_FILTER}"),
+            filter_params,
+            |row| row.get(0),
+        ) {
+            Ok(total) => total,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to count users: {err}"
+                )))
+            }
+        };
+
+        let sort = match filter.sort {
+            UserSort::Id => "id",
+            UserSort::Name => "name",
+            UserSort::Lastname => "lastname",
+            UserSort::Username => "username",
+            UserSort::Email => "email",
+            UserSort::Role => "role",
+        };
+        let order = if filter.descending { "DESC" } else { "ASC" };
+        let mut stmt = match conn.prepare(&format!(
+            "SELECT id, email, username, name, lastname, role, must_change_password,
+                {TWO_FACTOR_ENABLED}, active, email_verified
+                FROM Users WHERE {USER_FILTER}
+                ORDER BY {sort} {order}, id {order}
+                LIMIT ?5 OFFSET ?6"
+        )) {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to prepare get_users_page SQL statement: {err}"
+                )))
+            }
+        };
+        let rows = match stmt.query_map(
+            params![
+                search,
+                role,
+                filter.team_id,
+                filter.active,
+                filter.limit,
+                filter.offset
+            ],
+            |row| {
+                Ok(PublicUser {
+                    id: row.get(0)?,
+                    email: row.get(1)?,
+                    username: row.get(2)?,
+                    name: row.get(3)?,
+                    lastname: row.get(4)?,
+                    role: _role_from_id(row.get(5)?),
+                    must_change_password: row.get(6)?,
+                    two_factor_enabled: row.get(7)?,
+                    active: row.get(8)?,
+                    email_verified: row.get(9)?,
+                })
+            },
+        ) {
+            Ok(rows) => rows,
+            Err(err) => return Err(DatastoreError::InternalError(err.to_string())),
+        };
+        let mut users = Vec::new();
+        for user in rows {
+            match user {
+                Ok(user) => users.push(user),
+                Err(err) => return Err(DatastoreError::InternalError(err.to_string())),
+            }
+        }
+        Ok(UserPage { users, total })
+    }
+
+    fn _invitation_from_row(row: &rusqlite::Row) -> Result<Invitation, rusqlite::Error> {
+        let created: i64 = row.get(5)?;
+        let expires: i64 = row.get(6)?;
+        Ok(Invitation {
+            id: row.get(0)?,
+            team_id: row.get(1)?,
+            created_by: row.get(2)?,
+            max_uses: row.get(3)?,
+            uses: row.get(4)?,
+            created: DateTime::from_timestamp(created, 0).unwrap(),
+            expires: DateTime::from_timestamp(expires, 0).unwrap(),
+        })
+    }
+
+    pub fn add_invitation(
+        &self,
+        conn: &Connection,
+        team_id: i32,
+        created_by: i32,
+        code: &str,
+        max_uses: Option<i32>,
+        expires: DateTime<Utc>,
+    ) -> Result<Invitation, DatastoreError> {
+        let created = Utc::now();
+        match conn.execute(
+            "INSERT INTO Invitations (teamId, createdBy, code, maxUses, created, expires)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                team_id,
+                created_by,
+                code,
+                max_uses,
+                created.timestamp(),
+                expires.timestamp()
+            ],
+        ) {
+            Ok(_) => Ok(Invitation {
+                id: conn.last_insert_rowid(),
+                team_id,
+                created_by,
+                max_uses,
+                uses: 0,
+                created: DateTime::from_timestamp(created.timestamp(), 0).unwrap(),
+                expires: DateTime::from_timestamp(expires.timestamp(), 0).unwrap(),
+            }),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to insert invitation: {err}"
+            ))),
+        }
+    }
+
+    pub fn get_team_invitations(
+        &self,
+        conn: &Connection,
+        team_id: i32,
+    ) -> Result<Vec<Invitation>, DatastoreError> {
+        let mut stmt = match conn.prepare(
+            "SELECT id, teamId, createdBy, maxUses, uses, created, expires FROM Invitations
+                WHERE teamId = ?1 ORDER BY created",
+        ) {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to prepare get_team_invitations SQL statement: {err}"
+                )))
+            }
+        };
+        let rows = match stmt.query_map([team_id], Self::_invitation_from_row) {
+            Ok(rows) => rows,
+            Err(err) => return Err(DatastoreError::InternalError(err.to_string())),
+        };
+        let mut invitations = Vec::new();
+        for invitation in rows {
+            match invitation {
+                Ok(invitation) => invitations.push(invitation),
+                Err(err) => return Err(DatastoreError::InternalError(err.to_string())),
+            }
+        }
+        Ok(invitations)
+    }
+
+    /// Looks up an invitation by the hash of its code
+    ///
+    /// Returns NoSuchKey if there is no such invitation or it can not be used anymore.
+    pub fn get_invitation(
+        &self,
+        conn: &Connection,
+        code: &str,
+    ) -> Result<Invitation, DatastoreError> {
+        match conn.query_row(
+            "SELECT id, teamId, createdBy, maxUses, uses, created, expires FROM Invitations
+                WHERE code = ?1 AND expires > ?2 AND (maxUses IS NULL OR uses < maxUses)",
+            params![code, Utc::now().timestamp()],
+            Self::_invitation_from_row,
+        ) {
+            Ok(invitation) => Ok(invitation),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(DatastoreError::NoSuchKey("invitation".to_string()))
+            }
+            Err(err) => Err(DatastoreError::InternalError(err.to_string())),
+        }
+    }
+
+    /// Adds a user to the team of an invitation and counts the use
+    ///
+    /// Users who already are members of the team keep their membership and role, the invitation
+    /// is not used up by them.
+    pub fn use_invitation(
+        &self,
+        conn: &Connection,
+        code: &str,
+        user_id: i32,
+    ) -> Result<Invitation, DatastoreError> {
+        let mut invitation = self.get_invitation(conn, code)?;
+        if self.get_team_role(conn, invitation.team_id, user_id)?.is_some() {
+            return Ok(invitation);
+        }
+        if let Err(err) = conn.execute(
+            "INSERT INTO TeamsUsers (teamId, userId) VALUES (?1, ?2)",
+            params![invitation.team_id, user_id],
+        ) {
+            return Err(DatastoreError::InternalError(format!(
+                "Failed to add team member: {err}"
+            )));
+        }
+        match conn.execute(
+            "UPDATE Invitations SET uses = uses + 1 WHERE id = ?1",
+            [invitation.id],
+        ) {
+            Ok(_) => {
+                invitation.uses += 1;
+                Ok(invitation)
+            }
+            Err(err) => Err(DatastoreError::InternalError(err.to_string())),
+        }
+    }
+
+    pub fn delete_invitation(
+        &self,
+        conn: &Connection,
+        team_id: i32,
+        invitation_id: i64,
+    ) -> Result<(), DatastoreError> {
+        match conn.execute(
+            "DELETE FROM Invitations WHERE id = ?1 AND teamId = ?2",
+            params![invitation_id, team_id],
+        ) {
+            Ok(0) => Err(DatastoreError::NoSuchKey(invitation_id.to_string())),
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(err.to_string())),
+        }
+    }
+    pub fn add_audit_entry(
+        &self,
+        conn: &Connection,
+        actor: Option<i32>,
+        action: &str,
+        target: Option<&str>,
+        address: Option<&str>,
+    ) -> Result<(), DatastoreError> {
+        match conn.execute(
+            "INSERT INTO AuditLog (actor, action, target, timestamp, address)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![actor, action, target, Utc::now().timestamp(), address],
+        ) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to add audit entry: {err}"
+            ))),
+        }
+    }
+
+    pub fn get_audit_entries(
+        &self,
+        conn: &Connection,
+        filter: &AuditFilter,
+    ) -> Result<AuditPage, DatastoreError> {
+        let since = filter.since.map(|since| since.timestamp());
+        let until = filter.until.map(|until| until.timestamp());
+        let filter_params = params![filter.actor, filter.action, filter.target, since, until];
+
+        let total = match conn.query_row(
+            &format!("SELECT COUNT(*) FROM AuditLog WHERE {AUDIT_FILTER}"),
+            filter_params,
+            |row| row.get(0),
+        ) {
+            Ok(total) => total,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to count audit entries: {err}"
+                )))
+            }
+        };
+
+        // A negative limit means no limit in SQLite
+        let mut stmt = match conn.prepare(&format!(
+            "SELECT id, actor, action, target, timestamp, address FROM AuditLog
+                WHERE {AUDIT_FILTER}
+                ORDER BY id DESC
+                LIMIT ?6 OFFSET ?7"
+        )) {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to prepare get_audit_entries SQL statement: {err}"
+                )))
+            }
+        };
+        let rows = match stmt.query_map(
+            params![
+                filter.actor,
+                filter.action,
+                filter.target,
+                since,
+                until,
+                filter.limit.unwrap_or(-1),
+                filter.offset
+            ],
+            |row| {
+                let timestamp: i64 = row.get(4)?;
+                Ok(AuditEntry {
+                    id: row.get(0)?,
+                    actor: row.get(1)?,
+                    action: row.get(2)?,
+                    target: row.get(3)?,
+                    timestamp: DateTime::from_timestamp(timestamp, 0).unwrap(),
+                    address: row.get(5)?,
+                })
+            },
+        ) {
+            Ok(rows) => rows,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to query audit entries: {err}"
+                )))
+            }
+        };
+        let mut entries = Vec::new();
+        for row in rows {
+            match row {
+                Ok(entry) => entries.push(entry),
+                Err(err) => {
+                    return Err(DatastoreError::InternalError(format!(
+                        "Failed to read audit entry: {err}"
+                    )))
+                }
+            }
+        }
+        Ok(AuditPage { entries, total })
+    }
+
+    /// Returns the user the identity at an OpenID Connect provider belongs to, if it is known
+    pub fn get_identity_user(
+        &self,
+        conn: &Connection,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<Option<i32>, DatastoreError> {
+        let mut stmt = match conn
+            .prepare("SELECT userId FROM UserIdentities WHERE issuer = ?1 AND subject = ?2")
+        {
+            Ok(stmt) => stmt,
+            Err(err) => {
+                return Err(DatastoreError::InternalError(format!(
+                    "Failed to prepare get_identity_user SQL statement: {err}"
+                )))
+            }
+        };
+        match stmt.query_row(params![issuer, subject], |row| row.get::<usize, i32>(0)) {
+            Ok(user_id) => Ok(Some(user_id)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to query get_identity_user SQL statement: {err}"
+            ))),
+        }
+    }
+
+    pub fn add_user_identity(
+        &self,
+        conn: &Connection,
+        user_id: i32,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<(), DatastoreError> {
+        match conn.execute(
+            "INSERT INTO UserIdentities (issuer, subject, userId) VALUES (?1, ?2, ?3)",
+            params![issuer, subject, user_id],
+        ) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to add user identity: {err}"
+            ))),
+        }
+    }
+
+    pub fn update_team(
+        &self,
+        conn: &Connection,
+        team_id: i32,
+        name: &str,
+        description: &str,
+    ) -> Result<bool, DatastoreError> {
+        match conn.execute(
+            "UPDATE Teams SET name = ?1, description = ?2 WHERE id = ?3",
+            params![name, description, team_id],
+        ) {
+            Ok(updated) => Ok(updated > 0),
+            Err(err) => Err(DatastoreError::InternalError(format!(
+                "Failed to update team: {err}"
+            ))),
+        }
+    }
+
+    /// Makes a member the owner of the team
+    ///
+    /// The owner is set on the team itself, so the new owner's membership is removed and the
+    /// previous owner stays in the team as a manager.
+    pub fn transfer_team(
+        &self,
+        conn: &Connection,
+        team_id: i32,
+        new_owner_id: i32,
+    ) -> Result<(), DatastoreError> {
+        let team = self.get_team(conn, team_id)?;
+        let statements: [(&str, &[&dyn ToSql]); 3] = [
+            (
+                "UPDATE Teams SET ownerId = ?1 WHERE id = ?2",
+                &[&new_owner_id, &team_id],
+            ),
+            (
+                "DELETE FROM TeamsUsers WHERE teamId = ?1 AND userId = ?2",
+                &[&team_id, &new_owner_id],
+            ),
+            (
+                "INSERT INTO TeamsUsers (teamId, userId, role) VALUES (?1, ?2, ?3)",
+                &[&team_id, &team.ownerId, &Role::TeamManager.id()],
+            ),
+        ];
+        _in_savepoint(conn, "transfer_team", || {
+            for (statement, params) in statements {
+                if let Err(err) = conn.execute(statement, params) {
+                    return Err(DatastoreError::InternalError(format!(
+                        "Failed to transfer team: {err}"
+                    )));
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Deletes a team with its memberships, configuration and invitations
+    ///
+    /// Events stay in the buckets of their users, but are no longer tagged with the team.
+    pub fn delete_team(&self, conn: &Connection, team_id: i32) -> Result<bool, DatastoreError> {
+        let statements = [
+            "UPDATE events SET team_id = 0 WHERE team_id = ?1",
+            "DELETE FROM TeamConfigurationRevisions WHERE teamId = ?1",
+            "DELETE FROM Invitations WHERE teamId = ?1",
+            "DELETE FROM TeamsUsers WHERE teamId = ?1",
+        ];
+        _in_savepoint(conn, "delete_team", || {
+            for statement in statements {
+                if let Err(err) = conn.execute(statement, [team_id]) {
+                    return Err(DatastoreError::InternalError(format!(
+                        "Failed to delete team: {err}"
+                    )));
+                }
+            }
+            match conn.execute("DELETE FROM Teams WHERE id = ?1", [team_id]) {
+                Ok(deleted) => Ok(deleted > 0),
+                Err(err) => Err(DatastoreError::InternalError(format!(
+                    "Failed to delete team: {err}"
+                ))),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use aw_models::{AppRule, Bucket, BucketMetadata, TeamConfiguration, TeamRequestModel};
+
+    use super::DatastoreInstance;
+
+    #[test]
+    fn test_migrate_comma_joined_apps() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE TeamConfiguration (id INTEGER PRIMARY KEY, teamId INTEGER, apps TEXT)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO TeamConfiguration (teamId, apps) VALUES (1, 'GitHub,main.rs'), (2, NULL)",
+            [],
+        )
+        .unwrap();
+        super::_migrate_v17_to_v18(&conn);
+
+        let data: Vec<TeamConfiguration> = conn
+            .prepare("SELECT data FROM TeamConfiguration ORDER BY teamId")
+            .unwrap()
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .map(|data| serde_json::from_str(&data.unwrap()).unwrap())
+            .collect();
+        assert_eq!(
+            data[0].tracked_apps,
+            vec![
+                AppRule::title_contains("GitHub"),
+                AppRule::title_contains("main.rs")
+            ]
+        );
+        assert!(data[1].tracked_apps.is_empty());
+
+        // The entries were substrings of the window titles of any app
+        let matcher = data[0].matcher().unwrap();
+        assert!(matcher.matches("Firefox", "Pull requests · GitHub"));
+        assert!(matcher.matches("Code", "main.rs - aw-server"));
+        assert!(!matcher.matches("Code", "mainxrs"));
+        assert!(!matcher.matches("GitHub Desktop", "Changes"));
+    }
+
+    #[test]
+    fn test_migrate_duplicate_members() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE TeamsUsers (id INTEGER PRIMARY KEY, teamId INTEGER, userId INTEGER)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO TeamsUsers (teamId, userId) VALUES (1, 2), (1, 2), (1, 3), (2, 2)",
+            [],
+        )
+        .unwrap();
+        super::_migrate_v20_to_v21(&conn);
+
+        let ids: Vec<i32> = conn
+            .prepare("SELECT id FROM TeamsUsers ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(ids, vec![1, 3, 4]);
+        assert!(conn
+            .execute("INSERT INTO TeamsUsers (teamId, userId) VALUES (1, 3)", [])
+            .is_err());
+    }
+
+    #[test]
+    fn test_audit_log_append_only() {
+        let conn = Connection::open_in_memory().unwrap();
+        super::_migrate_v15_to_v16(&conn);
+        conn.execute(
+            "INSERT INTO AuditLog (actor, action, target, timestamp) VALUES (1, 'user.login', 'user:1', 0)",
+            [],
+        )
+        .unwrap();
+
+        assert!(conn
+            .execute("UPDATE AuditLog SET action = 'user.logout'", [])
+            .is_err());
+        assert!(conn.execute("DELETE FROM AuditLog", []).is_err());
+        let action: String = conn
+            .query_row("SELECT action FROM AuditLog", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(action, "user.login");
+    }
+
+    #[test]
+    fn test_delete_user_all_or_nothing() {
+        let conn = Connection::open_in_memory().unwrap();
+        let mut ds = DatastoreInstance::new(&conn, true).unwrap();
+        let bucket = Bucket {
+            bid: 0,
+            _type: "currentwindow".to_string(),
+            created: None,
+            data: serde_json::Map::new(),
+            metadata: BucketMetadata::default(),
+            events: None,
+            last_updated: None,
+            // The seeded admin
+            user_id: 1,
+        };
+        let bucket_id = ds.create_bucket(&conn, bucket).unwrap();
+        ds.add_api_key(&conn, 1, "laptop", "awk_key").unwrap();
+
+        // Deleting the user itself fails after its buckets and keys were deleted
+        conn.execute_batch(
+            "CREATE TEMP TRIGGER keep_users BEFORE DELETE ON Users
+                BEGIN SELECT RAISE(ABORT, 'Users are kept'); END",
+        )
+        .unwrap();
+        assert!(ds.delete_user(&conn, 1).is_err());
+        assert!(ds.get_bucket(bucket_id).is_ok());
+        let keys: i64 = conn
+            .query_row("SELECT COUNT(*) FROM ApiKeys", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(keys, 1);
+
+        conn.execute_batch("DROP TRIGGER keep_users").unwrap();
+        ds.delete_user(&conn, 1).unwrap();
+        assert!(ds.get_bucket(bucket_id).is_err());
+    }
+
+    #[test]
+    fn test_delete_team_all_or_nothing() {
+        let conn = Connection::open_in_memory().unwrap();
+        let ds = DatastoreInstance::new(&conn, true).unwrap();
+        let request = TeamRequestModel {
+            name: "team".to_string(),
+            description: String::new(),
+            ownerId: 1,
+        };
+        let team = ds.add_team(&conn, request, 1).unwrap();
+        conn.execute(
+            "INSERT INTO Users (username, email, name, lastname, password, role)
+                VALUES ('member', 'member@example.com', 'member', 'member', '', 2)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO TeamsUsers (teamId, userId) VALUES (?1, ?2)",
+            [team.id as i64, conn.last_insert_rowid()],
+        )
+        .unwrap();
+
+        // Deleting the team itself fails after its memberships were deleted
+        conn.execute_batch(
+            "CREATE TEMP TRIGGER keep_teams BEFORE DELETE ON Teams
+                BEGIN SELECT RAISE(ABORT, 'Teams are kept'); END",
+        )
+        .unwrap();
+        assert!(ds.delete_team(&conn, team.id).is_err());
+        let members: i64 = conn
+            .query_row("SELECT COUNT(*) FROM TeamsUsers", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(members, 1);
+
+        conn.execute_batch("DROP TRIGGER keep_teams").unwrap();
+        assert!(ds.delete_team(&conn, team.id).unwrap());
+        assert!(ds.get_team(&conn, team.id).is_err());
     }
 }
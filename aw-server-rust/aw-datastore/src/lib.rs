@@ -32,11 +32,17 @@ pub enum DatastoreMethod {
 #[derive(Debug, Clone)]
 pub enum DatastoreError {
     NoSuchBucket(String),
+    /// The event does not exist in the bucket
+    NoSuchEvent(i64),
     BucketAlreadyExists(String),
     NoSuchKey(String),
     MpscError,
     InternalError(String),
     NoUser(),
+    /// The email or username is already used by another account
+    UserAlreadyExists(String),
+    /// The user can not be deleted while they own teams
+    UserOwnsTeams(i32),
     // Errors specific to when migrate is disabled
     Uninitialized(String),
     OldDbVersion(String),
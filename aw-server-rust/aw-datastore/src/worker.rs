@@ -6,6 +6,7 @@ use std::thread;
 use aw_models::Member;
 use aw_models::PublicUser;
 use aw_models::TeamConfiguration;
+use aw_models::TeamConfigurationRevision;
 use aw_models::TeamUserModel;
 use chrono::DateTime;
 use chrono::Duration;
@@ -21,6 +22,21 @@ use aw_models::Event;
 use aw_models::Team;
 use aw_models::TeamRequestModel;
 use aw_models::User;
+use aw_models::MemberSchedule;
+use aw_models::AuditPage;
+use aw_models::AuditFilter;
+use aw_models::Invitation;
+use aw_models::UserPage;
+use aw_models::UserFilter;
+use aw_models::UserProfile;
+use aw_models::TwoFactor;
+use aw_models::LoginAttempts;
+use aw_models::ApiKey;
+use aw_models::TokenPurpose;
+use aw_models::OutboxMessage;
+use aw_models::OneTimeToken;
+use aw_models::Role;
+use aw_models::RefreshToken;
 
 use crate::DatastoreError;
 use crate::DatastoreInstance;
@@ -61,6 +77,8 @@ pub enum Response {
     UserTeams(Vec<TeamUserModel>),
     Team(Team),
     TeamConfiguration(TeamConfiguration),
+    TeamConfigurationRevision(TeamConfigurationRevision),
+    TeamConfigurationRevisions(Vec<TeamConfigurationRevision>),
     Bucket(Bucket),
     BucketMap(HashMap<String, Bucket>),
     Event(Event),
@@ -68,6 +86,22 @@ pub enum Response {
     Count(i64),
     KeyValue(String),
     KeyValues(HashMap<String, String>),
+    RefreshToken(RefreshToken),
+    Bool(bool),
+    TeamRole(Option<Role>),
+    Roles(Vec<Role>),
+    OneTimeToken(OneTimeToken),
+    OutboxMessages(Vec<OutboxMessage>),
+    ApiKey(ApiKey),
+    ApiKeys(Vec<ApiKey>),
+    LoginAttempts(Option<LoginAttempts>),
+    AllLoginAttempts(Vec<LoginAttempts>),
+    TwoFactor(Option<TwoFactor>),
+    UserPage(UserPage),
+    Invitation(Invitation),
+    Invitations(Vec<Invitation>),
+    AuditPage(AuditPage),
+    OptionalUserId(Option<i32>),
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -77,6 +111,7 @@ pub enum Command {
     DeleteBucket(i64),
     GetBucket(i64),
     GetBuckets(i32),
+    GetAllBuckets(),
     InsertEvents(i64, Vec<Event>),
     Heartbeat(i64, Event, f64),
     GetEvent(i64, i64),
@@ -110,12 +145,62 @@ pub enum Command {
     GetTeam(i32),
     GetAllUsers(),
     GetMembersOfTeam(i32),
+    GetMemberUser(i32, i32),
     AddMembers(i32, Vec<i32>),
     RemoveMember(i32, i32),
     GetUserTeams(i32),
-    AddTeamConfiguration(i32, String),
-    UpdateTeamConfiguration(i32, String),
+    SetTeamConfiguration(i32, TeamConfiguration, Option<i32>, Option<i32>),
+    GetTeamConfigurationRevisions(i32),
     GetTeamConfiguration(i32),
+    AddRefreshToken(i32, String, DateTime<Utc>),
+    GetRefreshToken(String),
+    DeleteRefreshToken(String),
+    RevokeUserTokens(i32),
+    GetTokenGeneration(i32),
+    RevokeToken(String, DateTime<Utc>),
+    IsTokenRevoked(String),
+    GetTeamRole(i32, i32),
+    GetSharedTeamRoles(i32, i32),
+    SetUserRole(i32, Role),
+    SetMemberRole(i32, i32, Role),
+    SetPassword(i32, String, bool),
+    SetInitialAdmin(String, String),
+    AddOneTimeToken(i32, TokenPurpose, String, DateTime<Utc>),
+    TakeOneTimeToken(TokenPurpose, String),
+    AddOutboxMessage(String, String, String),
+    GetOutboxMessages(),
+    AddApiKey(i32, String, String),
+    GetApiKeys(i32),
+    DeleteApiKey(i32, i64),
+    UseApiKey(String),
+    GetLoginAttempts(String),
+    GetAllLoginAttempts(),
+    SetLoginAttempts(LoginAttempts),
+    ClearLoginAttempts(String),
+    GetTwoFactor(i32),
+    SetTwoFactorSecret(i32, String),
+    EnableTwoFactor(i32, i64, Vec<String>),
+    SetTwoFactorStep(i32, i64),
+    UseRecoveryCode(i32, String),
+    DeleteTwoFactor(i32),
+    UpdateProfile(i32, UserProfile),
+    SetUserActive(i32, bool),
+    DeleteUser(i32),
+    GetUsersPage(UserFilter),
+    AddInvitation(i32, i32, String, Option<i32>, DateTime<Utc>),
+    GetTeamInvitations(i32),
+    GetInvitation(String),
+    UseInvitation(String, i32),
+    DeleteInvitation(i32, i64),
+    SetEmailVerified(i32),
+    AddAuditEntry(Option<i32>, String, Option<String>, Option<String>),
+    GetAuditEntries(AuditFilter),
+    GetIdentityUser(String, String),
+    AddUserIdentity(i32, String, String),
+    UpdateTeam(i32, String, String),
+    TransferTeam(i32, i32),
+    DeleteTeam(i32),
+    SetMemberSchedule(i32, i32, Option<MemberSchedule>),
 }
 
 fn _unwrap_response(
@@ -265,6 +350,7 @@ impl DatastoreWorker {
                 Err(e) => Err(e),
             },
             Command::GetBuckets(user_id) => Ok(Response::BucketMap(ds.get_buckets(tx, user_id))),
+            Command::GetAllBuckets() => Ok(Response::BucketMap(ds.get_all_buckets())),
             Command::InsertEvents(bucket_id, events) => {
                 match ds.insert_events(tx, bucket_id, events) {
                     Ok(events) => {
@@ -337,7 +423,10 @@ impl DatastoreWorker {
                 Err(e) => Err(e),
             },
             Command::AddUser(user) => match ds.signup(tx, user) {
-                Ok((result)) => Ok(Response::PublicUser(result)),
+                Ok((result)) => {
+                    self.commit = true;
+                    Ok(Response::PublicUser(result))
+                }
                 Err(e) => Err(e),
             },
 
@@ -352,7 +441,10 @@ impl DatastoreWorker {
             },
 
             Command::AddTeam(team, ownerId) => match ds.add_team(tx, team, ownerId) {
-                Ok(team) => Ok((Response::Empty())),
+                Ok(team) => {
+                    self.commit = true;
+                    Ok(Response::Team(team))
+                }
                 Err(e) => Err(e),
             },
 
@@ -365,6 +457,12 @@ impl DatastoreWorker {
                 Ok(members) => Ok(Response::Members((members))),
                 Err(e) => Err(e),
             },
+            Command::GetMemberUser(team_id, member_id) => {
+                match ds.get_member_user(tx, team_id, member_id) {
+                    Ok(user_id) => Ok(Response::OptionalUserId(user_id)),
+                    Err(e) => Err(e),
+                }
+            }
             Command::GetTeam(team_id) => match ds.get_team(tx, team_id) {
                 Ok(team) => Ok(Response::Team(team)),
                 Err(e) => Err(e),
@@ -392,16 +490,19 @@ impl DatastoreWorker {
                 Err(e) => Err(e),
             },
 
-            Command::AddTeamConfiguration(team_id, apps) => {
-                match ds.add_configuration(tx, team_id, apps) {
-                    Ok(teams) => Ok(Response::Empty()),
+            Command::SetTeamConfiguration(team_id, config, author, restored_from) => {
+                match ds.set_configuration(tx, team_id, &config, author, restored_from) {
+                    Ok(revision) => {
+                        self.commit = true;
+                        Ok(Response::TeamConfigurationRevision(revision))
+                    }
                     Err(e) => Err(e),
                 }
             }
 
-            Command::UpdateTeamConfiguration(team_id, apps) => {
-                match ds.update_configuration(tx, team_id, apps) {
-                    Ok(teams) => Ok(Response::Empty()),
+            Command::GetTeamConfigurationRevisions(team_id) => {
+                match ds.get_configuration_revisions(tx, team_id) {
+                    Ok(revisions) => Ok(Response::TeamConfigurationRevisions(revisions)),
                     Err(e) => Err(e),
                 }
             }
@@ -411,6 +512,408 @@ impl DatastoreWorker {
                 Err(e) => Err(e),
             },
 
+
+            Command::AddRefreshToken(user_id, token, expires) => {
+                match ds.add_refresh_token(tx, user_id, &token, expires) {
+                    Ok(()) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::GetRefreshToken(token) => match ds.get_refresh_token(tx, &token) {
+                Ok(refresh_token) => Ok(Response::RefreshToken(refresh_token)),
+                Err(e) => Err(e),
+            },
+
+            Command::DeleteRefreshToken(token) => match ds.delete_refresh_token(tx, &token) {
+                Ok(()) => {
+                    self.commit = true;
+                    Ok(Response::Empty())
+                }
+                Err(e) => Err(e),
+            },
+
+            Command::RevokeUserTokens(user_id) => match ds.revoke_user_tokens(tx, user_id) {
+                Ok(()) => {
+                    self.commit = true;
+                    Ok(Response::Empty())
+                }
+                Err(e) => Err(e),
+            },
+
+            Command::GetTokenGeneration(user_id) => match ds.get_token_generation(tx, user_id) {
+                Ok(generation) => Ok(Response::Count(generation)),
+                Err(e) => Err(e),
+            },
+
+            Command::RevokeToken(jti, expires) => match ds.revoke_token(tx, &jti, expires) {
+                Ok(()) => {
+                    self.commit = true;
+                    Ok(Response::Empty())
+                }
+                Err(e) => Err(e),
+            },
+
+            Command::IsTokenRevoked(jti) => match ds.is_token_revoked(tx, &jti) {
+                Ok(revoked) => Ok(Response::Bool(revoked)),
+                Err(e) => Err(e),
+            },
+
+            Command::GetTeamRole(team_id, user_id) => match ds.get_team_role(tx, team_id, user_id) {
+                Ok(role) => Ok(Response::TeamRole(role)),
+                Err(e) => Err(e),
+            },
+
+            Command::GetSharedTeamRoles(user_id, member_id) => {
+                match ds.get_shared_team_roles(tx, user_id, member_id) {
+                    Ok(roles) => Ok(Response::Roles(roles)),
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::SetUserRole(user_id, role) => match ds.set_user_role(tx, user_id, role) {
+                Ok(()) => {
+                    self.commit = true;
+                    Ok(Response::Empty())
+                }
+                Err(e) => Err(e),
+            },
+
+            Command::SetMemberRole(team_id, member_id, role) => {
+                match ds.set_member_role(tx, team_id, member_id, role) {
+                    Ok(()) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::SetPassword(user_id, password_hash, must_change_password) => {
+                match ds.set_password(tx, user_id, &password_hash, must_change_password) {
+                    Ok(()) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::SetInitialAdmin(email, password) => {
+                match ds.set_initial_admin(tx, &email, &password) {
+                    Ok(updated) => {
+                        self.commit = true;
+                        Ok(Response::Bool(updated))
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::AddOneTimeToken(user_id, purpose, token, expires) => {
+                match ds.add_one_time_token(tx, user_id, purpose, &token, expires) {
+                    Ok(()) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::TakeOneTimeToken(purpose, token) => {
+                match ds.take_one_time_token(tx, purpose, &token) {
+                    Ok(one_time_token) => {
+                        self.commit = true;
+                        Ok(Response::OneTimeToken(one_time_token))
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::AddOutboxMessage(recipient, subject, body) => {
+                match ds.add_outbox_message(tx, &recipient, &subject, &body) {
+                    Ok(()) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::GetOutboxMessages() => match ds.get_outbox_messages(tx) {
+                Ok(messages) => Ok(Response::OutboxMessages(messages)),
+                Err(e) => Err(e),
+            },
+
+            Command::AddApiKey(user_id, name, token) => {
+                match ds.add_api_key(tx, user_id, &name, &token) {
+                    Ok(api_key) => {
+                        self.commit = true;
+                        Ok(Response::ApiKey(api_key))
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::GetApiKeys(user_id) => match ds.get_api_keys(tx, user_id) {
+                Ok(api_keys) => Ok(Response::ApiKeys(api_keys)),
+                Err(e) => Err(e),
+            },
+
+            Command::DeleteApiKey(user_id, key_id) => {
+                match ds.delete_api_key(tx, user_id, key_id) {
+                    Ok(()) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::UseApiKey(token) => match ds.use_api_key(tx, &token) {
+                Ok(api_key) => {
+                    self.commit = true;
+                    Ok(Response::ApiKey(api_key))
+                }
+                Err(e) => Err(e),
+            },
+
+            Command::GetLoginAttempts(subject) => match ds.get_login_attempts(tx, &subject) {
+                Ok(attempts) => Ok(Response::LoginAttempts(attempts)),
+                Err(e) => Err(e),
+            },
+
+            Command::GetAllLoginAttempts() => match ds.get_all_login_attempts(tx) {
+                Ok(all_attempts) => Ok(Response::AllLoginAttempts(all_attempts)),
+                Err(e) => Err(e),
+            },
+
+            Command::SetLoginAttempts(attempts) => match ds.set_login_attempts(tx, &attempts) {
+                Ok(()) => {
+                    self.commit = true;
+                    Ok(Response::Empty())
+                }
+                Err(e) => Err(e),
+            },
+
+            Command::ClearLoginAttempts(subject) => match ds.clear_login_attempts(tx, &subject) {
+                Ok(()) => {
+                    self.commit = true;
+                    Ok(Response::Empty())
+                }
+                Err(e) => Err(e),
+            },
+
+            Command::GetTwoFactor(user_id) => match ds.get_two_factor(tx, user_id) {
+                Ok(two_factor) => Ok(Response::TwoFactor(two_factor)),
+                Err(e) => Err(e),
+            },
+
+            Command::SetTwoFactorSecret(user_id, secret) => {
+                match ds.set_two_factor_secret(tx, user_id, &secret) {
+                    Ok(()) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::EnableTwoFactor(user_id, last_step, recovery_codes) => {
+                match ds.enable_two_factor(tx, user_id, last_step, &recovery_codes) {
+                    Ok(()) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::SetTwoFactorStep(user_id, last_step) => {
+                match ds.set_two_factor_step(tx, user_id, last_step) {
+                    Ok(()) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::UseRecoveryCode(user_id, code) => {
+                match ds.use_recovery_code(tx, user_id, &code) {
+                    Ok(used) => {
+                        self.commit = true;
+                        Ok(Response::Bool(used))
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::DeleteTwoFactor(user_id) => match ds.delete_two_factor(tx, user_id) {
+                Ok(()) => {
+                    self.commit = true;
+                    Ok(Response::Empty())
+                }
+                Err(e) => Err(e),
+            },
+
+            Command::UpdateProfile(user_id, profile) => {
+                match ds.update_profile(tx, user_id, &profile) {
+                    Ok(updated) => {
+                        self.commit = true;
+                        Ok(Response::Bool(updated))
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::SetUserActive(user_id, active) => {
+                match ds.set_user_active(tx, user_id, active) {
+                    Ok(()) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::DeleteUser(user_id) => match ds.delete_user(tx, user_id) {
+                Ok(()) => {
+                    self.commit = true;
+                    Ok(Response::Empty())
+                }
+                Err(e) => Err(e),
+            },
+
+            Command::GetUsersPage(filter) => match ds.get_users_page(tx, &filter) {
+                Ok(page) => Ok(Response::UserPage(page)),
+                Err(e) => Err(e),
+            },
+
+            Command::AddInvitation(team_id, created_by, code, max_uses, expires) => {
+                match ds.add_invitation(tx, team_id, created_by, &code, max_uses, expires) {
+                    Ok(invitation) => {
+                        self.commit = true;
+                        Ok(Response::Invitation(invitation))
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::GetTeamInvitations(team_id) => match ds.get_team_invitations(tx, team_id) {
+                Ok(invitations) => Ok(Response::Invitations(invitations)),
+                Err(e) => Err(e),
+            },
+
+            Command::GetInvitation(code) => match ds.get_invitation(tx, &code) {
+                Ok(invitation) => Ok(Response::Invitation(invitation)),
+                Err(e) => Err(e),
+            },
+
+            Command::UseInvitation(code, user_id) => match ds.use_invitation(tx, &code, user_id) {
+                Ok(invitation) => {
+                    self.commit = true;
+                    Ok(Response::Invitation(invitation))
+                }
+                Err(e) => Err(e),
+            },
+
+            Command::DeleteInvitation(team_id, invitation_id) => {
+                match ds.delete_invitation(tx, team_id, invitation_id) {
+                    Ok(()) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::SetEmailVerified(user_id) => match ds.set_email_verified(tx, user_id) {
+                Ok(()) => {
+                    self.commit = true;
+                    Ok(Response::Empty())
+                }
+                Err(e) => Err(e),
+            },
+
+            Command::AddAuditEntry(actor, action, target, address) => {
+                match ds.add_audit_entry(
+                    tx,
+                    actor,
+                    &action,
+                    target.as_deref(),
+                    address.as_deref(),
+                ) {
+                    Ok(()) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::GetAuditEntries(filter) => match ds.get_audit_entries(tx, &filter) {
+                Ok(page) => Ok(Response::AuditPage(page)),
+                Err(e) => Err(e),
+            },
+
+            Command::GetIdentityUser(issuer, subject) => {
+                match ds.get_identity_user(tx, &issuer, &subject) {
+                    Ok(user_id) => Ok(Response::OptionalUserId(user_id)),
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::AddUserIdentity(user_id, issuer, subject) => {
+                match ds.add_user_identity(tx, user_id, &issuer, &subject) {
+                    Ok(()) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::UpdateTeam(team_id, name, description) => {
+                match ds.update_team(tx, team_id, &name, &description) {
+                    Ok(updated) => {
+                        self.commit = true;
+                        Ok(Response::Bool(updated))
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::TransferTeam(team_id, new_owner_id) => {
+                match ds.transfer_team(tx, team_id, new_owner_id) {
+                    Ok(()) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
+            Command::DeleteTeam(team_id) => match ds.delete_team(tx, team_id) {
+                Ok(deleted) => {
+                    self.commit = true;
+                    Ok(Response::Bool(deleted))
+                }
+                Err(e) => Err(e),
+            },
+
+            Command::SetMemberSchedule(team_id, member_id, schedule) => {
+                match ds.set_member_schedule(tx, team_id, member_id, schedule.as_ref()) {
+                    Ok(()) => {
+                        self.commit = true;
+                        Ok(Response::Empty())
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+
             Command::Close() => {
                 self.quit = true;
                 Ok(Response::Empty())
@@ -476,6 +979,21 @@ impl Datastore {
         }
     }
 
+    /// The buckets of all users, keyed by bucket id
+    pub fn get_all_buckets(&self) -> Result<HashMap<String, Bucket>, DatastoreError> {
+        let cmd = Command::GetAllBuckets();
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::BucketMap(bm) => Ok(bm),
+                e => Err(DatastoreError::InternalError(format!(
+                    "Invalid response: {e:?}"
+                ))),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn get_buckets(&self, user_id: i32) -> Result<HashMap<String, Bucket>, DatastoreError> {
         let cmd = Command::GetBuckets(user_id);
         let receiver = self.requester.request(cmd).unwrap();
@@ -731,12 +1249,12 @@ impl Datastore {
         }
     }
 
-    pub fn add_team(&self, team: TeamRequestModel, ownerId: i32) -> Result<(), DatastoreError> {
+    pub fn add_team(&self, team: TeamRequestModel, ownerId: i32) -> Result<Team, DatastoreError> {
         let cmd = Command::AddTeam(team, ownerId);
         let receiver = self.requester.request(cmd).unwrap();
         match receiver.collect().unwrap() {
             Ok(r) => match r {
-                Response::Empty() => Ok(()),
+                Response::Team(team) => Ok(team),
                 _ => Err(DatastoreError::InternalError(("".to_string()))),
             },
             Err(e) => Err(e),
@@ -767,13 +1285,30 @@ impl Datastore {
         }
     }
 
-    pub fn get_team(&self, team_id: i32) -> Result<Team, DatastoreError> {
-        let cmd = Command::GetTeam(team_id);
+    /// The user a membership of the team belongs to, if the membership exists
+    pub fn get_member_user(
+        &self,
+        team_id: i32,
+        member_id: i32,
+    ) -> Result<Option<i32>, DatastoreError> {
+        let cmd = Command::GetMemberUser(team_id, member_id);
         let receiver = self.requester.request(cmd).unwrap();
         match receiver.collect().unwrap() {
             Ok(r) => match r {
-                Response::Team(team) => Ok(team),
-                _ => Err(DatastoreError::InternalError(("".to_string()))),
+                Response::OptionalUserId(user_id) => Ok(user_id),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get_team(&self, team_id: i32) -> Result<Team, DatastoreError> {
+        let cmd = Command::GetTeam(team_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Team(team) => Ok(team),
+                _ => Err(DatastoreError::InternalError(("".to_string()))),
             },
             Err(e) => Err(e),
         }
@@ -817,29 +1352,39 @@ impl Datastore {
         }
     }
 
-    pub fn add_configuration(&self, team_id: i32, apps: String) -> Result<(), DatastoreError> {
-        let cmd = Command::AddTeamConfiguration(team_id, apps);
+    /// Stores a new revision of the configuration of a team, `restored_from` is the revision a
+    /// rollback restores
+    pub fn set_configuration(
+        &self,
+        team_id: i32,
+        config: TeamConfiguration,
+        author: Option<i32>,
+        restored_from: Option<i32>,
+    ) -> Result<TeamConfigurationRevision, DatastoreError> {
+        let cmd = Command::SetTeamConfiguration(team_id, config, author, restored_from);
         let receiver = self.requester.request(cmd).unwrap();
         match receiver.collect().unwrap() {
             Ok(r) => match r {
-                Response::Empty() => Ok(()),
+                Response::TeamConfigurationRevision(revision) => Ok(revision),
                 _ => Err(DatastoreError::InternalError(
-                    "Faild to add configuration".to_string(),
+                    "Faild to set configuration".to_string(),
                 )),
             },
             Err(e) => Err(e),
         }
     }
 
-    pub fn update_configuration(&self, team_id: i32, apps: String) -> Result<(), DatastoreError> {
-        let cmd = Command::UpdateTeamConfiguration(team_id, apps);
+    /// Newest first
+    pub fn get_configuration_revisions(
+        &self,
+        team_id: i32,
+    ) -> Result<Vec<TeamConfigurationRevision>, DatastoreError> {
+        let cmd = Command::GetTeamConfigurationRevisions(team_id);
         let receiver = self.requester.request(cmd).unwrap();
         match receiver.collect().unwrap() {
             Ok(r) => match r {
-                Response::Empty() => Ok(()),
-                _ => Err(DatastoreError::InternalError(
-                    "Faild to add configuration".to_string(),
-                )),
+                Response::TeamConfigurationRevisions(revisions) => Ok(revisions),
+                _ => panic!("Invalid response"),
             },
             Err(e) => Err(e),
         }
@@ -858,4 +1403,537 @@ impl Datastore {
             Err(e) => Err(e),
         }
     }
+
+    pub fn add_refresh_token(
+        &self,
+        user_id: i32,
+        token: &str,
+        expires: DateTime<Utc>,
+    ) -> Result<(), DatastoreError> {
+        let cmd = Command::AddRefreshToken(user_id, token.to_string(), expires);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn get_refresh_token(&self, token: &str) -> Result<RefreshToken, DatastoreError> {
+        let cmd = Command::GetRefreshToken(token.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::RefreshToken(refresh_token) => Ok(refresh_token),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn delete_refresh_token(&self, token: &str) -> Result<(), DatastoreError> {
+        let cmd = Command::DeleteRefreshToken(token.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn revoke_user_tokens(&self, user_id: i32) -> Result<(), DatastoreError> {
+        let cmd = Command::RevokeUserTokens(user_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn get_token_generation(&self, user_id: i32) -> Result<i64, DatastoreError> {
+        let cmd = Command::GetTokenGeneration(user_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Count(generation) => Ok(generation),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn revoke_token(&self, jti: &str, expires: DateTime<Utc>) -> Result<(), DatastoreError> {
+        let cmd = Command::RevokeToken(jti.to_string(), expires);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn is_token_revoked(&self, jti: &str) -> Result<bool, DatastoreError> {
+        let cmd = Command::IsTokenRevoked(jti.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Bool(revoked) => Ok(revoked),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get_team_role(&self, team_id: i32, user_id: i32) -> Result<Option<Role>, DatastoreError> {
+        let cmd = Command::GetTeamRole(team_id, user_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::TeamRole(role) => Ok(role),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get_shared_team_roles(
+        &self,
+        user_id: i32,
+        member_id: i32,
+    ) -> Result<Vec<Role>, DatastoreError> {
+        let cmd = Command::GetSharedTeamRoles(user_id, member_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Roles(roles) => Ok(roles),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn set_user_role(&self, user_id: i32, role: Role) -> Result<(), DatastoreError> {
+        let cmd = Command::SetUserRole(user_id, role);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn set_member_role(
+        &self,
+        team_id: i32,
+        member_id: i32,
+        role: Role,
+    ) -> Result<(), DatastoreError> {
+        let cmd = Command::SetMemberRole(team_id, member_id, role);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn set_password(
+        &self,
+        user_id: i32,
+        password_hash: &str,
+        must_change_password: bool,
+    ) -> Result<(), DatastoreError> {
+        let cmd = Command::SetPassword(user_id, password_hash.to_string(), must_change_password);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn set_initial_admin(&self, email: &str, password: &str) -> Result<bool, DatastoreError> {
+        let cmd = Command::SetInitialAdmin(email.to_string(), password.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Bool(updated) => Ok(updated),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn add_one_time_token(
+        &self,
+        user_id: i32,
+        purpose: TokenPurpose,
+        token: &str,
+        expires: DateTime<Utc>,
+    ) -> Result<(), DatastoreError> {
+        let cmd = Command::AddOneTimeToken(user_id, purpose, token.to_string(), expires);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn take_one_time_token(
+        &self,
+        purpose: TokenPurpose,
+        token: &str,
+    ) -> Result<OneTimeToken, DatastoreError> {
+        let cmd = Command::TakeOneTimeToken(purpose, token.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::OneTimeToken(one_time_token) => Ok(one_time_token),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn add_outbox_message(
+        &self,
+        recipient: &str,
+        subject: &str,
+        body: &str,
+    ) -> Result<(), DatastoreError> {
+        let cmd = Command::AddOutboxMessage(
+            recipient.to_string(),
+            subject.to_string(),
+            body.to_string(),
+        );
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn get_outbox_messages(&self) -> Result<Vec<OutboxMessage>, DatastoreError> {
+        let cmd = Command::GetOutboxMessages();
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::OutboxMessages(messages) => Ok(messages),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn add_api_key(
+        &self,
+        user_id: i32,
+        name: &str,
+        token: &str,
+    ) -> Result<ApiKey, DatastoreError> {
+        let cmd = Command::AddApiKey(user_id, name.to_string(), token.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::ApiKey(api_key) => Ok(api_key),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get_api_keys(&self, user_id: i32) -> Result<Vec<ApiKey>, DatastoreError> {
+        let cmd = Command::GetApiKeys(user_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::ApiKeys(api_keys) => Ok(api_keys),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn delete_api_key(&self, user_id: i32, key_id: i64) -> Result<(), DatastoreError> {
+        let cmd = Command::DeleteApiKey(user_id, key_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn use_api_key(&self, token: &str) -> Result<ApiKey, DatastoreError> {
+        let cmd = Command::UseApiKey(token.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::ApiKey(api_key) => Ok(api_key),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get_login_attempts(
+        &self,
+        subject: &str,
+    ) -> Result<Option<LoginAttempts>, DatastoreError> {
+        let cmd = Command::GetLoginAttempts(subject.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::LoginAttempts(attempts) => Ok(attempts),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get_all_login_attempts(&self) -> Result<Vec<LoginAttempts>, DatastoreError> {
+        let cmd = Command::GetAllLoginAttempts();
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::AllLoginAttempts(all_attempts) => Ok(all_attempts),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn set_login_attempts(&self, attempts: &LoginAttempts) -> Result<(), DatastoreError> {
+        let cmd = Command::SetLoginAttempts(attempts.clone());
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn clear_login_attempts(&self, subject: &str) -> Result<(), DatastoreError> {
+        let cmd = Command::ClearLoginAttempts(subject.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn get_two_factor(&self, user_id: i32) -> Result<Option<TwoFactor>, DatastoreError> {
+        let cmd = Command::GetTwoFactor(user_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::TwoFactor(two_factor) => Ok(two_factor),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn set_two_factor_secret(&self, user_id: i32, secret: &str) -> Result<(), DatastoreError> {
+        let cmd = Command::SetTwoFactorSecret(user_id, secret.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn enable_two_factor(
+        &self,
+        user_id: i32,
+        last_step: i64,
+        recovery_codes: Vec<String>,
+    ) -> Result<(), DatastoreError> {
+        let cmd = Command::EnableTwoFactor(user_id, last_step, recovery_codes);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn set_two_factor_step(&self, user_id: i32, last_step: i64) -> Result<(), DatastoreError> {
+        let cmd = Command::SetTwoFactorStep(user_id, last_step);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn use_recovery_code(&self, user_id: i32, code: &str) -> Result<bool, DatastoreError> {
+        let cmd = Command::UseRecoveryCode(user_id, code.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Bool(used) => Ok(used),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn delete_two_factor(&self, user_id: i32) -> Result<(), DatastoreError> {
+        let cmd = Command::DeleteTwoFactor(user_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn update_profile(
+        &self,
+        user_id: i32,
+        profile: UserProfile,
+    ) -> Result<bool, DatastoreError> {
+        let cmd = Command::UpdateProfile(user_id, profile);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Bool(updated) => Ok(updated),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn set_user_active(&self, user_id: i32, active: bool) -> Result<(), DatastoreError> {
+        let cmd = Command::SetUserActive(user_id, active);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn delete_user(&self, user_id: i32) -> Result<(), DatastoreError> {
+        let cmd = Command::DeleteUser(user_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn get_users_page(&self, filter: UserFilter) -> Result<UserPage, DatastoreError> {
+        let cmd = Command::GetUsersPage(filter);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::UserPage(page) => Ok(page),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn add_invitation(
+        &self,
+        team_id: i32,
+        created_by: i32,
+        code: &str,
+        max_uses: Option<i32>,
+        expires: DateTime<Utc>,
+    ) -> Result<Invitation, DatastoreError> {
+        let cmd = Command::AddInvitation(team_id, created_by, code.to_string(), max_uses, expires);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Invitation(invitation) => Ok(invitation),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get_team_invitations(&self, team_id: i32) -> Result<Vec<Invitation>, DatastoreError> {
+        let cmd = Command::GetTeamInvitations(team_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Invitations(invitations) => Ok(invitations),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get_invitation(&self, code: &str) -> Result<Invitation, DatastoreError> {
+        let cmd = Command::GetInvitation(code.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Invitation(invitation) => Ok(invitation),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn use_invitation(&self, code: &str, user_id: i32) -> Result<Invitation, DatastoreError> {
+        let cmd = Command::UseInvitation(code.to_string(), user_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Invitation(invitation) => Ok(invitation),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn delete_invitation(&self, team_id: i32, invitation_id: i64) -> Result<(), DatastoreError> {
+        let cmd = Command::DeleteInvitation(team_id, invitation_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn set_email_verified(&self, user_id: i32) -> Result<(), DatastoreError> {
+        let cmd = Command::SetEmailVerified(user_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn add_audit_entry(
+        &self,
+        actor: Option<i32>,
+        action: &str,
+        target: Option<String>,
+        address: Option<String>,
+    ) -> Result<(), DatastoreError> {
+        let cmd = Command::AddAuditEntry(actor, action.to_string(), target, address);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    pub fn get_audit_entries(&self, filter: AuditFilter) -> Result<AuditPage, DatastoreError> {
+        let cmd = Command::GetAuditEntries(filter);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::AuditPage(page) => Ok(page),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get_identity_user(
+        &self,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<Option<i32>, DatastoreError> {
+        let cmd = Command::GetIdentityUser(issuer.to_string(), subject.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::OptionalUserId(user_id) => Ok(user_id),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn add_user_identity(
+        &self,
+        user_id: i32,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<(), DatastoreError> {
+        let cmd = Command::AddUserIdentity(user_id, issuer.to_string(), subject.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    /// Returns false if there is no team with the id
+    pub fn update_team(
+        &self,
+        team_id: i32,
+        name: &str,
+        description: &str,
+    ) -> Result<bool, DatastoreError> {
+        let cmd = Command::UpdateTeam(team_id, name.to_string(), description.to_string());
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Bool(changed) => Ok(changed),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn transfer_team(&self, team_id: i32, new_owner_id: i32) -> Result<(), DatastoreError> {
+        let cmd = Command::TransferTeam(team_id, new_owner_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
+
+    /// Returns false if there is no team with the id
+    pub fn delete_team(&self, team_id: i32) -> Result<bool, DatastoreError> {
+        let cmd = Command::DeleteTeam(team_id);
+        let receiver = self.requester.request(cmd).unwrap();
+        match receiver.collect().unwrap() {
+            Ok(r) => match r {
+                Response::Bool(changed) => Ok(changed),
+                _ => panic!("Invalid response"),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn set_member_schedule(
+        &self,
+        team_id: i32,
+        member_id: i32,
+        schedule: Option<MemberSchedule>,
+    ) -> Result<(), DatastoreError> {
+        let cmd = Command::SetMemberSchedule(team_id, member_id, schedule);
+        let receiver = self.requester.request(cmd).unwrap();
+        _unwrap_response(receiver)
+    }
 }
@@ -0,0 +1,17 @@
+use chrono::DateTime;
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A long-lived key a device, like a watcher, uses instead of logging in
+///
+/// Only a hash of the key is stored, the key itself is shown to the user once when it is created.
+/// Keys can only create buckets of their user and write events and heartbeats to them.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct ApiKey {
+    pub id: i64,
+    pub user_id: i32,
+    pub name: String,
+    pub created: DateTime<Utc>,
+    pub last_used: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,44 @@
+use chrono::DateTime;
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// An entry of the audit log, which records security relevant and administrative actions
+///
+/// Entries are only ever appended, the datastore refuses to change or delete them.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// The user who performed the action, unknown for failed logins of missing accounts
+    pub actor: Option<i32>,
+    /// Dotted name of the action, such as `team.member_add`
+    pub action: String,
+    /// What the action was performed on, such as `bucket:12` or `team:3/user:5`
+    pub target: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    /// Address of the client which sent the request
+    pub address: Option<String>,
+}
+
+/// Filters and page of a query of the audit log
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
+pub struct AuditFilter {
+    pub actor: Option<i32>,
+    /// Matches the action itself and actions below it, `team` matches `team.member_add`
+    pub action: Option<String>,
+    /// Matches the target itself and targets below it, `team:3` matches `team:3/user:5`
+    pub target: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub offset: i64,
+    /// Entries are returned newest first, no limit returns all matching entries
+    pub limit: Option<i64>,
+}
+
+/// A page of the audit log
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct AuditPage {
+    pub entries: Vec<AuditEntry>,
+    /// Number of entries matching the filters on all pages
+    pub total: i64,
+}
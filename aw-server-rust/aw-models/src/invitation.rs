@@ -0,0 +1,19 @@
+use chrono::DateTime;
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// An invitation to join a team, which can also be used to sign up
+///
+/// Only a hash of the code is stored, the code itself is shown once when the invitation is
+/// created. Invitations without `max_uses` can be used until they expire.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct Invitation {
+    pub id: i64,
+    pub team_id: i32,
+    pub created_by: i32,
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    pub created: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+}
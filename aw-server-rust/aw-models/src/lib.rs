@@ -16,31 +16,74 @@ macro_rules! json_map {
     }};
 }
 
+mod api_key;
+mod audit;
 mod bucket;
 mod duration;
 mod event;
 mod info;
+mod invitation;
+mod outbox;
 mod query;
+mod report;
+mod role;
+mod schedule;
 mod team;
+mod team_config;
 mod timeinterval;
 mod tryvec;
 mod user;
 
+pub use self::api_key::ApiKey;
+pub use self::audit::AuditEntry;
+pub use self::audit::AuditFilter;
+pub use self::audit::AuditPage;
 pub use self::bucket::Bucket;
 pub use self::bucket::PublicBucket;
 pub use self::bucket::BucketMetadata;
 pub use self::bucket::BucketsExport;
 pub use self::event::Event;
 pub use self::info::Info;
+pub use self::invitation::Invitation;
+pub use self::outbox::OutboxMessage;
 pub use self::query::Query;
+pub use self::report::AppTime;
+pub use self::report::CategoryTime;
+pub use self::report::MemberReport;
+pub use self::report::ReportGranularity;
+pub use self::report::ReportPeriod;
+pub use self::report::TeamReport;
+pub use self::role::Permission;
+pub use self::role::Role;
+pub use self::schedule::parse_time_zone;
+pub use self::schedule::MemberSchedule;
+pub use self::schedule::WorkSchedule;
 pub use self::team::Member;
 pub use self::team::Team;
 pub use self::team::TeamDetailModel;
 pub use self::team::TeamRequestModel;
 pub use self::team::TeamResponseModel;
 pub use self::team::TeamUserModel;
-pub use self::team::TeamConfiguration;
+pub use self::team_config::AppMatcher;
+pub use self::team_config::AppRule;
+pub use self::team_config::ConfigurationChange;
+pub use self::team_config::ConfigurationHistory;
+pub use self::team_config::PrivacyLevel;
+pub use self::team_config::TeamConfiguration;
+pub use self::team_config::TeamConfigurationDiff;
+pub use self::team_config::TeamConfigurationRevision;
+pub use self::team_config::WorkingHours;
+pub use self::team_config::TEAM_CONFIGURATION_VERSION;
 pub use self::timeinterval::TimeInterval;
 pub use self::tryvec::TryVec;
+pub use self::user::LoginAttempts;
+pub use self::user::OneTimeToken;
 pub use self::user::PublicUser;
+pub use self::user::RefreshToken;
+pub use self::user::TokenPurpose;
+pub use self::user::TwoFactor;
 pub use self::user::User;
+pub use self::user::UserFilter;
+pub use self::user::UserPage;
+pub use self::user::UserProfile;
+pub use self::user::UserSort;
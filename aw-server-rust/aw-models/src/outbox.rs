@@ -0,0 +1,14 @@
+use chrono::DateTime;
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A notification stored in the database instead of being sent, see the `database` notifier
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct OutboxMessage {
+    pub id: i64,
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    pub created: DateTime<Utc>,
+}
@@ -0,0 +1,65 @@
+use chrono::DateTime;
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::PrivacyLevel;
+
+/// Length of the periods a report is split into
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportGranularity {
+    Day,
+    /// Weeks start on Monday
+    Week,
+}
+
+/// The active time of the members of a team in the tracked apps
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct TeamReport {
+    pub team_id: i32,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub granularity: ReportGranularity,
+    /// Whether only the activity within the working hours of the members is counted
+    pub working_hours: bool,
+    pub members: Vec<MemberReport>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct MemberReport {
+    pub user_id: i32,
+    pub name: String,
+    pub lastname: String,
+    /// Time zone of the member, their days and weeks start at its midnight
+    pub time_zone: String,
+    /// The level the periods are reduced to for the user requesting the report, apps are left out
+    /// from `category_only` and categories from `totals_only`
+    pub privacy_level: PrivacyLevel,
+    pub periods: Vec<ReportPeriod>,
+}
+
+/// The active time of a member within one period of a report, durations are in seconds
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct ReportPeriod {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub active: f64,
+    /// Longest first
+    pub apps: Vec<AppTime>,
+    /// Categories of the window titles, longest first
+    pub categories: Vec<CategoryTime>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct AppTime {
+    pub app: String,
+    pub duration: f64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct CategoryTime {
+    /// Hierarchy of the category, such as `["Work", "Programming"]`
+    pub category: Vec<String>,
+    pub duration: f64,
+}
@@ -0,0 +1,152 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Role of a user
+///
+/// A user has a system wide role stored in `Users.role` and a role in each team they are a
+/// member of stored in `TeamsUsers.role`. The owner of a team always has the `TeamOwner` role in
+/// it, the other members have one of the team roles (see [`Role::is_team_role`]).
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Admin,
+    TeamOwner,
+    TeamManager,
+    Member,
+    Auditor,
+    /// Sees the team and the activity of its members, but can not change anything
+    Viewer,
+}
+
+/// An action which a role may or may not be allowed to perform
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// List all users of the server
+    ViewUsers,
+    /// Change the system wide role of users
+    ManageUsers,
+    ReadSettings,
+    WriteSettings,
+    CreateTeam,
+    /// See a team, its members and configuration
+    ViewTeam,
+    /// Add and remove members, rename a team and change its configuration
+    ManageTeam,
+    /// Hand a team over to another member
+    TransferTeam,
+    DeleteTeam,
+    /// Read the buckets and events of other users
+    ReadUserData,
+    /// Modify and delete the buckets and events of other users, only granted to admins
+    WriteUserData,
+    Query,
+    /// Read and export the audit log
+    ViewAuditLog,
+}
+
+impl Role {
+    /// The integer the role is stored as in the database
+    pub fn id(self) -> i8 {
+        match self {
+            Role::Admin => 1,
+            Role::Member => 2,
+            Role::TeamOwner => 3,
+            Role::TeamManager => 4,
+            Role::Auditor => 5,
+            Role::Viewer => 6,
+        }
+    }
+
+    pub fn from_id(id: i8) -> Option<Role> {
+        match id {
+            1 => Some(Role::Admin),
+            2 => Some(Role::Member),
+            3 => Some(Role::TeamOwner),
+            4 => Some(Role::TeamManager),
+            5 => Some(Role::Auditor),
+            6 => Some(Role::Viewer),
+            _ => None,
+        }
+    }
+
+    /// Whether the team and user data permissions of the role apply to all teams and users, not
+    /// only to the teams the user is a member of
+    pub fn is_global(self) -> bool {
+        matches!(self, Role::Admin | Role::Auditor)
+    }
+
+    /// Whether members can be given the role within a team
+    ///
+    /// The owner role is set by transferring the team, the viewer role only exists within teams.
+    pub fn is_team_role(self) -> bool {
+        matches!(self, Role::TeamManager | Role::Member | Role::Viewer)
+    }
+
+    pub fn has_permission(self, permission: Permission) -> bool {
+        use Permission::*;
+        match self {
+            Role::Admin => true,
+            Role::TeamOwner => !matches!(permission, ManageUsers | WriteUserData | ViewAuditLog),
+            Role::TeamManager => !matches!(
+                permission,
+                ManageUsers | CreateTeam | TransferTeam | DeleteTeam | WriteUserData | ViewAuditLog
+            ),
+            Role::Member => matches!(permission, ReadSettings | WriteSettings | ViewTeam | Query),
+            Role::Auditor => matches!(
+                permission,
+                ViewUsers | ReadSettings | ViewTeam | ReadUserData | Query | ViewAuditLog
+            ),
+            Role::Viewer => matches!(
+                permission,
+                ReadSettings | WriteSettings | ViewTeam | ReadUserData | Query
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Permission, Role};
+
+    #[test]
+    fn test_role_id() {
+        for role in [
+            Role::Admin,
+            Role::TeamOwner,
+            Role::TeamManager,
+            Role::Member,
+            Role::Auditor,
+            Role::Viewer,
+        ] {
+            assert_eq!(Role::from_id(role.id()), Some(role));
+        }
+        assert_eq!(Role::from_id(0), None);
+    }
+
+    #[test]
+    fn test_role_permissions() {
+        assert!(Role::Admin.has_permission(Permission::ManageUsers));
+        assert!(!Role::TeamOwner.has_permission(Permission::ManageUsers));
+        assert!(Role::TeamManager.has_permission(Permission::ManageTeam));
+        assert!(!Role::TeamManager.has_permission(Permission::WriteUserData));
+        assert!(!Role::TeamOwner.has_permission(Permission::WriteUserData));
+        assert!(Role::TeamOwner.has_permission(Permission::DeleteTeam));
+        assert!(!Role::TeamManager.has_permission(Permission::DeleteTeam));
+        assert!(!Role::Member.has_permission(Permission::ReadUserData));
+        assert!(Role::Auditor.has_permission(Permission::ReadUserData));
+        assert!(!Role::Auditor.has_permission(Permission::WriteSettings));
+        assert!(Role::Auditor.has_permission(Permission::ViewAuditLog));
+        assert!(!Role::TeamOwner.has_permission(Permission::ViewAuditLog));
+        assert!(Role::Viewer.has_permission(Permission::ReadUserData));
+        assert!(!Role::Viewer.has_permission(Permission::ManageTeam));
+    }
+
+    #[test]
+    fn test_team_roles() {
+        assert!(Role::Viewer.is_team_role());
+        assert!(Role::TeamManager.is_team_role());
+        assert!(!Role::TeamOwner.is_team_role());
+        assert!(!Role::Auditor.is_team_role());
+    }
+}
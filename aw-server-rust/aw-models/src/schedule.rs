@@ -0,0 +1,250 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{TeamConfiguration, WorkingHours};
+
+/// Working hours of a member which differ from those of their team
+///
+/// Stored per team membership, fields which are missing are taken from the configuration of the
+/// team.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct MemberSchedule {
+    /// IANA name of the time zone of the member
+    pub time_zone: Option<String>,
+    pub working_hours: Option<WorkingHours>,
+    /// Days off of the member, in addition to the holidays of the team
+    pub holidays: Vec<NaiveDate>,
+}
+
+/// The time zone, working hours and days off which apply to a member of a team
+#[derive(Clone, Debug)]
+pub struct WorkSchedule {
+    pub time_zone: Tz,
+    /// Every day is worked all day if missing
+    pub working_hours: Option<WorkingHours>,
+    pub holidays: Vec<NaiveDate>,
+}
+
+/// Parses the IANA name of a time zone, such as `Europe/Berlin`
+pub fn parse_time_zone(name: &str) -> Result<Tz, String> {
+    Tz::from_str(name).map_err(|_| format!("Unknown time zone '{name}'"))
+}
+
+impl MemberSchedule {
+    /// Checks that the schedule can be stored, returns a description of the first problem
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(time_zone) = &self.time_zone {
+            parse_time_zone(time_zone)?;
+        }
+        if let Some(hours) = &self.working_hours {
+            hours.validate()?;
+        }
+        Ok(())
+    }
+}
+
+impl WorkSchedule {
+    /// The schedule of a member of a team, `member` overriding the configuration of the team
+    pub fn new(
+        team: &TeamConfiguration,
+        member: Option<&MemberSchedule>,
+    ) -> Result<WorkSchedule, String> {
+        let time_zone = member
+            .and_then(|member| member.time_zone.as_deref())
+            .unwrap_or(&team.time_zone);
+        let working_hours = member
+            .and_then(|member| member.working_hours.clone())
+            .or_else(|| team.working_hours.clone());
+        let mut holidays = team.holidays.clone();
+        if let Some(member) = member {
+            holidays.extend(&member.holidays);
+        }
+        Ok(WorkSchedule {
+            time_zone: parse_time_zone(time_zone)?,
+            working_hours,
+            holidays,
+        })
+    }
+
+    /// The local date at `time`
+    pub fn date(&self, time: DateTime<Utc>) -> NaiveDate {
+        time.with_timezone(&self.time_zone).date_naive()
+    }
+
+    /// The instant of a local date and time
+    ///
+    /// Local times which are skipped by a daylight saving change resolve to the first instant
+    /// after the change, repeated ones to their first occurrence.
+    pub fn instant(&self, date: NaiveDate, time: NaiveTime) -> DateTime<Utc> {
+        let mut local = date.and_time(time);
+        loop {
+            match self.time_zone.from_local_datetime(&local) {
+                LocalResult::Single(instant) | LocalResult::Ambiguous(instant, _) => {
+                    return instant.with_timezone(&Utc)
+                }
+                LocalResult::None => local += Duration::minutes(1),
+            }
+        }
+    }
+
+    /// The local midnight which starts the day after the one containing `time`
+    pub fn next_day(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let date = self.date(time) + Duration::days(1);
+        self.instant(date, NaiveTime::MIN)
+    }
+
+    /// The local midnight which starts the week after the one containing `time`, weeks start on
+    /// Monday
+    pub fn next_week(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let date = self.date(time);
+        let date = date + Duration::days(7 - date.weekday().num_days_from_monday() as i64);
+        self.instant(date, NaiveTime::MIN)
+    }
+
+    fn is_working_day(&self, date: NaiveDate) -> bool {
+        !self.holidays.contains(&date)
+            && match &self.working_hours {
+                Some(hours) => hours.days.contains(&date.weekday()),
+                None => true,
+            }
+    }
+
+    /// The working time between `start` and `end` as ordered, non overlapping periods
+    pub fn working_periods(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let mut periods = Vec::new();
+        let mut date = self.date(start);
+        while date <= self.date(end) {
+            if self.is_working_day(date) {
+                let (from, to) = match &self.working_hours {
+                    Some(hours) => (
+                        self.instant(date, hours.start),
+                        self.instant(date, hours.end),
+                    ),
+                    None => (
+                        self.instant(date, NaiveTime::MIN),
+                        self.instant(date + Duration::days(1), NaiveTime::MIN),
+                    ),
+                };
+                let (from, to) = (from.max(start), to.min(end));
+                if from < to {
+                    periods.push((from, to));
+                }
+            }
+            date += Duration::days(1);
+        }
+        periods
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{DateTime, NaiveDate, NaiveTime, Utc, Weekday};
+
+    use super::{MemberSchedule, WorkSchedule};
+    use crate::{TeamConfiguration, WorkingHours};
+
+    fn time(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn team() -> TeamConfiguration {
+        TeamConfiguration {
+            working_hours: Some(WorkingHours {
+                days: vec![Weekday::Mon, Weekday::Tue, Weekday::Wed],
+                start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+            }),
+            time_zone: "Europe/Berlin".to_string(),
+            holidays: vec![date("2024-01-02")],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_days_split_at_local_midnight() {
+        let schedule = WorkSchedule::new(&team(), None).unwrap();
+        // 23:30 UTC is already the next day in Berlin
+        assert_eq!(
+            schedule.next_day(time("2024-01-03T23:30:00Z")),
+            time("2024-01-04T23:00:00Z")
+        );
+        assert_eq!(
+            schedule.next_week(time("2024-01-03T12:00:00Z")),
+            time("2024-01-07T23:00:00Z")
+        );
+        // The day of the change to daylight saving time has 23 hours
+        assert_eq!(
+            schedule.next_day(time("2024-03-30T23:00:00Z")),
+            time("2024-03-31T22:00:00Z")
+        );
+    }
+
+    #[test]
+    fn test_working_periods() {
+        let schedule = WorkSchedule::new(&team(), None).unwrap();
+        // 2024-01-01 is a Monday, the Tuesday is a holiday and Thursdays are not worked
+        let periods =
+            schedule.working_periods(time("2024-01-01T10:00:00Z"), time("2024-01-05T00:00:00Z"));
+        assert_eq!(
+            periods,
+            vec![
+                (time("2024-01-01T10:00:00Z"), time("2024-01-01T16:00:00Z")),
+                (time("2024-01-03T08:00:00Z"), time("2024-01-03T16:00:00Z")),
+            ]
+        );
+
+        // Members can have their own time zone and days off
+        let member = MemberSchedule {
+            time_zone: Some("America/New_York".to_string()),
+            holidays: vec![date("2024-01-01")],
+            ..Default::default()
+        };
+        assert!(member.validate().is_ok());
+        let schedule = WorkSchedule::new(&team(), Some(&member)).unwrap();
+        let periods =
+            schedule.working_periods(time("2024-01-01T00:00:00Z"), time("2024-01-05T00:00:00Z"));
+        assert_eq!(
+            periods,
+            vec![(time("2024-01-03T14:00:00Z"), time("2024-01-03T22:00:00Z"))]
+        );
+
+        // Without working hours every day but the holidays is worked all day
+        let schedule = WorkSchedule::new(&TeamConfiguration::default(), None).unwrap();
+        let periods =
+            schedule.working_periods(time("2024-01-01T12:00:00Z"), time("2024-01-02T12:00:00Z"));
+        assert_eq!(
+            periods,
+            vec![
+                (time("2024-01-01T12:00:00Z"), time("2024-01-02T00:00:00Z")),
+                (time("2024-01-02T00:00:00Z"), time("2024-01-02T12:00:00Z")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_time_zone() {
+        let member = MemberSchedule {
+            time_zone: Some("Mars/Olympus_Mons".to_string()),
+            ..Default::default()
+        };
+        assert!(member.validate().is_err());
+        let team = TeamConfiguration {
+            time_zone: "Nowhere".to_string(),
+            ..Default::default()
+        };
+        assert!(team.validate().is_err());
+    }
+}
@@ -1,6 +1,10 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::MemberSchedule;
+use crate::Role;
+use crate::TeamConfiguration;
+
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub struct Team {
     pub id: i32,
@@ -33,6 +37,9 @@ pub struct Member {
     pub name: String,
     pub lastname: String,
     pub email: String,
+    pub role: Role,
+    /// Working hours of the member which differ from those of the team
+    pub schedule: Option<MemberSchedule>,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
@@ -41,7 +48,7 @@ pub struct TeamDetailModel {
     pub name: String,
     pub description: String,
     pub members: Vec<Member>,
-    pub apps: Vec<String>,
+    pub configuration: TeamConfiguration,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
@@ -51,11 +58,3 @@ pub struct TeamUserModel {
     pub name: String,
     pub description: String,
 }
-
-#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
-
-pub struct TeamConfiguration {
-    pub id: i32,
-    pub team_id: i32,
-    pub apps: Vec<String>,
-}
@@ -0,0 +1,492 @@
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc, Weekday};
+use fancy_regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::parse_time_zone;
+
+/// Version of the team configuration format written by this server
+pub const TEAM_CONFIGURATION_VERSION: u32 = 1;
+
+/// What the watchers of the members of a team track
+///
+/// Stored as JSON per team, fields missing from the JSON take their default values.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TeamConfiguration {
+    pub version: u32,
+    /// Apps which are tracked, no app is tracked if there are none
+    pub tracked_apps: Vec<AppRule>,
+    /// Regexes of app names which are never tracked, even if a tracked app matches them
+    pub excluded_apps: Vec<String>,
+    /// Domains of the URLs which are tracked in browsers, including their subdomains
+    pub url_domains: Vec<String>,
+    /// Seconds without input after which a member is considered AFK
+    pub afk_timeout: u32,
+    /// Seconds between two polls of the watchers
+    pub poll_interval: u32,
+    /// When members are tracked, always if missing
+    pub working_hours: Option<WorkingHours>,
+    /// IANA name of the time zone of the working hours and holidays, such as `Europe/Berlin`
+    pub time_zone: String,
+    /// Days without working hours
+    pub holidays: Vec<NaiveDate>,
+    /// How much of the activity of the members the managers and owner of the team can see
+    pub privacy_level: PrivacyLevel,
+}
+
+/// Granularity at which the activity of a member is shown to others in their team
+///
+/// Levels are ordered from the most to the least detailed. Members always see all of their own
+/// activity.
+#[derive(
+    Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivacyLevel {
+    /// The events as recorded, including window titles and URLs
+    #[default]
+    RawEvents,
+    /// The time spent in each app
+    AppOnly,
+    /// The time spent in each category
+    CategoryOnly,
+    /// The active time only
+    TotalsOnly,
+}
+
+/// Matches the windows of a tracked app
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct AppRule {
+    /// Regex matched against the app name
+    pub app: String,
+    /// Regex matched against the window title, any title matches if missing
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct WorkingHours {
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+/// A stored version of the configuration of a team, every change of the configuration adds one
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct TeamConfigurationRevision {
+    /// Number of the revision within its team, starting at 1
+    pub revision: i32,
+    /// The user who made the change, unknown for configurations from before revisions existed
+    pub author: Option<i32>,
+    /// When the revision took effect
+    pub created: DateTime<Utc>,
+    /// The revision which was restored, if the change was a rollback
+    pub restored_from: Option<i32>,
+    pub configuration: TeamConfiguration,
+}
+
+/// The changes between two revisions of the configuration of a team
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct TeamConfigurationDiff {
+    pub from: i32,
+    pub to: i32,
+    pub changes: Vec<ConfigurationChange>,
+}
+
+/// A change of a field of a [`TeamConfiguration`]
+///
+/// Changes of list fields are reported per added and removed item.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ConfigurationChange {
+    Added {
+        field: String,
+        value: Value,
+    },
+    Removed {
+        field: String,
+        value: Value,
+    },
+    Changed {
+        field: String,
+        from: Value,
+        to: Value,
+    },
+}
+
+/// The revisions of the configuration of a team, ordered by when they took effect
+pub struct ConfigurationHistory {
+    revisions: Vec<TeamConfigurationRevision>,
+    default: TeamConfiguration,
+}
+
+/// The compiled app rules of a [`TeamConfiguration`]
+pub struct AppMatcher {
+    tracked: Vec<(Regex, Option<Regex>)>,
+    excluded: Vec<Regex>,
+}
+
+impl Default for TeamConfiguration {
+    fn default() -> Self {
+        TeamConfiguration {
+            version: TEAM_CONFIGURATION_VERSION,
+            tracked_apps: Vec::new(),
+            excluded_apps: Vec::new(),
+            url_domains: Vec::new(),
+            afk_timeout: 180,
+            poll_interval: 5,
+            working_hours: None,
+            time_zone: "UTC".to_string(),
+            holidays: Vec::new(),
+            privacy_level: PrivacyLevel::default(),
+        }
+    }
+}
+
+impl AppRule {
+    /// A rule matching exactly the app with the given name
+    pub fn exact(app: &str) -> AppRule {
+        AppRule {
+            app: format!("^{}$", fancy_regex::escape(app)),
+            title: None,
+        }
+    }
+
+    /// A rule matching the windows of any app whose title contains the given text
+    pub fn title_contains(text: &str) -> AppRule {
+        AppRule {
+            app: ".*".to_string(),
+            title: Some(fancy_regex::escape(text).to_string()),
+        }
+    }
+}
+
+impl WorkingHours {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.days.is_empty() {
+            return Err("Working hours need at least one day".to_string());
+        }
+        if self.start >= self.end {
+            return Err("Working hours must start before they end".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl TeamConfiguration {
+    /// Checks that the configuration can be stored, returns a description of the first problem
+    pub fn validate(&self) -> Result<(), String> {
+        if self.version != TEAM_CONFIGURATION_VERSION {
+            return Err(format!(
+                "Unsupported configuration version {}, expected {TEAM_CONFIGURATION_VERSION}",
+                self.version
+            ));
+        }
+        self.matcher()?;
+        for domain in &self.url_domains {
+            let valid = !domain.is_empty()
+                && domain.split('.').all(|label| {
+                    !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+                });
+            if !valid {
+                return Err(format!("Invalid URL domain '{domain}'"));
+            }
+        }
+        if self.afk_timeout == 0 {
+            return Err("The AFK timeout must be at least one second".to_string());
+        }
+        if self.poll_interval == 0 || self.poll_interval > self.afk_timeout {
+            return Err(
+                "The poll interval must be between one second and the AFK timeout".to_string(),
+            );
+        }
+        if let Some(hours) = &self.working_hours {
+            hours.validate()?;
+        }
+        parse_time_zone(&self.time_zone)?;
+        Ok(())
+    }
+
+    /// Compiles the tracked and excluded app regexes
+    pub fn matcher(&self) -> Result<AppMatcher, String> {
+        let compile = |regex: &str| {
+            Regex::new(regex).map_err(|err| format!("Invalid regex '{regex}': {err}"))
+        };
+        let mut tracked = Vec::new();
+        for rule in &self.tracked_apps {
+            let title = match &rule.title {
+                Some(title) => Some(compile(title)?),
+                None => None,
+            };
+            tracked.push((compile(&rule.app)?, title));
+        }
+        let mut excluded = Vec::new();
+        for app in &self.excluded_apps {
+            excluded.push(compile(app)?);
+        }
+        Ok(AppMatcher { tracked, excluded })
+    }
+
+    /// Whether visits of the given domain are tracked, all domains are if none are configured
+    pub fn tracks_domain(&self, domain: &str) -> bool {
+        let domain = domain.to_ascii_lowercase();
+        self.url_domains.is_empty()
+            || self.url_domains.iter().any(|tracked| {
+                let tracked = tracked.to_ascii_lowercase();
+                domain == tracked || domain.ends_with(&format!(".{tracked}"))
+            })
+    }
+}
+
+impl TeamConfigurationDiff {
+    pub fn new(from: &TeamConfigurationRevision, to: &TeamConfigurationRevision) -> Self {
+        let from_value = serde_json::to_value(&from.configuration).unwrap();
+        let to_value = serde_json::to_value(&to.configuration).unwrap();
+        let mut changes = Vec::new();
+        if let (Value::Object(from_fields), Value::Object(to_fields)) = (from_value, to_value) {
+            for (field, old) in from_fields {
+                let new = to_fields.get(&field).cloned().unwrap_or(Value::Null);
+                match (old, new) {
+                    (Value::Array(old), Value::Array(new)) => {
+                        for value in old.iter().filter(|value| !new.contains(value)) {
+                            changes.push(ConfigurationChange::Removed {
+                                field: field.clone(),
+                                value: value.clone(),
+                            });
+                        }
+                        for value in new.iter().filter(|value| !old.contains(value)) {
+                            changes.push(ConfigurationChange::Added {
+                                field: field.clone(),
+                                value: value.clone(),
+                            });
+                        }
+                    }
+                    (old, new) if old != new => changes.push(ConfigurationChange::Changed {
+                        field,
+                        from: old,
+                        to: new,
+                    }),
+                    _ => (),
+                }
+            }
+        }
+        TeamConfigurationDiff {
+            from: from.revision,
+            to: to.revision,
+            changes,
+        }
+    }
+}
+
+impl ConfigurationHistory {
+    pub fn new(mut revisions: Vec<TeamConfigurationRevision>) -> Self {
+        revisions.sort_by_key(|revision| (revision.created, revision.revision));
+        ConfigurationHistory {
+            revisions,
+            default: TeamConfiguration::default(),
+        }
+    }
+
+    /// The revision which was in effect at the given time, none before the first one
+    pub fn revision_at(&self, time: DateTime<Utc>) -> Option<&TeamConfigurationRevision> {
+        let index = self
+            .revisions
+            .partition_point(|revision| revision.created <= time);
+        index.checked_sub(1).map(|index| &self.revisions[index])
+    }
+
+    /// The configuration which was in effect at the given time, the default one before the
+    /// first revision
+    pub fn configuration_at(&self, time: DateTime<Utc>) -> &TeamConfiguration {
+        match self.revision_at(time) {
+            Some(revision) => &revision.configuration,
+            None => &self.default,
+        }
+    }
+
+    pub fn revisions(&self) -> &[TeamConfigurationRevision] {
+        &self.revisions
+    }
+}
+
+impl AppMatcher {
+    /// Whether a window of the given app with the given title is tracked
+    pub fn matches(&self, app: &str, title: &str) -> bool {
+        let is_match = |regex: &Regex, text: &str| regex.is_match(text).unwrap_or(false);
+        if self.excluded.iter().any(|regex| is_match(regex, app)) {
+            return false;
+        }
+        self.tracked.iter().any(|(app_regex, title_regex)| {
+            is_match(app_regex, app)
+                && title_regex
+                    .as_ref()
+                    .map_or(true, |regex| is_match(regex, title))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AppRule, ConfigurationChange, ConfigurationHistory, PrivacyLevel, TeamConfiguration,
+        TeamConfigurationDiff, TeamConfigurationRevision, WorkingHours,
+    };
+    use chrono::{DateTime, NaiveTime, Weekday};
+    use serde_json::json;
+
+    #[test]
+    fn test_default_from_empty_json() {
+        let config: TeamConfiguration = serde_json::from_str("{}").unwrap();
+        assert_eq!(config, TeamConfiguration::default());
+        assert!(config.validate().is_ok());
+        assert_eq!(config.privacy_level, PrivacyLevel::RawEvents);
+    }
+
+    #[test]
+    fn test_privacy_level() {
+        let config: TeamConfiguration =
+            serde_json::from_str(r#"{"privacy_level": "category_only"}"#).unwrap();
+        assert_eq!(config.privacy_level, PrivacyLevel::CategoryOnly);
+        assert!(serde_json::from_str::<TeamConfiguration>(r#"{"privacy_level": "none"}"#).is_err());
+        // Stricter levels compare greater
+        assert!(PrivacyLevel::AppOnly > PrivacyLevel::RawEvents);
+        assert!(PrivacyLevel::TotalsOnly > PrivacyLevel::CategoryOnly);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = TeamConfiguration::default();
+        config.tracked_apps.push(AppRule {
+            app: "(unclosed".to_string(),
+            title: None,
+        });
+        assert!(config.validate().is_err());
+
+        let mut config = TeamConfiguration::default();
+        config.version = 2;
+        assert!(config.validate().is_err());
+
+        let mut config = TeamConfiguration::default();
+        config.url_domains.push("https://example.com".to_string());
+        assert!(config.validate().is_err());
+
+        let mut config = TeamConfiguration::default();
+        config.poll_interval = config.afk_timeout + 1;
+        assert!(config.validate().is_err());
+
+        let mut config = TeamConfiguration::default();
+        config.working_hours = Some(WorkingHours {
+            days: vec![Weekday::Mon],
+            start: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_matcher() {
+        let config = TeamConfiguration {
+            tracked_apps: vec![
+                AppRule::exact("Code, Insiders"),
+                AppRule {
+                    app: "(?i)firefox".to_string(),
+                    title: Some("GitHub".to_string()),
+                },
+            ],
+            excluded_apps: vec!["Private".to_string()],
+            ..Default::default()
+        };
+        let matcher = config.matcher().unwrap();
+        assert!(matcher.matches("Code, Insiders", "main.rs"));
+        assert!(!matcher.matches("Code", "main.rs"));
+        assert!(matcher.matches("Firefox", "Pull requests · GitHub"));
+        assert!(!matcher.matches("Firefox", "News"));
+        assert!(!matcher.matches("Firefox Private", "GitHub"));
+
+        let matcher = TeamConfiguration::default().matcher().unwrap();
+        assert!(!matcher.matches("anything", ""));
+    }
+
+    #[test]
+    fn test_tracks_domain() {
+        let config = TeamConfiguration {
+            url_domains: vec!["example.com".to_string()],
+            ..Default::default()
+        };
+        assert!(config.tracks_domain("example.com"));
+        assert!(config.tracks_domain("docs.Example.com"));
+        assert!(!config.tracks_domain("badexample.com"));
+        assert!(!config.tracks_domain("example.org"));
+    }
+
+    fn revision(
+        revision: i32,
+        created: i64,
+        configuration: TeamConfiguration,
+    ) -> TeamConfigurationRevision {
+        TeamConfigurationRevision {
+            revision,
+            author: None,
+            created: DateTime::from_timestamp(created, 0).unwrap(),
+            restored_from: None,
+            configuration,
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = TeamConfiguration {
+            tracked_apps: vec![AppRule::exact("Code"), AppRule::exact("Firefox")],
+            ..Default::default()
+        };
+        let new = TeamConfiguration {
+            tracked_apps: vec![AppRule::exact("Code"), AppRule::exact("Slack")],
+            afk_timeout: 300,
+            ..Default::default()
+        };
+        let diff = TeamConfigurationDiff::new(&revision(1, 0, old), &revision(2, 0, new));
+        assert_eq!(
+            diff.changes,
+            vec![
+                ConfigurationChange::Changed {
+                    field: "afk_timeout".to_string(),
+                    from: json!(180),
+                    to: json!(300),
+                },
+                ConfigurationChange::Removed {
+                    field: "tracked_apps".to_string(),
+                    value: json!({"app": "^Firefox$", "title": null}),
+                },
+                ConfigurationChange::Added {
+                    field: "tracked_apps".to_string(),
+                    value: json!({"app": "^Slack$", "title": null}),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        let second = TeamConfiguration {
+            afk_timeout: 300,
+            ..Default::default()
+        };
+        let history = ConfigurationHistory::new(vec![
+            revision(2, 200, second.clone()),
+            revision(1, 100, TeamConfiguration::default()),
+            revision(3, 200, TeamConfiguration::default()),
+        ]);
+        let at = |time| DateTime::from_timestamp(time, 0).unwrap();
+        assert!(history.revision_at(at(99)).is_none());
+        assert_eq!(
+            history.configuration_at(at(99)),
+            &TeamConfiguration::default()
+        );
+        assert_eq!(history.revision_at(at(100)).unwrap().revision, 1);
+        assert_eq!(history.revision_at(at(199)).unwrap().revision, 1);
+        // Of revisions made in the same second the later one is in effect
+        assert_eq!(history.revision_at(at(200)).unwrap().revision, 3);
+    }
+}
@@ -1,6 +1,10 @@
+use chrono::DateTime;
+use chrono::Utc;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::Role;
+
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub struct User {
     pub id: i32,
@@ -8,15 +12,129 @@ pub struct User {
     pub username: String,
     pub name: String,
     pub lastname: String,
-    pub role: i8,
+    pub role: Role,
     pub password: String,
+    pub must_change_password: bool,
+    pub email_verified: bool,
 }
 #[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 
 pub struct PublicUser {
     pub id: i32,
     pub email: String,
+    pub username: String,
     pub name: String,
     pub lastname: String,
-    pub role: i8,
+    pub role: Role,
+    pub must_change_password: bool,
+    pub two_factor_enabled: bool,
+    /// Deactivated users can not log in until an admin reactivates them
+    pub active: bool,
+    /// Users who have not verified their email address can not join teams and are not shown to
+    /// team owners
+    pub email_verified: bool,
+}
+
+/// The details of an account a user can change themselves
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct UserProfile {
+    pub name: String,
+    pub lastname: String,
+    pub username: String,
+    pub email: String,
+}
+
+/// Column the user directory is sorted by
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UserSort {
+    #[default]
+    Id,
+    Name,
+    Lastname,
+    Username,
+    Email,
+    Role,
+}
+
+/// Search, filters and page of a query of the user directory
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct UserFilter {
+    /// Matched against name, lastname, email and username
+    pub search: Option<String>,
+    pub role: Option<Role>,
+    /// Members and owner of the team
+    pub team_id: Option<i32>,
+    pub active: Option<bool>,
+    pub sort: UserSort,
+    pub descending: bool,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+/// A page of the user directory
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct UserPage {
+    pub users: Vec<PublicUser>,
+    /// Number of users matching the filters on all pages
+    pub total: i64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct RefreshToken {
+    pub id: i64,
+    pub user_id: i32,
+    pub expires: DateTime<Utc>,
+}
+
+/// What a one-time token sent to a user can be used for
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenPurpose {
+    PasswordReset,
+    /// Issued by the login after checking the password, when the second factor is still needed
+    TwoFactorChallenge,
+    EmailVerification,
+}
+
+impl TokenPurpose {
+    /// The name the purpose is stored as in the database
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TokenPurpose::PasswordReset => "password_reset",
+            TokenPurpose::TwoFactorChallenge => "two_factor_challenge",
+            TokenPurpose::EmailVerification => "email_verification",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct OneTimeToken {
+    pub id: i64,
+    pub user_id: i32,
+    pub purpose: TokenPurpose,
+    pub expires: DateTime<Utc>,
+}
+
+/// Failed login attempts for an account or a client address
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct LoginAttempts {
+    /// `account:<email>` or `ip:<address>`
+    pub subject: String,
+    pub failures: i32,
+    pub last_failure: DateTime<Utc>,
+    /// No login attempts are accepted for the subject until then
+    pub locked_until: DateTime<Utc>,
+}
+
+/// TOTP second factor of a user
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct TwoFactor {
+    pub user_id: i32,
+    /// Base32 encoded shared secret
+    pub secret: String,
+    /// Unset while the enrollment has not been confirmed with a valid code
+    pub enabled: bool,
+    /// The last time step a code was accepted for, codes can not be used twice
+    pub last_step: i64,
 }
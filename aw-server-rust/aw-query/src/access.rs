@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use aw_datastore::Datastore;
+use aw_models::{Permission, Role};
+
+use crate::DataType;
+use crate::QueryError;
+use crate::VarEnv;
+
+/// Name of the caller in the query environment
+///
+/// Not a valid identifier, so queries can neither read nor reassign it.
+const CALLER_VAR: &str = "$CALLER";
+
+/// The user a query is run for
+///
+/// Queries run with a caller can only read the data of users and teams the caller is
+/// authorized to see, with the same rules as the HTTP endpoints.
+#[derive(Clone, Copy, Debug)]
+pub struct QueryCaller {
+    pub user_id: i32,
+    pub role: Role,
+}
+
+impl QueryCaller {
+    pub(crate) fn insert_into(&self, env: &mut VarEnv) {
+        let mut caller = HashMap::new();
+        caller.insert("user_id".to_string(), DataType::Number(self.user_id.into()));
+        caller.insert("role".to_string(), DataType::Number(self.role.id().into()));
+        env.insert(CALLER_VAR.to_string(), DataType::Dict(caller));
+    }
+
+    /// The caller of the query, `None` for queries run without one which have full access
+    pub(crate) fn from_env(env: &VarEnv) -> Result<Option<QueryCaller>, QueryError> {
+        let caller = match env.get(CALLER_VAR) {
+            Some(DataType::Dict(caller)) => caller,
+            Some(_) => {
+                return Err(QueryError::InvalidType(
+                    "Query caller is not of type dict!".to_string(),
+                ))
+            }
+            None => return Ok(None),
+        };
+        let user_id: i32 = caller
+            .get("user_id")
+            .ok_or_else(|| QueryError::VariableNotDefined("user_id".to_string()))?
+            .try_into()?;
+        let role_id: f64 = caller
+            .get("role")
+            .ok_or_else(|| QueryError::VariableNotDefined("role".to_string()))?
+            .try_into()?;
+        match Role::from_id(role_id as i8) {
+            Some(role) => Ok(Some(QueryCaller { user_id, role })),
+            None => Err(QueryError::InvalidType(format!(
+                "Query caller has unknown role {role_id}"
+            ))),
+        }
+    }
+
+    /// Checks a permission within a team, granted by a global role or by the role in the team
+    pub fn require_team(
+        &self,
+        ds: &Datastore,
+        team_id: i32,
+        permission: Permission,
+    ) -> Result<(), QueryError> {
+        if self.role.is_global() && self.role.has_permission(permission) {
+            return Ok(());
+        }
+        match ds.get_team_role(team_id, self.user_id) {
+            Ok(Some(role)) if role.has_permission(permission) => Ok(()),
+            Ok(_) => Err(access_denied(&format!("team {team_id}"), permission)),
+            Err(e) => Err(QueryError::BucketQueryError(format!(
+                "Failed to query team role: {e:?}"
+            ))),
+        }
+    }
+
+    /// Checks a permission on the data of `user_id`
+    ///
+    /// Users always have access to their own data, access to the data of others is granted by a
+    /// global role or by the role in a team the other user is a member of.
+    pub fn require_user(
+        &self,
+        ds: &Datastore,
+        user_id: i32,
+        permission: Permission,
+    ) -> Result<(), QueryError> {
+        if self.user_id == user_id {
+            return Ok(());
+        }
+        if self.role.is_global() && self.role.has_permission(permission) {
+            return Ok(());
+        }
+        match ds.get_shared_team_roles(self.user_id, user_id) {
+            Ok(roles) if roles.iter().any(|role| role.has_permission(permission)) => Ok(()),
+            Ok(_) => Err(access_denied(&format!("user {user_id}"), permission)),
+            Err(e) => Err(QueryError::BucketQueryError(format!(
+                "Failed to query team roles: {e:?}"
+            ))),
+        }
+    }
+
+    /// The users whose data the caller has `permission` on, the same users as
+    /// [`QueryCaller::require_user`] allows, `None` if the caller has it on the data of all users
+    pub fn permitted_users(
+        &self,
+        ds: &Datastore,
+        permission: Permission,
+    ) -> Result<Option<Vec<i32>>, QueryError> {
+        if self.role.is_global() && self.role.has_permission(permission) {
+            return Ok(None);
+        }
+        let team_ids = match (
+            ds.get_owner_teams(self.user_id),
+            ds.get_user_teams(self.user_id),
+        ) {
+            (Ok(owned), Ok(joined)) => owned
+                .iter()
+                .map(|team| team.id)
+                .chain(joined.iter().map(|team| team.id))
+                .collect::<Vec<i32>>(),
+            (Err(e), _) | (_, Err(e)) => {
+                return Err(QueryError::BucketQueryError(format!(
+                    "Failed to query teams: {e:?}"
+                )))
+            }
+        };
+        let mut user_ids = vec![self.user_id];
+        for team_id in team_ids {
+            match self.require_team(ds, team_id, permission) {
+                Ok(()) => (),
+                Err(QueryError::AccessDenied(_)) => continue,
+                Err(e) => return Err(e),
+            }
+            match ds.get_team_members(team_id) {
+                Ok(members) => user_ids.extend(members.iter().map(|member| member.user_id)),
+                Err(e) => {
+                    return Err(QueryError::BucketQueryError(format!(
+                        "Failed to query team members: {e:?}"
+                    )))
+                }
+            }
+        }
+        user_ids.sort_unstable();
+        user_ids.dedup();
+        Ok(Some(user_ids))
+    }
+}
+
+fn access_denied(resource: &str, permission: Permission) -> QueryError {
+    QueryError::AccessDenied(format!("Missing permission: {permission:?} for {resource}"))
+}
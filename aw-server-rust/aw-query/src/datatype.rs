@@ -234,6 +234,14 @@ impl TryFrom<&DataType> for usize {
     }
 }
 
+impl TryFrom<&DataType> for i32 {
+    type Error = QueryError;
+    fn try_from(value: &DataType) -> Result<Self, Self::Error> {
+        let f: f64 = value.try_into()?;
+        Ok(f as i32)
+    }
+}
+
 impl TryFrom<&DataType> for Value {
     type Error = QueryError;
     fn try_from(value: &DataType) -> Result<Self, Self::Error> {
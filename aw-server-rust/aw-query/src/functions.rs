@@ -22,6 +22,28 @@ pub fn fill_env(env: &mut VarEnv) {
             qfunctions::query_bucket_names,
         ),
     );
+    env.insert(
+        "team_members".to_string(),
+        DataType::Function("team_members".to_string(), qfunctions::team_members),
+    );
+    env.insert(
+        "query_user_bucket".to_string(),
+        DataType::Function(
+            "query_user_bucket".to_string(),
+            qfunctions::query_user_bucket,
+        ),
+    );
+    env.insert(
+        "query_team_buckets".to_string(),
+        DataType::Function(
+            "query_team_buckets".to_string(),
+            qfunctions::query_team_buckets,
+        ),
+    );
+    env.insert(
+        "working_hours".to_string(),
+        DataType::Function("working_hours".to_string(), qfunctions::working_hours),
+    );
     env.insert(
         "sort_by_duration".to_string(),
         DataType::Function("sort_by_duration".to_string(), qfunctions::sort_by_duration),
@@ -116,11 +138,15 @@ pub fn fill_env(env: &mut VarEnv) {
 }
 
 mod qfunctions {
+    use std::collections::HashMap;
+
     use aw_datastore::Datastore;
-    use aw_models::Event;
+    use aw_models::{Bucket, Event, Permission, Role, TimeInterval, WorkSchedule};
     use aw_transform::classify::Rule;
 
     use super::validate;
+    use crate::access::QueryCaller;
+    use crate::privacy::PrivacyFilter;
     use crate::DataType;
     use crate::QueryError;
     use crate::VarEnv;
@@ -146,9 +172,28 @@ mod qfunctions {
 
         let bucket_id: String = (&args[0]).try_into()?;
         let interval = validate::get_timeinterval(env)?;
+        let bucket_id: i64 = match bucket_id.parse() {
+            Ok(bucket_id) => bucket_id,
+            Err(_) => {
+                return Err(QueryError::InvalidFunctionParameters(format!(
+                    "Invalid bucket id '{bucket_id}'"
+                )))
+            }
+        };
+        let bucket = match ds.get_bucket(bucket_id) {
+            Ok(bucket) => bucket,
+            Err(e) => {
+                return Err(QueryError::BucketQueryError(format!(
+                    "Failed to query bucket: {e:?}"
+                )))
+            }
+        };
+        if let Some(caller) = QueryCaller::from_env(env)? {
+            caller.require_user(ds, bucket.user_id, Permission::ReadUserData)?;
+        }
 
         let events = match ds.get_events(
-            bucket_id.parse().unwrap(),
+            bucket_id,
             Some(*interval.start()),
             Some(*interval.end()),
             None,
@@ -160,6 +205,7 @@ mod qfunctions {
                 )))
             }
         };
+        let events = restrict_events(env, ds, bucket.user_id, events)?;
         let mut ret = Vec::new();
         for event in events {
             ret.push(DataType::Event(event));
@@ -169,19 +215,12 @@ mod qfunctions {
 
     pub fn query_bucket_names(
         args: Vec<DataType>,
-        _env: &VarEnv,
+        env: &VarEnv,
         ds: &Datastore,
     ) -> Result<DataType, QueryError> {
         validate::args_length(&args, 0)?;
         let mut bucketnames: Vec<DataType> = Vec::new();
-        let buckets = match ds.get_buckets(1) {
-            Ok(buckets) => buckets,
-            Err(e) => {
-                return Err(QueryError::BucketQueryError(format!(
-                    "Failed to query bucket names: {e:?}"
-                )))
-            }
-        };
+        let buckets = permitted_buckets(env, ds)?;
         for bucketname in buckets.keys() {
             bucketnames.push(DataType::String(bucketname.to_string()));
         }
@@ -190,7 +229,7 @@ mod qfunctions {
 
     pub fn find_bucket(
         args: Vec<DataType>,
-        _env: &VarEnv,
+        env: &VarEnv,
         ds: &Datastore,
     ) -> Result<DataType, QueryError> {
         validate::args_length(&args, 1).or_else(|_| validate::args_length(&args, 2))?;
@@ -201,14 +240,7 @@ mod qfunctions {
             _ => None,
         };
 
-        let buckets = match ds.get_buckets(1) {
-            Ok(buckets) => buckets,
-            Err(e) => {
-                return Err(QueryError::BucketQueryError(format!(
-                    "Failed to query bucket names: {e:?}"
-                )))
-            }
-        };
+        let buckets = permitted_buckets(env, ds)?;
         let bucketname = match aw_transform::find_bucket(
             &bucket_filter,
             &hostname_filter,
@@ -229,6 +261,266 @@ mod qfunctions {
         Ok(DataType::String(bucketname))
     }
 
+    /// The members of a team as dicts with `user_id`, `name`, `lastname` and `role`
+    pub fn team_members(
+        args: Vec<DataType>,
+        env: &VarEnv,
+        ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        validate::args_length(&args, 1)?;
+        let team_id: i32 = (&args[0]).try_into()?;
+        if let Some(caller) = QueryCaller::from_env(env)? {
+            caller.require_team(ds, team_id, Permission::ViewTeam)?;
+        }
+
+        let members = match ds.get_team_members(team_id) {
+            Ok(members) => members,
+            Err(e) => {
+                return Err(QueryError::BucketQueryError(format!(
+                    "Failed to query team members: {e:?}"
+                )))
+            }
+        };
+        let mut ret = Vec::new();
+        for member in members {
+            let mut dict = HashMap::new();
+            dict.insert(
+                "user_id".to_string(),
+                DataType::Number(member.user_id.into()),
+            );
+            dict.insert("name".to_string(), DataType::String(member.name));
+            dict.insert("lastname".to_string(), DataType::String(member.lastname));
+            let role = serde_json::to_value(member.role).unwrap();
+            dict.insert(
+                "role".to_string(),
+                DataType::String(role.as_str().unwrap().to_string()),
+            );
+            ret.push(DataType::Dict(dict));
+        }
+        Ok(DataType::List(ret))
+    }
+
+    /// The events of all buckets of a type of a user, such as `currentwindow`
+    ///
+    /// Events of other users are reduced to the privacy levels of their teams.
+    pub fn query_user_bucket(
+        args: Vec<DataType>,
+        env: &VarEnv,
+        ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        validate::args_length(&args, 2)?;
+        let user_id: i32 = (&args[0]).try_into()?;
+        let bucket_type: String = (&args[1]).try_into()?;
+        let interval = validate::get_timeinterval(env)?;
+        if let Some(caller) = QueryCaller::from_env(env)? {
+            caller.require_user(ds, user_id, Permission::ReadUserData)?;
+        }
+
+        let events = user_bucket_events(ds, user_id, &bucket_type, &interval, None)?;
+        let events = restrict_events(env, ds, user_id, events)?;
+        Ok(DataType::List(
+            events.into_iter().map(DataType::Event).collect(),
+        ))
+    }
+
+    /// The events of the buckets of a type of all members of a team
+    ///
+    /// Only events recorded for the team are included, the id of the member is added to the data
+    /// of each event as `user_id`. Viewers are observers of the team and not included. Events of
+    /// other users are reduced to the privacy level of the team.
+    pub fn query_team_buckets(
+        args: Vec<DataType>,
+        env: &VarEnv,
+        ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        validate::args_length(&args, 2)?;
+        let team_id: i32 = (&args[0]).try_into()?;
+        let bucket_type: String = (&args[1]).try_into()?;
+        let interval = validate::get_timeinterval(env)?;
+        if let Some(caller) = QueryCaller::from_env(env)? {
+            caller.require_team(ds, team_id, Permission::ReadUserData)?;
+        }
+
+        let members = match ds.get_team_members(team_id) {
+            Ok(members) => members,
+            Err(e) => {
+                return Err(QueryError::BucketQueryError(format!(
+                    "Failed to query team members: {e:?}"
+                )))
+            }
+        };
+        let mut events = Vec::new();
+        for member in members.iter().filter(|m| m.role != Role::Viewer) {
+            let member_events =
+                user_bucket_events(ds, member.user_id, &bucket_type, &interval, Some(team_id))?;
+            let member_events = restrict_events(env, ds, member.user_id, member_events)?;
+            for mut event in member_events {
+                event
+                    .data
+                    .insert("user_id".to_string(), member.user_id.into());
+                events.push(event);
+            }
+        }
+        let events = aw_transform::sort_by_timestamp(events);
+        Ok(DataType::List(
+            events.into_iter().map(DataType::Event).collect(),
+        ))
+    }
+
+    /// The working hours of a team within the time interval as events, for use with
+    /// `filter_period_intersect`
+    ///
+    /// With a user id the schedule of that member is used, which may differ from the team.
+    pub fn working_hours(
+        args: Vec<DataType>,
+        env: &VarEnv,
+        ds: &Datastore,
+    ) -> Result<DataType, QueryError> {
+        validate::args_length(&args, 1).or_else(|_| validate::args_length(&args, 2))?;
+        let team_id: i32 = (&args[0]).try_into()?;
+        let user_id: Option<i32> = match args.get(1) {
+            Some(arg) => Some(arg.try_into()?),
+            None => None,
+        };
+        let interval = validate::get_timeinterval(env)?;
+        if let Some(caller) = QueryCaller::from_env(env)? {
+            caller.require_team(ds, team_id, Permission::ViewTeam)?;
+        }
+
+        let configuration = match ds.get_configuration(team_id) {
+            Ok(configuration) => configuration,
+            Err(e) => {
+                return Err(QueryError::BucketQueryError(format!(
+                    "Failed to query team configuration: {e:?}"
+                )))
+            }
+        };
+        let member_schedule = match user_id {
+            Some(user_id) => {
+                let members = match ds.get_team_members(team_id) {
+                    Ok(members) => members,
+                    Err(e) => {
+                        return Err(QueryError::BucketQueryError(format!(
+                            "Failed to query team members: {e:?}"
+                        )))
+                    }
+                };
+                match members.into_iter().find(|m| m.user_id == user_id) {
+                    Some(member) => member.schedule,
+                    None => {
+                        return Err(QueryError::InvalidFunctionParameters(format!(
+                            "User {user_id} is not a member of team {team_id}"
+                        )))
+                    }
+                }
+            }
+            None => None,
+        };
+        let schedule = WorkSchedule::new(&configuration, member_schedule.as_ref())
+            .map_err(QueryError::InvalidFunctionParameters)?;
+
+        let mut ret = Vec::new();
+        for (start, end) in schedule.working_periods(*interval.start(), *interval.end()) {
+            ret.push(DataType::Event(Event::new(
+                start,
+                end - start,
+                Default::default(),
+                team_id,
+            )));
+        }
+        Ok(DataType::List(ret))
+    }
+
+    fn user_bucket_events(
+        ds: &Datastore,
+        user_id: i32,
+        bucket_type: &str,
+        interval: &TimeInterval,
+        team_id: Option<i32>,
+    ) -> Result<Vec<Event>, QueryError> {
+        let buckets = match ds.get_buckets(user_id) {
+            Ok(buckets) => buckets,
+            Err(e) => {
+                return Err(QueryError::BucketQueryError(format!(
+                    "Failed to query buckets of user {user_id}: {e:?}"
+                )))
+            }
+        };
+        let mut events = Vec::new();
+        for bucket in buckets.values().filter(|b| b._type == bucket_type) {
+            let start = Some(*interval.start());
+            let end = Some(*interval.end());
+            let bucket_events = match team_id {
+                Some(team_id) => ds.get_user_events(bucket.bid, start, end, None, Some(team_id)),
+                None => ds.get_events(bucket.bid, start, end, None),
+            };
+            match bucket_events {
+                Ok(mut bucket_events) => events.append(&mut bucket_events),
+                Err(e) => {
+                    return Err(QueryError::BucketQueryError(format!(
+                        "Failed to query bucket {}: {e:?}",
+                        bucket.bid
+                    )))
+                }
+            }
+        }
+        Ok(aw_transform::sort_by_timestamp(events))
+    }
+
+    /// Reduces the events of a user to the privacy levels of their teams, unless the caller of the
+    /// query may see all of them
+    fn restrict_events(
+        env: &VarEnv,
+        ds: &Datastore,
+        user_id: i32,
+        events: Vec<Event>,
+    ) -> Result<Vec<Event>, QueryError> {
+        let caller = match QueryCaller::from_env(env)? {
+            Some(caller) => caller,
+            None => return Ok(events),
+        };
+        match PrivacyFilter::new(ds, &caller, user_id) {
+            Ok(Some(filter)) => Ok(filter.apply(events)),
+            Ok(None) => Ok(events),
+            Err(e) => Err(QueryError::BucketQueryError(format!(
+                "Failed to query privacy levels: {e:?}"
+            ))),
+        }
+    }
+
+    /// The buckets the caller of the query may read, keyed by bucket id, or all buckets for
+    /// queries without a caller
+    fn permitted_buckets(
+        env: &VarEnv,
+        ds: &Datastore,
+    ) -> Result<HashMap<String, Bucket>, QueryError> {
+        let user_ids = match QueryCaller::from_env(env)? {
+            Some(caller) => caller.permitted_users(ds, Permission::ReadUserData)?,
+            // Queries run without a caller have full access
+            None => None,
+        };
+        let user_ids = match user_ids {
+            Some(user_ids) => user_ids,
+            None => {
+                return ds.get_all_buckets().map_err(|e| {
+                    QueryError::BucketQueryError(format!("Failed to query bucket names: {e:?}"))
+                })
+            }
+        };
+        let mut buckets = HashMap::new();
+        for user_id in user_ids {
+            match ds.get_buckets(user_id) {
+                Ok(user_buckets) => buckets.extend(user_buckets),
+                Err(e) => {
+                    return Err(QueryError::BucketQueryError(format!(
+                        "Failed to query bucket names: {e:?}"
+                    )))
+                }
+            }
+        }
+        Ok(buckets)
+    }
+
     pub fn contains(
         args: Vec<DataType>,
         _env: &VarEnv,
@@ -5,15 +5,19 @@ use crate::functions;
 use aw_datastore::Datastore;
 use aw_models::TimeInterval;
 
+use crate::access::QueryCaller;
 use crate::ast::*;
 use crate::DataType;
 use crate::QueryError;
 
 pub type VarEnv = HashMap<String, DataType>;
 
-fn init_env(ti: &TimeInterval) -> VarEnv {
+fn init_env(ti: &TimeInterval, caller: Option<QueryCaller>) -> VarEnv {
     let mut env = HashMap::new();
     env.insert("TIMEINTERVAL".to_string(), DataType::String(ti.to_string()));
+    if let Some(caller) = caller {
+        caller.insert_into(&mut env);
+    }
     functions::fill_env(&mut env);
     env
 }
@@ -22,8 +26,9 @@ pub fn interpret_prog(
     p: Program,
     ti: &TimeInterval,
     ds: &Datastore,
+    caller: Option<QueryCaller>,
 ) -> Result<DataType, QueryError> {
-    let mut env = init_env(ti);
+    let mut env = init_env(ti, caller);
     for expr in p.stmts {
         interpret_expr(&mut env, ds, expr)?;
     }
@@ -11,6 +11,7 @@ use aw_datastore::Datastore;
 
 pub mod datatype;
 
+mod access;
 mod ast;
 mod functions;
 mod interpret;
@@ -21,9 +22,12 @@ mod lexer;
     unused_braces
 )]
 mod parser;
+mod privacy;
 
+pub use crate::access::QueryCaller;
 pub use crate::datatype::DataType;
 pub use crate::interpret::VarEnv;
+pub use crate::privacy::{load_categories, PrivacyFilter};
 
 // TODO: add line numbers to errors
 // (works during lexing, but not during parsing I believe)
@@ -42,6 +46,7 @@ pub enum QueryError {
     TimeIntervalError(String),
     BucketQueryError(String),
     RegexCompileError(String),
+    AccessDenied(String),
 }
 
 impl fmt::Display for QueryError {
@@ -50,7 +55,27 @@ impl fmt::Display for QueryError {
     }
 }
 
+/// Runs a query with full access to the datastore, including the buckets of all users
 pub fn query(code: &str, ti: &TimeInterval, ds: &Datastore) -> Result<DataType, QueryError> {
+    run(code, ti, ds, None)
+}
+
+/// Runs a query on behalf of a user, limiting it to the data the user is authorized to see
+pub fn query_as(
+    code: &str,
+    ti: &TimeInterval,
+    ds: &Datastore,
+    caller: QueryCaller,
+) -> Result<DataType, QueryError> {
+    run(code, ti, ds, Some(caller))
+}
+
+fn run(
+    code: &str,
+    ti: &TimeInterval,
+    ds: &Datastore,
+    caller: Option<QueryCaller>,
+) -> Result<DataType, QueryError> {
     let lexer = lexer::Lexer::new(code);
     let program = match parser::parse(lexer) {
         Ok(p) => p,
@@ -60,5 +85,5 @@ pub fn query(code: &str, ti: &TimeInterval, ds: &Datastore) -> Result<DataType,
             return Err(QueryError::ParsingError(format!("{e:?}")));
         }
     };
-    interpret::interpret_prog(program, ti, ds)
+    interpret::interpret_prog(program, ti, ds, caller)
 }
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use aw_datastore::{Datastore, DatastoreError};
+use aw_models::{Event, Permission, PrivacyLevel};
+use aw_transform::classify::{self, RegexRule, Rule};
+
+use crate::QueryCaller;
+
+// The categories of the web UI, which it stores in the settings
+const CATEGORIES_KEY: &str = "settings.classes";
+
+/// Keys of the event data which are kept at [`PrivacyLevel::AppOnly`]
+const APP_KEYS: [&str; 2] = ["app", "status"];
+/// Keys of the event data which are kept at [`PrivacyLevel::CategoryOnly`], after categorizing
+const CATEGORY_KEYS: [&str; 2] = ["$category", "status"];
+/// Keys of the event data which are kept at [`PrivacyLevel::TotalsOnly`], the AFK status is needed
+/// to tell the active time
+const TOTALS_KEYS: [&str; 1] = ["status"];
+
+#[derive(Deserialize)]
+struct CategorySetting {
+    name: Vec<String>,
+    rule: RuleSetting,
+}
+
+#[derive(Deserialize)]
+struct RuleSetting {
+    #[serde(rename = "type")]
+    rule_type: String,
+    regex: Option<String>,
+    #[serde(default)]
+    ignore_case: bool,
+}
+
+/// The category rules from the settings, invalid rules are skipped
+pub fn load_categories(ds: &Datastore) -> Result<Vec<(Vec<String>, Rule)>, DatastoreError> {
+    let value = match ds.get_key_value(CATEGORIES_KEY) {
+        Ok(value) => value,
+        Err(DatastoreError::NoSuchKey(_)) => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let categories: Vec<CategorySetting> = match serde_json::from_str(&value) {
+        Ok(categories) => categories,
+        Err(err) => {
+            warn!("Ignoring the categories in the settings, they are invalid: {err}");
+            return Ok(Vec::new());
+        }
+    };
+    let mut rules = Vec::new();
+    for category in categories {
+        let regex = match (category.rule.rule_type.as_str(), category.rule.regex) {
+            ("regex", Some(regex)) => regex,
+            _ => continue,
+        };
+        match RegexRule::new(&regex, category.rule.ignore_case) {
+            Ok(rule) => rules.push((category.name, Rule::Regex(rule))),
+            Err(err) => warn!("Ignoring category {:?}: {err}", category.name),
+        }
+    }
+    Ok(rules)
+}
+
+/// Reduces the events of a user to what another user may see of them
+///
+/// Events are recorded for a team and reduced to the privacy level of that team. Events of teams
+/// in which the reader can't read the data of members, or of no team, are reduced to totals.
+pub struct PrivacyFilter {
+    levels: HashMap<i32, PrivacyLevel>,
+    categories: Vec<(Vec<String>, Rule)>,
+}
+
+impl PrivacyFilter {
+    /// The filter for `caller` reading the data of `user_id`, `None` if the caller may see all of
+    /// it
+    ///
+    /// Users see all of their own data, as do users with a global role which can read the data of
+    /// all users.
+    pub fn new(
+        ds: &Datastore,
+        caller: &QueryCaller,
+        user_id: i32,
+    ) -> Result<Option<PrivacyFilter>, DatastoreError> {
+        if caller.user_id == user_id
+            || (caller.role.is_global() && caller.role.has_permission(Permission::ReadUserData))
+        {
+            return Ok(None);
+        }
+        let team_ids: Vec<i32> = ds
+            .get_owner_teams(user_id)?
+            .iter()
+            .map(|team| team.id)
+            .chain(ds.get_user_teams(user_id)?.iter().map(|team| team.id))
+            .collect();
+        let mut levels = HashMap::new();
+        for team_id in team_ids {
+            match ds.get_team_role(team_id, caller.user_id)? {
+                Some(role) if role.has_permission(Permission::ReadUserData) => {
+                    levels.insert(team_id, ds.get_configuration(team_id)?.privacy_level);
+                }
+                _ => (),
+            }
+        }
+        let categories = if levels.values().any(|l| *l == PrivacyLevel::CategoryOnly) {
+            load_categories(ds)?
+        } else {
+            Vec::new()
+        };
+        Ok(Some(PrivacyFilter { levels, categories }))
+    }
+
+    /// The level the events of a team are reduced to
+    pub fn level(&self, team_id: i32) -> PrivacyLevel {
+        self.levels
+            .get(&team_id)
+            .copied()
+            .unwrap_or(PrivacyLevel::TotalsOnly)
+    }
+
+    /// Reduces the data of an event to its privacy level
+    pub fn apply_event(&self, mut event: Event) -> Event {
+        let keys: &[&str] = match self.level(event.team_id) {
+            PrivacyLevel::RawEvents => return event,
+            PrivacyLevel::AppOnly => &APP_KEYS,
+            PrivacyLevel::CategoryOnly => {
+                event = classify::categorize(vec![event], &self.categories)
+                    .pop()
+                    .unwrap();
+                &CATEGORY_KEYS
+            }
+            PrivacyLevel::TotalsOnly => &TOTALS_KEYS,
+        };
+        let data: Map<String, Value> = keys
+            .iter()
+            .filter_map(|key| Some((key.to_string(), event.data.remove(*key)?)))
+            .collect();
+        event.data = data;
+        event
+    }
+
+    /// Reduces events to their privacy level and merges the reduced events which have the same
+    /// data into one
+    ///
+    /// Events at [`PrivacyLevel::RawEvents`] are kept as they are, merged events start at the
+    /// earliest of their events and last as long as all of them together.
+    pub fn apply(&self, events: Vec<Event>) -> Vec<Event> {
+        let mut result: Vec<Event> = Vec::new();
+        let mut merged: HashMap<(i32, String), usize> = HashMap::new();
+        for event in events {
+            if self.level(event.team_id) == PrivacyLevel::RawEvents {
+                result.push(event);
+                continue;
+            }
+            let mut event = self.apply_event(event);
+            let key = (event.team_id, Value::Object(event.data.clone()).to_string());
+            match merged.get(&key) {
+                Some(index) => {
+                    let aggregate = &mut result[*index];
+                    aggregate.duration = aggregate.duration + event.duration;
+                    aggregate.timestamp = aggregate.timestamp.min(event.timestamp);
+                }
+                None => {
+                    // Merged events are no longer any of the stored events
+                    event.id = None;
+                    merged.insert(key, result.len());
+                    result.push(event);
+                }
+            }
+        }
+        result
+    }
+}
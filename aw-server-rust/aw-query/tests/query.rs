@@ -26,6 +26,8 @@ mod query_tests {
     use std::convert::TryFrom;
 
     use aw_query::DataType;
+    use aw_query::PrivacyFilter;
+    use aw_query::QueryCaller;
     use aw_query::QueryError;
 
     use aw_datastore::Datastore;
@@ -33,10 +35,12 @@ mod query_tests {
     use aw_models::Bucket;
     use aw_models::BucketMetadata;
     use aw_models::Event;
+    use aw_models::Role;
     use aw_models::TimeInterval;
 
     static TIME_INTERVAL: &str = "1980-01-01T00:00:00Z/2080-01-02T00:00:00Z";
-    static BUCKET_ID: &str = "testid";
+    // Buckets are numbered from 1, the test bucket is the first one
+    static BUCKET_ID: i64 = 1;
 
     fn setup_datastore_empty() -> Datastore {
         Datastore::new_in_memory(false)
@@ -46,18 +50,17 @@ mod query_tests {
         let ds = setup_datastore_empty();
         // Create bucket
         let bucket = Bucket {
-            bid: None,
-            id: BUCKET_ID.to_string(),
+            bid: 0,
             _type: "testtype".to_string(),
-            client: "testclient".to_string(),
-            hostname: "testhost".to_string(),
             created: Some(chrono::Utc::now()),
             data: json_map! {},
             metadata: BucketMetadata::default(),
             events: None,
             last_updated: None,
+            // The seeded admin
+            user_id: 1,
         };
-        ds.create_bucket(&bucket).unwrap();
+        assert_eq!(ds.create_bucket(&bucket).unwrap(), BUCKET_ID);
         ds
     }
 
@@ -69,6 +72,7 @@ mod query_tests {
             timestamp: chrono::Utc::now(),
             duration: Duration::seconds(0),
             data: json_map! {"key": json!("value")},
+            team_id: 0,
         };
         let mut e2 = e1.clone();
         e2.timestamp = chrono::Utc::now();
@@ -340,12 +344,12 @@ mod query_tests {
         let ds = setup_datastore_populated();
         let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
 
-        let code = String::from("return query_bucket(\"testid\");");
+        let code = format!("return query_bucket(\"{BUCKET_ID}\");");
         aw_query::query(&code, &interval, &ds).unwrap();
 
         let code = format!(
             r#"
-            events = query_bucket(find_bucket("{}", "testhost"));
+            events = query_bucket("{}");
             events = flood(events);
             events = sort_by_duration(events);
             events = limit_events(events, 10000);
@@ -363,7 +367,7 @@ mod query_tests {
             chunked_events = chunk_events_by_key(events, "key");
             merged_events = merge_events_by_keys(events, ["key"]);
             return  merged_events;"#,
-            "testid", "testid"
+            BUCKET_ID, BUCKET_ID
         );
         match aw_query::query(&code, &interval, &ds).unwrap() {
             aw_query::DataType::List(l) => l,
@@ -382,7 +386,7 @@ mod query_tests {
             events = query_bucket("{}");
             events = categorize(events, [[["Test", "Subtest"], {{ "type": "regex", "regex": "^value$" }}]]);
             return  events;"#,
-            "testid"
+            BUCKET_ID
         );
         let result: DataType = aw_query::query(&code, &interval, &ds).unwrap();
         let events: Vec<Event> = Vec::try_from(&result).unwrap();
@@ -402,7 +406,7 @@ mod query_tests {
             events = query_bucket("{}");
             events = tag(events, [["testtag", {{ "type": "regex", "regex": "value$" }}], ["another testtag", {{ "type": "regex", "regex": "value$" }}]]);
             return  events;"#,
-            "testid"
+            BUCKET_ID
         );
         let result: DataType = aw_query::query(&code, &interval, &ds).unwrap();
         let events: Vec<Event> = Vec::try_from(&result).unwrap();
@@ -649,4 +653,143 @@ mod query_tests {
             num => panic!("Expected number, got {num:?}"),
         };
     }
+
+    #[test]
+    fn test_team_functions() {
+        let ds = setup_datastore_empty();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+        let caller = QueryCaller {
+            user_id: 5,
+            role: Role::Member,
+        };
+
+        // Users can always query their own buckets
+        let code = String::from("return query_user_bucket(5, \"currentwindow\");");
+        match aw_query::query_as(&code, &interval, &ds, caller).unwrap() {
+            DataType::List(l) => assert!(l.is_empty()),
+            data => panic!("Expected list, got {data:?}"),
+        };
+
+        let code = String::from("return query_user_bucket(6, \"currentwindow\");");
+        let res = aw_query::query_as(&code, &interval, &ds, caller);
+        assert_err_type!(res, QueryError::AccessDenied(_));
+
+        let code = String::from("return team_members(1);");
+        let res = aw_query::query_as(&code, &interval, &ds, caller);
+        assert_err_type!(res, QueryError::AccessDenied(_));
+
+        let code = String::from("return query_team_buckets(1, \"currentwindow\");");
+        let res = aw_query::query_as(&code, &interval, &ds, caller);
+        assert_err_type!(res, QueryError::AccessDenied(_));
+    }
+
+    #[test]
+    fn test_bucket_access() {
+        let ds = setup_datastore_empty();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+        let caller = QueryCaller {
+            user_id: 5,
+            role: Role::Member,
+        };
+
+        let code = String::from("return query_bucket_names();");
+        match aw_query::query_as(&code, &interval, &ds, caller).unwrap() {
+            DataType::List(l) => assert!(l.is_empty()),
+            data => panic!("Expected list, got {data:?}"),
+        };
+
+        let code = String::from("return query_bucket(\"invalid\");");
+        let res = aw_query::query_as(&code, &interval, &ds, caller);
+        assert_err_type!(res, QueryError::InvalidFunctionParameters(_));
+    }
+
+    #[test]
+    fn test_bucket_access_without_caller() {
+        let ds = setup_datastore_populated();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+        let member = QueryCaller {
+            user_id: 5,
+            role: Role::Member,
+        };
+
+        // Queries without a caller have full access
+        let code = String::from("return query_bucket_names();");
+        match aw_query::query(&code, &interval, &ds).unwrap() {
+            DataType::List(l) => assert_eq!(l, vec![DataType::String(BUCKET_ID.to_string())]),
+            data => panic!("Expected list, got {data:?}"),
+        };
+        match aw_query::query_as(&code, &interval, &ds, member).unwrap() {
+            DataType::List(l) => assert!(l.is_empty()),
+            data => panic!("Expected list, got {data:?}"),
+        };
+
+        let code = format!("return query_bucket(\"{BUCKET_ID}\");");
+        let res = aw_query::query_as(&code, &interval, &ds, member);
+        assert_err_type!(res, QueryError::AccessDenied(_));
+    }
+
+    #[test]
+    fn test_bucket_access_global_roles() {
+        let ds = setup_datastore_populated();
+        let interval = TimeInterval::new_from_string(TIME_INTERVAL).unwrap();
+        let admin = QueryCaller {
+            user_id: 1,
+            role: Role::Admin,
+        };
+        let auditor = QueryCaller {
+            user_id: 5,
+            role: Role::Auditor,
+        };
+
+        // Admins read their own buckets, auditors the buckets of all users including admins
+        let code = String::from("return query_bucket_names();");
+        for caller in [admin, auditor] {
+            match aw_query::query_as(&code, &interval, &ds, caller).unwrap() {
+                DataType::List(l) => {
+                    assert_eq!(l, vec![DataType::String(BUCKET_ID.to_string())])
+                }
+                data => panic!("Expected list, got {data:?}"),
+            };
+            let code = format!("return query_bucket(\"{BUCKET_ID}\");");
+            assert!(aw_query::query_as(&code, &interval, &ds, caller).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_privacy_filter() {
+        let ds = setup_datastore_empty();
+        let member = QueryCaller {
+            user_id: 5,
+            role: Role::Member,
+        };
+        let admin = QueryCaller {
+            user_id: 1,
+            role: Role::Admin,
+        };
+
+        // Users see all of their own data, admins all data
+        assert!(PrivacyFilter::new(&ds, &member, 5).unwrap().is_none());
+        assert!(PrivacyFilter::new(&ds, &admin, 5).unwrap().is_none());
+
+        // Without a shared team everything is reduced to totals
+        let filter = PrivacyFilter::new(&ds, &member, 6).unwrap().unwrap();
+        let events = vec![
+            Event::new(
+                chrono::Utc::now(),
+                Duration::seconds(10),
+                json_map! {"app": json!("Code"), "title": json!("secret.rs")},
+                1,
+            ),
+            Event::new(
+                chrono::Utc::now(),
+                Duration::seconds(20),
+                json_map! {"app": json!("Slack"), "title": json!("#general")},
+                1,
+            ),
+        ];
+        let events = filter.apply(events);
+        assert_eq!(events.len(), 1);
+        assert!(events[0].data.is_empty());
+        assert_eq!(events[0].duration, Duration::seconds(30));
+    }
 }
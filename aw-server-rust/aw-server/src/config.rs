@@ -37,6 +37,161 @@ pub struct AWConfig {
     // custom visualizations are located.
     #[serde(default = "default_custom_static")]
     pub custom_static: std::collections::HashMap<String, String>,
+
+    // The address the web UI is reachable at for links sent to users, like password reset links.
+    // Defaults to http://<address>:<port>
+    #[serde(default)]
+    pub public_url: Option<String>,
+
+    #[serde(default)]
+    pub auth: AuthConfig,
+
+    #[serde(default)]
+    pub notifier: NotifierConfig,
+
+    #[serde(default)]
+    pub login_throttle: LoginThrottleConfig,
+
+    // Single sign-on through an OpenID Connect provider, disabled if not set
+    #[serde(default)]
+    pub oidc: Option<OidcConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JwtKeyConfig {
+    pub kid: String,
+    pub secret: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AuthConfig {
+    // Lifetime of issued access tokens, in seconds
+    #[serde(default = "default_jwt_expiry")]
+    pub jwt_expiry: i64,
+
+    // Lifetime of refresh tokens, in seconds. Every refresh issues a new refresh token.
+    #[serde(default = "default_refresh_token_expiry")]
+    pub refresh_token_expiry: i64,
+
+    // The key (by kid) used to sign new tokens, defaults to the first entry in jwt_keys.
+    // Tokens signed by any of the other keys are still accepted, which allows rotating the
+    // signing key without invalidating every session.
+    #[serde(default)]
+    pub jwt_signing_kid: Option<String>,
+
+    // If empty, a key is generated on first start and stored in the database
+    #[serde(default)]
+    pub jwt_keys: Vec<JwtKeyConfig>,
+
+    // Lifetime of password reset tokens, in seconds
+    #[serde(default = "default_password_reset_expiry")]
+    pub password_reset_expiry: i64,
+
+    // Lifetime of email verification tokens, in seconds
+    #[serde(default = "default_email_verification_expiry")]
+    pub email_verification_expiry: i64,
+}
+
+// Limits on failed logins, counted per account and per client address. Every failure makes the
+// subject wait twice as long before the next attempt, starting at backoff_base seconds. Once the
+// maximum is reached it is locked for lockout_duration seconds.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LoginThrottleConfig {
+    #[serde(default = "default_max_failures_per_account")]
+    pub max_failures_per_account: i32,
+
+    #[serde(default = "default_max_failures_per_ip")]
+    pub max_failures_per_ip: i32,
+
+    #[serde(default = "default_backoff_base")]
+    pub backoff_base: i64,
+
+    // Also the time after which earlier failures are forgotten
+    #[serde(default = "default_lockout_duration")]
+    pub lockout_duration: i64,
+}
+
+// Users logging in through the provider for the first time get an account, and are linked to an
+// existing account with the same email address if the provider has verified it.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OidcConfig {
+    // The provider metadata is discovered at <issuer>/.well-known/openid-configuration
+    pub issuer: String,
+    pub client_id: String,
+    // Only needed for confidential clients, the authorization code is protected by PKCE either way
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    // The page of the web UI the provider sends users back to, defaults to
+    // <public_url>/oidc/callback
+    #[serde(default)]
+    pub redirect_url: Option<String>,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    // Label of the login button in the web UI
+    #[serde(default = "default_oidc_display_name")]
+    pub display_name: String,
+    // The claim of the ID token listing the groups of the user
+    #[serde(default = "default_oidc_groups_claim")]
+    pub groups_claim: String,
+    // Maps groups to the ids of teams. At every login users are added to the teams of their
+    // groups, and removed from the mapped teams of groups they are no longer in.
+    #[serde(default)]
+    pub group_teams: std::collections::HashMap<String, i32>,
+}
+
+// How messages to users, like password reset links, are delivered
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotifierConfig {
+    // Send emails through an SMTP server
+    Smtp {
+        host: String,
+        // Defaults to the submission port 587, or 465 with tls = true
+        #[serde(default)]
+        port: Option<u16>,
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+        // Use implicit TLS instead of STARTTLS
+        #[serde(default)]
+        tls: bool,
+        from: String,
+    },
+    // Append the messages as JSON lines to a file
+    File { path: String },
+    // Store the messages in the database, where admins can read them through the API
+    Database,
+}
+
+impl Default for NotifierConfig {
+    fn default() -> NotifierConfig {
+        NotifierConfig::Database
+    }
+}
+
+impl Default for LoginThrottleConfig {
+    fn default() -> LoginThrottleConfig {
+        LoginThrottleConfig {
+            max_failures_per_account: default_max_failures_per_account(),
+            max_failures_per_ip: default_max_failures_per_ip(),
+            backoff_base: default_backoff_base(),
+            lockout_duration: default_lockout_duration(),
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> AuthConfig {
+        AuthConfig {
+            jwt_expiry: default_jwt_expiry(),
+            refresh_token_expiry: default_refresh_token_expiry(),
+            jwt_signing_kid: None,
+            jwt_keys: Vec::new(),
+            password_reset_expiry: default_password_reset_expiry(),
+            email_verification_expiry: default_email_verification_expiry(),
+        }
+    }
 }
 
 impl Default for AWConfig {
@@ -47,6 +202,11 @@ impl Default for AWConfig {
             testing: default_testing(),
             cors: default_cors(),
             custom_static: default_custom_static(),
+            public_url: None,
+            auth: AuthConfig::default(),
+            notifier: NotifierConfig::default(),
+            login_throttle: LoginThrottleConfig::default(),
+            oidc: None,
         }
     }
 }
@@ -73,6 +233,13 @@ impl AWConfig {
 
         config
     }
+
+    pub fn public_url(&self) -> String {
+        match &self.public_url {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => format!("http://{}:{}", self.address, self.port),
+        }
+    }
 }
 
 fn default_address() -> String {
@@ -99,6 +266,60 @@ fn default_custom_static() -> std::collections::HashMap<String, String> {
     std::collections::HashMap::new()
 }
 
+fn default_jwt_expiry() -> i64 {
+    // 15 minutes, clients are expected to use their refresh token to get a new one
+    15 * 60
+}
+
+fn default_refresh_token_expiry() -> i64 {
+    // 30 days
+    30 * 24 * 60 * 60
+}
+
+fn default_password_reset_expiry() -> i64 {
+    // 1 hour
+    60 * 60
+}
+
+fn default_email_verification_expiry() -> i64 {
+    // 2 days
+    2 * 24 * 60 * 60
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec![
+        "openid".to_string(),
+        "email".to_string(),
+        "profile".to_string(),
+    ]
+}
+
+fn default_oidc_display_name() -> String {
+    "Single sign-on".to_string()
+}
+
+fn default_oidc_groups_claim() -> String {
+    "groups".to_string()
+}
+
+fn default_max_failures_per_account() -> i32 {
+    5
+}
+
+fn default_max_failures_per_ip() -> i32 {
+    // Higher, as several users can share an address
+    20
+}
+
+fn default_backoff_base() -> i64 {
+    1
+}
+
+fn default_lockout_duration() -> i64 {
+    // 15 minutes
+    15 * 60
+}
+
 pub fn create_config(testing: bool) -> AWConfig {
     set_testing(testing);
     let mut config_path = dirs::get_config_dir().unwrap();
@@ -0,0 +1,148 @@
+use std::net::IpAddr;
+
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::{Deserialize, Serialize};
+
+use aw_models::ApiKey;
+
+use crate::endpoints::audit::{self, target};
+use crate::endpoints::auth::{AuthenticatedUser, API_KEY_PREFIX};
+use crate::endpoints::jwt::{hash_token, random_token};
+use crate::endpoints::{HttpErrorJson, ServerState};
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct ApiKeyModel<'r> {
+    name: &'r str,
+}
+
+#[derive(Serialize)]
+pub struct NewApiKeyResponse {
+    #[serde(flatten)]
+    api_key: ApiKey,
+    // Only returned here, the server does not keep the key itself
+    key: String,
+}
+
+#[get("/")]
+pub fn api_keys_get(
+    state: &State<ServerState>,
+    auth: AuthenticatedUser,
+) -> Result<Json<Vec<ApiKey>>, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    Ok(Json(datastore.get_api_keys(auth.id)?))
+}
+
+/// Creates an API key for a device of the user
+#[post("/", data = "<input>", format = "application/json")]
+pub fn api_key_new(
+    state: &State<ServerState>,
+    input: Json<ApiKeyModel>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<NewApiKeyResponse>, HttpErrorJson> {
+    let name = input.name.trim();
+    if name.is_empty() {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "No name was provided".to_string(),
+        ));
+    }
+    let key = format!("{API_KEY_PREFIX}{}", random_token());
+    let datastore = endpoints_get_lock!(state.datastore);
+    let api_key = datastore.add_api_key(auth.id, name, &hash_token(&key))?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "api_key.create",
+        target("api_key", api_key.id),
+    )?;
+    Ok(Json(NewApiKeyResponse { api_key, key }))
+}
+
+#[delete("/<key_id>")]
+pub fn api_key_delete(
+    state: &State<ServerState>,
+    key_id: i64,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<(), HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    datastore.delete_api_key(auth.id, key_id)?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "api_key.delete",
+        target("api_key", key_id),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{Method, Status};
+    use serde_json::json;
+
+    use crate::endpoints::test_client::TestServer;
+
+    #[test]
+    fn test_api_key_limited_to_own_buckets() {
+        let server = TestServer::new();
+        let (alice_id, alice) = server.create_user("alice", "member");
+        let (_, bob) = server.create_user("bob", "member");
+        let key = server.create_api_key(&alice);
+        let bob_bucket = server.create_bucket(&bob, "currentwindow");
+
+        // Buckets created with the key belong to its user
+        let bucket_id = server.create_bucket(&key, "currentwindow");
+        let (_, bucket) = server.call(
+            Method::Get,
+            &format!("/api/0/buckets/{bucket_id}/info"),
+            Some(&alice),
+            None,
+        );
+        assert_eq!(bucket["user_id"], alice_id, "{bucket}");
+
+        let events = json!([{
+            "timestamp": "2024-01-01T10:00:00Z",
+            "duration": 60,
+            "data": {"app": "Code", "title": "main.rs"},
+            "team_id": 0,
+        }]);
+        let post_events = |bucket_id: i64| {
+            server
+                .call(
+                    Method::Post,
+                    &format!("/api/0/buckets/{bucket_id}/events"),
+                    Some(&key),
+                    Some(events.clone()),
+                )
+                .0
+        };
+        assert_eq!(post_events(bucket_id), Status::Ok);
+        assert_eq!(post_events(bob_bucket), Status::Forbidden);
+
+        // The key can not read data, not even of its own user
+        let (status, _) = server.call(
+            Method::Get,
+            &format!("/api/0/buckets/{bucket_id}/events"),
+            Some(&key),
+            None,
+        );
+        assert_eq!(status, Status::Forbidden);
+
+        let (_, keys) = server.call(Method::Get, "/api/user/keys/", Some(&alice), None);
+        let key_id = keys[0]["id"].as_i64().unwrap();
+        let (status, _) = server.call(
+            Method::Delete,
+            &format!("/api/user/keys/{key_id}"),
+            Some(&alice),
+            None,
+        );
+        assert_eq!(status, Status::Ok);
+        assert_eq!(post_events(bucket_id), Status::Unauthorized);
+    }
+}
@@ -0,0 +1,343 @@
+use std::io::Cursor;
+use std::net::IpAddr;
+
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::Serialize;
+
+use aw_datastore::{Datastore, DatastoreError};
+use aw_models::{AuditEntry, AuditFilter, AuditPage, Permission};
+
+use crate::endpoints::auth::AuthenticatedUser;
+use crate::endpoints::jwt::{hash_token, random_token};
+use crate::endpoints::util::{page_offset, parse_time};
+use crate::endpoints::{HttpErrorJson, ServerState};
+
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+// Key in the key_value table of the secret the email addresses of unknown accounts are hashed with
+const ACCOUNT_KEY: &str = "audit.account_key";
+
+/// Appends an entry to the audit log
+///
+/// `target` names what the action was performed on as `<kind>:<id>`, see [`target`]. Targets
+/// within another one are joined with `/`, such as `team:3/user:5`.
+pub fn record(
+    datastore: &Datastore,
+    actor: Option<i32>,
+    address: Option<IpAddr>,
+    action: &str,
+    target: Option<String>,
+) -> Result<(), HttpErrorJson> {
+    datastore.add_audit_entry(actor, action, target, address.map(|ip| ip.to_string()))?;
+    Ok(())
+}
+
+/// Target of an audit entry, such as `team:3`
+pub fn target(kind: &str, id: impl std::fmt::Display) -> Option<String> {
+    Some(format!("{kind}:{id}"))
+}
+
+/// Target within a team, such as `team:3/user:5` for a member
+pub fn team_target(team_id: i32, kind: &str, id: impl std::fmt::Display) -> Option<String> {
+    Some(format!("team:{team_id}/{kind}:{id}"))
+}
+
+/// Target of an action on an account which may not exist, such as a failed login
+///
+/// Existing accounts are named by their user. The email addresses of unknown accounts are not
+/// stored, only a hash keyed with a secret of the server which still tells them apart.
+pub fn account_target(
+    datastore: &Datastore,
+    user_id: Option<i32>,
+    email: &str,
+) -> Result<Option<String>, HttpErrorJson> {
+    if let Some(user_id) = user_id {
+        return Ok(target("user", user_id));
+    }
+    let key = match datastore.get_key_value(ACCOUNT_KEY) {
+        Ok(key) => key,
+        Err(DatastoreError::NoSuchKey(_)) => {
+            let key = random_token();
+            datastore.set_key_value(ACCOUNT_KEY, &key)?;
+            key
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let hash = hash_token(&format!("{key}:{}", email.to_lowercase()));
+    Ok(target("account", hash))
+}
+
+#[derive(FromForm)]
+pub struct AuditQuery<'r> {
+    // Starts at 1, the export ignores the page
+    page: Option<i64>,
+    per_page: Option<i64>,
+    actor: Option<i32>,
+    action: Option<&'r str>,
+    target: Option<&'r str>,
+    // RFC 3339 timestamps, `until` is exclusive
+    since: Option<&'r str>,
+    until: Option<&'r str>,
+}
+
+#[derive(Serialize)]
+pub struct AuditResponse {
+    #[serde(flatten)]
+    page: AuditPage,
+    page_number: i64,
+    per_page: i64,
+}
+
+impl AuditQuery<'_> {
+    fn to_filter(&self) -> Result<AuditFilter, HttpErrorJson> {
+        Ok(AuditFilter {
+            actor: self.actor,
+            action: self
+                .action
+                .filter(|action| !action.is_empty())
+                .map(str::to_string),
+            target: self
+                .target
+                .filter(|target| !target.is_empty())
+                .map(str::to_string),
+            since: parse_time("since", self.since)?,
+            until: parse_time("until", self.until)?,
+            ..Default::default()
+        })
+    }
+}
+
+/// Lists the entries of the audit log matching the filters, newest first
+///
+/// `action` also matches the actions below it, so `team` lists all changes to teams.
+#[get("/?<query..>")]
+pub fn audit_get(
+    state: &State<ServerState>,
+    query: AuditQuery,
+    auth: AuthenticatedUser,
+) -> Result<Json<AuditResponse>, HttpErrorJson> {
+    auth.require(Permission::ViewAuditLog)?;
+    let page_number = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let filter = AuditFilter {
+        offset: page_offset(page_number, per_page)?,
+        limit: Some(per_page),
+        ..query.to_filter()?
+    };
+    let datastore = endpoints_get_lock!(state.datastore);
+    Ok(Json(AuditResponse {
+        page: datastore.get_audit_entries(filter)?,
+        page_number,
+        per_page,
+    }))
+}
+
+pub enum AuditExport {
+    Json(Vec<AuditEntry>),
+    Csv(Vec<AuditEntry>),
+}
+
+// Quotes a CSV field when it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn to_csv(entries: &[AuditEntry]) -> String {
+    let mut csv = "id,timestamp,actor,action,target,address\n".to_string();
+    for entry in entries {
+        let fields = [
+            entry.id.to_string(),
+            entry.timestamp.to_rfc3339(),
+            entry
+                .actor
+                .map(|actor| actor.to_string())
+                .unwrap_or_default(),
+            entry.action.clone(),
+            entry.target.clone().unwrap_or_default(),
+            entry.address.clone().unwrap_or_default(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+impl<'r> Responder<'r, 'static> for AuditExport {
+    fn respond_to(self, _: &Request) -> response::Result<'static> {
+        let (body, content_type, extension) = match self {
+            AuditExport::Json(entries) => (
+                serde_json::to_string(&entries).unwrap(),
+                ContentType::JSON,
+                "json",
+            ),
+            AuditExport::Csv(entries) => (to_csv(&entries), ContentType::CSV, "csv"),
+        };
+        Response::build()
+            .status(Status::Ok)
+            .header(content_type)
+            .header(Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=aw-audit-log.{extension}"),
+            ))
+            .sized_body(body.len(), Cursor::new(body))
+            .ok()
+    }
+}
+
+/// Exports all entries of the audit log matching the filters as `json` or `csv`
+///
+/// The export is recorded in the audit log itself.
+#[get("/export?<format>&<query..>")]
+pub fn audit_export(
+    state: &State<ServerState>,
+    query: AuditQuery,
+    format: Option<&str>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<AuditExport, HttpErrorJson> {
+    auth.require(Permission::ViewAuditLog)?;
+    let filter = query.to_filter()?;
+    let datastore = endpoints_get_lock!(state.datastore);
+    let entries = datastore.get_audit_entries(filter)?.entries;
+    let export = match format {
+        None | Some("json") => AuditExport::Json(entries),
+        Some("csv") => AuditExport::Csv(entries),
+        Some(format) => {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                format!("Invalid value for format: {format}"),
+            ))
+        }
+    };
+    record(&datastore, Some(auth.id), ip, "audit.export", None)?;
+    Ok(export)
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{Method, Status};
+    use serde_json::json;
+
+    use crate::endpoints::test_client::{bearer, host, TestServer};
+
+    #[test]
+    fn test_actions_recorded() {
+        let server = TestServer::new();
+        let (owner_id, owner) = server.create_user("owner", "team_owner");
+        let (_, auditor) = server.create_user("auditor", "auditor");
+        let team_id = server.create_team(&owner, "team");
+        let bucket_id = server.create_bucket(&owner, "currentwindow");
+
+        let entries = |query: &str| {
+            let (status, page) = server.call(
+                Method::Get,
+                &format!("/api/audit/?{query}"),
+                Some(&auditor),
+                None,
+            );
+            assert_eq!(status, Status::Ok, "{page}");
+            page["entries"].as_array().unwrap().clone()
+        };
+        let team = entries(&format!("action=team&actor={owner_id}"));
+        assert_eq!(team.len(), 1);
+        assert_eq!(team[0]["action"], "team.create");
+        assert_eq!(team[0]["target"], format!("team:{team_id}"));
+        let bucket = entries("action=bucket.create");
+        assert_eq!(bucket[0]["target"], format!("bucket:{bucket_id}"));
+        assert_eq!(bucket[0]["actor"], owner_id);
+
+        // Only roles with the permission can read the log, nobody can change it
+        let (status, _) = server.call(Method::Get, "/api/audit/", Some(&owner), None);
+        assert_eq!(status, Status::Forbidden);
+        let (status, _) = server.call(Method::Delete, "/api/audit/", Some(&server.admin), None);
+        assert_eq!(status, Status::NotFound);
+
+        let response = server
+            .client
+            .get("/api/audit/export?format=csv&action=team")
+            .header(host())
+            .header(bearer(&auditor))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let csv = response.into_string().unwrap();
+        assert!(csv.starts_with("id,timestamp,actor,action,target,address\n"));
+        assert!(csv.contains(&format!(",{owner_id},team.create,team:{team_id},")));
+        assert_eq!(entries("action=audit.export").len(), 1);
+    }
+
+    #[test]
+    fn test_member_actions_name_the_user() {
+        let server = TestServer::new();
+        let (_, owner) = server.create_user("owner", "team_owner");
+        let (member_id, member) = server.create_user("member", "member");
+        let team_id = server.create_team(&owner, "team");
+        server.join_team(&owner, &member, team_id);
+        let (_, team) = server.call(
+            Method::Get,
+            &format!("/api/teams/team/{team_id}"),
+            Some(&owner),
+            None,
+        );
+        let membership = team["members"][0]["id"].as_i64().unwrap();
+
+        // Member routes take the id of the membership, the log records the id of the user
+        let (status, _) = server.call(
+            Method::Put,
+            &format!("/api/teams/{team_id}/member/{membership}/role"),
+            Some(&owner),
+            Some(json!("team_manager")),
+        );
+        assert_eq!(status, Status::Ok);
+        let (status, _) = server.call(
+            Method::Put,
+            &format!("/api/teams/{team_id}/member/{membership}/schedule"),
+            Some(&owner),
+            Some(json!(null)),
+        );
+        assert_eq!(status, Status::Ok);
+        let (status, _) = server.call(
+            Method::Delete,
+            &format!("/api/teams/{team_id}/member/{membership}"),
+            Some(&owner),
+            None,
+        );
+        assert_eq!(status, Status::Ok);
+        for action in [
+            "team.member_role_set",
+            "team.member_schedule_set",
+            "team.member_remove",
+        ] {
+            let (_, page) = server.call(
+                Method::Get,
+                &format!("/api/audit/?action={action}"),
+                Some(&server.admin),
+                None,
+            );
+            assert_eq!(page["entries"].as_array().unwrap().len(), 1);
+            assert_eq!(
+                page["entries"][0]["target"],
+                format!("team:{team_id}/user:{member_id}")
+            );
+        }
+
+        let (status, _) = server.call(
+            Method::Delete,
+            &format!("/api/teams/{team_id}/member/{membership}"),
+            Some(&owner),
+            None,
+        );
+        assert_eq!(status, Status::NotFound);
+    }
+}
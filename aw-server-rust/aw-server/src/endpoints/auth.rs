@@ -0,0 +1,360 @@
+use rocket::http::Status;
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request};
+
+use aw_datastore::{Datastore, DatastoreError};
+use aw_models::{ApiKey, Permission, PublicUser, Role};
+use aw_query::{PrivacyFilter, QueryCaller};
+
+use crate::endpoints::jwt::{hash_token, Claims, JwtKeys};
+use crate::endpoints::two_factor;
+use crate::endpoints::{HttpErrorJson, ServerState};
+
+/// Request guard for routes which require a logged in user
+///
+/// Validates the bearer token in the Authorization header, checks that it has not been revoked
+/// and loads the user it was issued for. On failure the request is answered with a JSON error by
+/// the 401/403 catchers below.
+pub struct AuthenticatedUser {
+    pub id: i32,
+    pub user: PublicUser,
+    pub claims: Claims,
+    /// Set when an admin requires two-factor authentication for the user and it is not enabled
+    pub must_enroll_two_factor: bool,
+}
+
+impl AuthenticatedUser {
+    pub fn role(&self) -> Role {
+        self.user.role
+    }
+
+    /// Checks a permission granted by the system wide role of the user
+    pub fn require(&self, permission: Permission) -> Result<(), HttpErrorJson> {
+        if self.role().has_permission(permission) {
+            Ok(())
+        } else {
+            Err(forbidden_error(permission))
+        }
+    }
+
+    /// Checks a permission within a team
+    ///
+    /// Granted by a global role (admin, auditor) or by the role of the user in the team.
+    pub fn require_team(
+        &self,
+        datastore: &Datastore,
+        team_id: i32,
+        permission: Permission,
+    ) -> Result<(), HttpErrorJson> {
+        if self.role().is_global() && self.role().has_permission(permission) {
+            return Ok(());
+        }
+        match datastore.get_team_role(team_id, self.id)? {
+            Some(role) if role.has_permission(permission) => Ok(()),
+            _ => Err(forbidden_error(permission)),
+        }
+    }
+
+    /// Checks a permission on the buckets and events of `user_id`
+    ///
+    /// Users always have access to their own data. Access to the data of others is granted by a
+    /// global role or by the role of the user in a team the other user is a member of.
+    pub fn require_user(
+        &self,
+        datastore: &Datastore,
+        user_id: i32,
+        permission: Permission,
+    ) -> Result<(), HttpErrorJson> {
+        if self.id == user_id {
+            return Ok(());
+        }
+        if self.role().is_global() && self.role().has_permission(permission) {
+            return Ok(());
+        }
+        let roles = datastore.get_shared_team_roles(self.id, user_id)?;
+        if roles.iter().any(|role| role.has_permission(permission)) {
+            Ok(())
+        } else {
+            Err(forbidden_error(permission))
+        }
+    }
+
+    /// The user as the caller of queries, which are limited to the data the user may read
+    pub fn query_caller(&self) -> QueryCaller {
+        QueryCaller {
+            user_id: self.id,
+            role: self.role(),
+        }
+    }
+
+    /// Reduces the events of `user_id` to the privacy levels of their teams, `None` if the user
+    /// may see all of them
+    pub fn privacy_filter(
+        &self,
+        datastore: &Datastore,
+        user_id: i32,
+    ) -> Result<Option<PrivacyFilter>, HttpErrorJson> {
+        Ok(PrivacyFilter::new(
+            datastore,
+            &self.query_caller(),
+            user_id,
+        )?)
+    }
+}
+
+/// Error for requests and logins of users an admin has deactivated
+pub fn deactivated_error() -> HttpErrorJson {
+    HttpErrorJson::new(
+        Status::Forbidden,
+        "The account has been deactivated".to_string(),
+    )
+}
+
+fn forbidden_error(permission: Permission) -> HttpErrorJson {
+    HttpErrorJson::new(
+        Status::Forbidden,
+        format!("Missing permission: {permission:?}"),
+    )
+}
+
+// The error of a failed guard is kept in the request-local cache so the catcher can respond with
+// the actual reason instead of a generic message
+struct AuthError(Option<HttpErrorJson>);
+
+fn fail<T>(request: &Request<'_>, err: HttpErrorJson) -> request::Outcome<T, HttpErrorJson> {
+    request.local_cache(|| AuthError(Some(err.clone())));
+    Outcome::Error((err.status(), err))
+}
+
+/// Like [AuthenticatedUser], but also accepts users who still have to change their password or
+/// enable two-factor authentication
+///
+/// Only for the routes needed to do so, like changing the password and logging out.
+pub struct AnyAuthenticatedUser(pub AuthenticatedUser);
+
+fn authenticate(request: &Request<'_>) -> Result<AuthenticatedUser, HttpErrorJson> {
+    let token = match request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|header| header.strip_prefix("Bearer "))
+    {
+        Some(token) => token,
+        None => {
+            return Err(HttpErrorJson::new(
+                Status::Unauthorized,
+                "Authentication is required".to_string(),
+            ))
+        }
+    };
+    if token.starts_with(API_KEY_PREFIX) {
+        return Err(HttpErrorJson::new(
+            Status::Forbidden,
+            "API keys can only create buckets and write events".to_string(),
+        ));
+    }
+
+    let state = request.rocket().state::<ServerState>().unwrap();
+    let jwt_keys = request.rocket().state::<JwtKeys>().unwrap();
+    let datastore = match state.datastore.lock() {
+        Ok(datastore) => datastore,
+        Err(err) => {
+            let err_msg = format!("Taking datastore lock failed, returning 504: {err}");
+            warn!("{}", err_msg);
+            return Err(HttpErrorJson::new(Status::ServiceUnavailable, err_msg));
+        }
+    };
+
+    let claims = jwt_keys.authenticate(&datastore, token)?;
+    match datastore.get_user(claims.user_id) {
+        Ok(user) if !user.active => Err(deactivated_error()),
+        Ok(user) => Ok(AuthenticatedUser {
+            id: user.id,
+            must_enroll_two_factor: two_factor::must_enroll(&datastore, &user)?,
+            user,
+            claims,
+        }),
+        Err(DatastoreError::NoUser()) => Err(HttpErrorJson::new(
+            Status::Unauthorized,
+            "User does not exist".to_string(),
+        )),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthenticatedUser {
+    type Error = HttpErrorJson;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match authenticate(request) {
+            Ok(auth) if auth.user.must_change_password => fail(
+                request,
+                HttpErrorJson::new(
+                    Status::Forbidden,
+                    "The password has to be changed first".to_string(),
+                ),
+            ),
+            Ok(auth) if auth.must_enroll_two_factor => fail(
+                request,
+                HttpErrorJson::new(
+                    Status::Forbidden,
+                    "Two-factor authentication has to be enabled first".to_string(),
+                ),
+            ),
+            Ok(auth) => Outcome::Success(auth),
+            Err(err) => fail(request, err),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AnyAuthenticatedUser {
+    type Error = HttpErrorJson;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match authenticate(request) {
+            Ok(auth) => Outcome::Success(AnyAuthenticatedUser(auth)),
+            Err(err) => fail(request, err),
+        }
+    }
+}
+
+/// Prefix of API keys, tells them apart from access tokens in the Authorization header
+pub const API_KEY_PREFIX: &str = "awk_";
+
+/// Request guard for the routes watchers use to write their data
+///
+/// Accepts either a logged in user or an API key. API keys only grant access to the buckets of
+/// the user who created them.
+pub enum BucketWriter {
+    User(AuthenticatedUser),
+    ApiKey(ApiKey),
+}
+
+impl BucketWriter {
+    /// The user new buckets are created for
+    pub fn user_id(&self) -> i32 {
+        match self {
+            BucketWriter::User(auth) => auth.id,
+            BucketWriter::ApiKey(api_key) => api_key.user_id,
+        }
+    }
+
+    /// Checks that events may be written to the buckets of `user_id`
+    pub fn require_user(&self, datastore: &Datastore, user_id: i32) -> Result<(), HttpErrorJson> {
+        match self {
+            BucketWriter::User(auth) => {
+                auth.require_user(datastore, user_id, Permission::WriteUserData)
+            }
+            BucketWriter::ApiKey(api_key) if api_key.user_id == user_id => Ok(()),
+            BucketWriter::ApiKey(_) => Err(HttpErrorJson::new(
+                Status::Forbidden,
+                "API keys can only write to the buckets of their user".to_string(),
+            )),
+        }
+    }
+
+    /// Checks that the configuration of a team may be read, watchers need it to know what to track
+    pub fn require_team(&self, datastore: &Datastore, team_id: i32) -> Result<(), HttpErrorJson> {
+        match self {
+            BucketWriter::User(auth) => auth.require_team(datastore, team_id, Permission::ViewTeam),
+            BucketWriter::ApiKey(api_key) => {
+                match datastore.get_team_role(team_id, api_key.user_id)? {
+                    Some(_) => Ok(()),
+                    None => Err(HttpErrorJson::new(
+                        Status::Forbidden,
+                        "API keys can only read the teams of their user".to_string(),
+                    )),
+                }
+            }
+        }
+    }
+}
+
+fn authenticate_api_key(request: &Request<'_>, key: &str) -> Result<ApiKey, HttpErrorJson> {
+    let state = request.rocket().state::<ServerState>().unwrap();
+    let datastore = match state.datastore.lock() {
+        Ok(datastore) => datastore,
+        Err(err) => {
+            let err_msg = format!("Taking datastore lock failed, returning 504: {err}");
+            warn!("{}", err_msg);
+            return Err(HttpErrorJson::new(Status::ServiceUnavailable, err_msg));
+        }
+    };
+    let api_key = match datastore.use_api_key(&hash_token(key)) {
+        Ok(api_key) => api_key,
+        Err(DatastoreError::NoSuchKey(_)) => {
+            return Err(HttpErrorJson::new(
+                Status::Unauthorized,
+                "Invalid API key".to_string(),
+            ))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if !datastore.get_user(api_key.user_id)?.active {
+        return Err(deactivated_error());
+    }
+    Ok(api_key)
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BucketWriter {
+    type Error = HttpErrorJson;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let api_key = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .filter(|token| token.starts_with(API_KEY_PREFIX));
+        match api_key {
+            Some(key) => match authenticate_api_key(request, key) {
+                Ok(api_key) => Outcome::Success(BucketWriter::ApiKey(api_key)),
+                Err(err) => fail(request, err),
+            },
+            None => AuthenticatedUser::from_request(request)
+                .await
+                .map(BucketWriter::User),
+        }
+    }
+}
+
+fn auth_error(status: Status, request: &Request) -> HttpErrorJson {
+    match &request.local_cache(|| AuthError(None)).0 {
+        Some(err) => err.clone(),
+        None => HttpErrorJson::new(status, status.reason_lossy().to_string()),
+    }
+}
+
+#[catch(401)]
+pub fn unauthorized(request: &Request) -> HttpErrorJson {
+    auth_error(Status::Unauthorized, request)
+}
+
+#[catch(403)]
+pub fn forbidden(request: &Request) -> HttpErrorJson {
+    auth_error(Status::Forbidden, request)
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{Method, Status};
+
+    use crate::endpoints::test_client::TestServer;
+
+    #[test]
+    fn test_missing_or_invalid_token() {
+        let server = TestServer::new();
+        let (status, error) = server.call(Method::Get, "/api/teams/user", None, None);
+        assert_eq!(status, Status::Unauthorized);
+        assert_eq!(error["message"], "Authentication is required");
+
+        let (status, error) = server.call(Method::Get, "/api/teams/user", Some("garbage"), None);
+        assert_eq!(status, Status::Unauthorized);
+        assert!(error["message"].is_string(), "{error}");
+
+        let api_key = server.create_api_key(&server.admin);
+        let (status, _) = server.call(Method::Get, "/api/teams/user", Some(&api_key), None);
+        assert_eq!(status, Status::Forbidden);
+    }
+}
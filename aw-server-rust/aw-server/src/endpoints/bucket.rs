@@ -1,8 +1,5 @@
-use jwt::{create_jwt, validate_jwt, Claims};
-use rocket::outcome::Outcome;
-use rocket::request::{self, FromRequest, Request};
-use serde::Deserialize;
 use std::collections::HashMap;
+use std::net::IpAddr;
 
 use gethostname::gethostname;
 use rocket::serde::json::Json;
@@ -10,25 +7,76 @@ use rocket::serde::json::Json;
 use chrono::DateTime;
 use chrono::Utc;
 
+use aw_datastore::Datastore;
 use aw_models::BucketsExport;
 use aw_models::Event;
 use aw_models::TryVec;
-use aw_models::{Bucket, PublicBucket};
+use aw_models::{Bucket, Permission, PublicBucket};
 
 use rocket::http::Status;
 use rocket::State;
 
-mod jwt;
-
 use crate::endpoints::util::BucketsExportRocket;
+use crate::endpoints::audit::{self, target};
+use crate::endpoints::auth::{AuthenticatedUser, BucketWriter};
 use crate::endpoints::{HttpErrorJson, ServerState};
 
+/// Fetches the bucket, making sure the user has the permission on the data of its owner
+fn authorize_bucket(
+    datastore: &Datastore,
+    auth: &AuthenticatedUser,
+    bucket_id: i64,
+    permission: Permission,
+) -> Result<Bucket, HttpErrorJson> {
+    let bucket = datastore.get_bucket(bucket_id)?;
+    auth.require_user(datastore, bucket.user_id, permission)?;
+    Ok(bucket)
+}
+
+/// Fetches the bucket, making sure events may be written to it
+fn authorize_bucket_write(
+    datastore: &Datastore,
+    auth: &BucketWriter,
+    bucket_id: i64,
+) -> Result<Bucket, HttpErrorJson> {
+    let bucket = datastore.get_bucket(bucket_id)?;
+    auth.require_user(datastore, bucket.user_id)?;
+    Ok(bucket)
+}
+
+/// Makes sure events are only recorded for teams the owner of the bucket is a member of, team 0
+/// being no team
+pub fn authorize_event_teams(
+    datastore: &Datastore,
+    bucket: &Bucket,
+    events: &[Event],
+) -> Result<(), HttpErrorJson> {
+    let mut team_ids: Vec<i32> = events
+        .iter()
+        .map(|event| event.team_id)
+        .filter(|team_id| *team_id != 0)
+        .collect();
+    team_ids.sort_unstable();
+    team_ids.dedup();
+    for team_id in team_ids {
+        if datastore.get_team_role(team_id, bucket.user_id)?.is_none() {
+            return Err(HttpErrorJson::new(
+                Status::Forbidden,
+                format!("The user of the bucket is not a member of team {team_id}"),
+            ));
+        }
+    }
+    Ok(())
+}
+
 #[get("/<user_id>")]
 pub fn buckets_get(
     state: &State<ServerState>,
     user_id: i32,
+    auth: AuthenticatedUser,
 ) -> Result<Json<Vec<Bucket>>, HttpErrorJson> {
     let datastore = endpoints_get_lock!(state.datastore);
+    auth.require_user(&datastore, user_id, Permission::ReadUserData)?;
     match datastore.get_buckets(user_id) {
         Ok(bucketlist) => Ok(Json(bucketlist.values().cloned().collect())),
         Err(err) => Err(err.into()),
@@ -39,54 +87,25 @@ pub fn buckets_get(
 pub fn bucket_get(
     bucket_id: i64,
     state: &State<ServerState>,
+    auth: AuthenticatedUser,
 ) -> Result<Json<Bucket>, HttpErrorJson> {
     let datastore = endpoints_get_lock!(state.datastore);
-    match datastore.get_bucket(bucket_id) {
-        Ok(bucket) => Ok(Json(bucket)),
-        Err(e) => Err(e.into()),
-    }
+    let bucket = authorize_bucket(&datastore, &auth, bucket_id, Permission::ReadUserData)?;
+    Ok(Json(bucket))
 }
 
 /// Create a new bucket
 ///
 /// If hostname is "!local", the hostname and device_id will be set from the server info.
 /// This is useful for watchers which are known/assumed to run locally but might not know their hostname (like aw-watcher-web).
-#[derive(Deserialize, Clone)]
-pub struct Token(String);
-#[rocket::async_trait]
-impl<'r> FromRequest<'r> for Token {
-    type Error = ();
-
-    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
-        // Look for the "Authorization" header
-        if let Some(token_header) = request.headers().get_one("Authorization") {
-            if let Some(token) = token_header.strip_prefix("Bearer ") {
-                return Outcome::Success(Token(token.to_string()));
-            }
-        }
-
-        Outcome::Forward(Status::Unauthorized)
-    }
-}
-
 #[post("/", data = "<message>", format = "application/json")]
 pub fn bucket_new(
     message: Json<PublicBucket>,
     state: &State<ServerState>,
-    token: Token,
+    auth: BucketWriter,
+    ip: Option<IpAddr>,
 ) -> Result<Json<i64>, HttpErrorJson> {
     let sent_bucket = message.into_inner();
-    let token_string = token.clone().0;
-    let user_id = match validate_jwt(&token_string) {
-        Ok(user_id) => user_id,
-        Err(_) => -1,
-    };
-    if user_id == -1 {
-        return Err(HttpErrorJson::new(
-            Status::Forbidden,
-            "Authentication is required".to_string(),
-        ));
-    }
     let bucket = Bucket {
         bid: sent_bucket.bid,
         _type: sent_bucket._type,
@@ -95,12 +114,21 @@ pub fn bucket_new(
         metadata: sent_bucket.metadata,
         events: sent_bucket.events,
         last_updated: sent_bucket.last_updated,
-        user_id: user_id,
+        user_id: auth.user_id(),
     };
     let datastore = endpoints_get_lock!(state.datastore);
     let ret = datastore.create_bucket(&bucket);
     let result = match ret {
-        Ok(id) => Ok(Json(id)),
+        Ok(id) => {
+            audit::record(
+                &datastore,
+                Some(auth.user_id()),
+                ip,
+                "bucket.create",
+                target("bucket", id),
+            )?;
+            Ok(Json(id))
+        }
         Err(err) => Err(HttpErrorJson::new(
             Status::InternalServerError,
             "Could not create bucket".to_string(),
@@ -117,6 +145,7 @@ pub fn bucket_events_get(
     limit: Option<u64>,
     team_id: Option<i32>,
     state: &State<ServerState>,
+    auth: AuthenticatedUser,
 ) -> Result<Json<Vec<Event>>, HttpErrorJson> {
     let starttime: Option<DateTime<Utc>> = match start {
         Some(dt_str) => match DateTime::parse_from_rfc3339(&dt_str) {
@@ -144,10 +173,11 @@ pub fn bucket_events_get(
         None => None,
     };
     let datastore = endpoints_get_lock!(state.datastore);
-    let res = datastore.get_user_events(bucket_id, starttime, endtime, limit, team_id);
-    match res {
-        Ok(events) => Ok(Json(events)),
-        Err(err) => Err(err.into()),
+    let bucket = authorize_bucket(&datastore, &auth, bucket_id, Permission::ReadUserData)?;
+    let events = datastore.get_user_events(bucket_id, starttime, endtime, limit, team_id)?;
+    match auth.privacy_filter(&datastore, bucket.user_id)? {
+        Some(filter) => Ok(Json(filter.apply(events))),
+        None => Ok(Json(events)),
     }
 }
 
@@ -159,12 +189,14 @@ pub fn bucket_events_get_single(
     event_id: i64,
     _unused: Option<u64>,
     state: &State<ServerState>,
+    auth: AuthenticatedUser,
 ) -> Result<Json<Event>, HttpErrorJson> {
     let datastore = endpoints_get_lock!(state.datastore);
-    let res = datastore.get_event(bucket_id, event_id);
-    match res {
-        Ok(events) => Ok(Json(events)),
-        Err(err) => Err(err.into()),
+    let bucket = authorize_bucket(&datastore, &auth, bucket_id, Permission::ReadUserData)?;
+    let event = datastore.get_event(bucket_id, event_id)?;
+    match auth.privacy_filter(&datastore, bucket.user_id)? {
+        Some(filter) => Ok(Json(filter.apply_event(event))),
+        None => Ok(Json(event)),
     }
 }
 
@@ -173,8 +205,11 @@ pub fn bucket_events_create(
     bucket_id: i64,
     events: Json<Vec<Event>>,
     state: &State<ServerState>,
+    auth: BucketWriter,
 ) -> Result<Json<Vec<Event>>, HttpErrorJson> {
     let datastore = endpoints_get_lock!(state.datastore);
+    let bucket = authorize_bucket_write(&datastore, &auth, bucket_id)?;
+    authorize_event_teams(&datastore, &bucket, &events)?;
     let res = datastore.insert_events(bucket_id, &events);
     match res {
         Ok(events) => Ok(Json(events)),
@@ -192,9 +227,12 @@ pub fn bucket_events_heartbeat(
     heartbeat_json: Json<Event>,
     pulsetime: f64,
     state: &State<ServerState>,
+    auth: BucketWriter,
 ) -> Result<Json<Event>, HttpErrorJson> {
     let heartbeat = heartbeat_json.into_inner();
     let datastore = endpoints_get_lock!(state.datastore);
+    let bucket = authorize_bucket_write(&datastore, &auth, bucket_id)?;
+    authorize_event_teams(&datastore, &bucket, std::slice::from_ref(&heartbeat))?;
     match datastore.heartbeat(bucket_id, heartbeat, pulsetime) {
         Ok(e) => Ok(Json(e)),
         Err(err) => Err(err.into()),
@@ -205,8 +243,10 @@ pub fn bucket_events_heartbeat(
 pub fn bucket_event_count(
     bucket_id: i64,
     state: &State<ServerState>,
+    auth: AuthenticatedUser,
 ) -> Result<Json<u64>, HttpErrorJson> {
     let datastore = endpoints_get_lock!(state.datastore);
+    authorize_bucket(&datastore, &auth, bucket_id, Permission::ReadUserData)?;
     let res = datastore.get_event_count(bucket_id, None, None);
     match res {
         Ok(eventcount) => Ok(Json(eventcount as u64)),
@@ -219,10 +259,19 @@ pub fn bucket_events_delete_by_id(
     bucket_id: i64,
     event_id: i64,
     state: &State<ServerState>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
 ) -> Result<(), HttpErrorJson> {
     let datastore = endpoints_get_lock!(state.datastore);
+    authorize_bucket(&datastore, &auth, bucket_id, Permission::WriteUserData)?;
     match datastore.delete_events_by_id(bucket_id, vec![event_id]) {
-        Ok(_) => Ok(()),
+        Ok(_) => audit::record(
+            &datastore,
+            Some(auth.id),
+            ip,
+            "bucket.event_delete",
+            Some(format!("bucket:{bucket_id}/event:{event_id}")),
+        ),
         Err(err) => Err(err.into()),
     }
 }
@@ -231,19 +280,17 @@ pub fn bucket_events_delete_by_id(
 pub fn bucket_export(
     bucket_id: i64,
     state: &State<ServerState>,
+    auth: AuthenticatedUser,
 ) -> Result<BucketsExportRocket, HttpErrorJson> {
     let datastore = endpoints_get_lock!(state.datastore);
     let mut export = BucketsExport {
         buckets: HashMap::new(),
     };
-    let mut bucket = match datastore.get_bucket(bucket_id) {
-        Ok(bucket) => bucket,
-        Err(err) => return Err(err.into()),
-    };
-    /* TODO: Replace expect with http error */
-    let events = datastore
-        .get_events(bucket_id, None, None, None)
-        .expect("Failed to get events for bucket");
+    let mut bucket = authorize_bucket(&datastore, &auth, bucket_id, Permission::ReadUserData)?;
+    let mut events = datastore.get_events(bucket_id, None, None, None)?;
+    if let Some(filter) = auth.privacy_filter(&datastore, bucket.user_id)? {
+        events = filter.apply(events);
+    }
     bucket.events = Some(TryVec::new(events));
     export.buckets.insert(bucket_id.to_string(), bucket);
 
@@ -251,10 +298,163 @@ pub fn bucket_export(
 }
 
 #[delete("/<bucket_id>")]
-pub fn bucket_delete(bucket_id: i64, state: &State<ServerState>) -> Result<(), HttpErrorJson> {
+pub fn bucket_delete(
+    bucket_id: i64,
+    state: &State<ServerState>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<(), HttpErrorJson> {
     let datastore = endpoints_get_lock!(state.datastore);
+    authorize_bucket(&datastore, &auth, bucket_id, Permission::WriteUserData)?;
     match datastore.delete_bucket(bucket_id) {
-        Ok(_) => Ok(()),
+        Ok(_) => audit::record(
+            &datastore,
+            Some(auth.id),
+            ip,
+            "bucket.delete",
+            target("bucket", bucket_id),
+        ),
         Err(err) => Err(err.into()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{Method, Status};
+    use serde_json::json;
+
+    use crate::endpoints::test_client::TestServer;
+
+    fn event(title: &str, team_id: i32) -> serde_json::Value {
+        json!({
+            "timestamp": "2024-01-01T10:00:00Z",
+            "duration": 60,
+            "data": {"app": "Code", "title": title},
+            "team_id": team_id,
+        })
+    }
+
+    #[test]
+    fn test_event_id_scoped_to_bucket() {
+        let server = TestServer::new();
+        let (_, alice) = server.create_user("alice", "member");
+        let (_, mallory) = server.create_user("mallory", "member");
+        let alice_bucket = server.create_bucket(&alice, "currentwindow");
+        let mallory_bucket = server.create_bucket(&mallory, "currentwindow");
+
+        let (status, events) = server.call(
+            Method::Post,
+            &format!("/api/0/buckets/{alice_bucket}/events"),
+            Some(&alice),
+            Some(json!([event("secret.rs", 0)])),
+        );
+        assert_eq!(status, Status::Ok, "{events}");
+        let id = events[0]["id"].as_i64().unwrap();
+
+        // The id of an event of another bucket can't be used to replace it
+        let mut stolen = event("replaced", 0);
+        stolen["id"] = json!(id);
+        let (status, _) = server.call(
+            Method::Post,
+            &format!("/api/0/buckets/{mallory_bucket}/events"),
+            Some(&mallory),
+            Some(json!([stolen.clone()])),
+        );
+        assert_eq!(status, Status::NotFound);
+        let (status, _) = server.call(
+            Method::Post,
+            &format!("/api/0/buckets/{mallory_bucket}/heartbeat?pulsetime=0"),
+            Some(&mallory),
+            Some(stolen.clone()),
+        );
+        assert_eq!(status, Status::NotFound);
+        let (status, kept) = server.call(
+            Method::Get,
+            &format!("/api/0/buckets/{alice_bucket}/events/{id}"),
+            Some(&alice),
+            None,
+        );
+        assert_eq!(status, Status::Ok);
+        assert_eq!(kept["data"]["title"], "secret.rs");
+
+        // Events of the own bucket can still be replaced
+        let (status, _) = server.call(
+            Method::Post,
+            &format!("/api/0/buckets/{alice_bucket}/events"),
+            Some(&alice),
+            Some(json!([stolen])),
+        );
+        assert_eq!(status, Status::Ok);
+        let (_, replaced) = server.call(
+            Method::Get,
+            &format!("/api/0/buckets/{alice_bucket}/events/{id}"),
+            Some(&alice),
+            None,
+        );
+        assert_eq!(replaced["data"]["title"], "replaced");
+    }
+
+    #[test]
+    fn test_events_only_for_own_teams() {
+        let server = TestServer::new();
+        let (owner_id, owner) = server.create_user("owner", "team_owner");
+        let (_, member) = server.create_user("member", "member");
+        let (status, team) = server.call(
+            Method::Post,
+            "/api/teams/",
+            Some(&owner),
+            Some(json!({"name": "team", "description": "", "ownerId": owner_id})),
+        );
+        assert_eq!(status, Status::Ok, "{team}");
+        let owner_bucket = server.create_bucket(&owner, "currentwindow");
+        let member_bucket = server.create_bucket(&member, "currentwindow");
+
+        let post = |token: &str, bucket_id: i64, team_id: i32| {
+            server
+                .call(
+                    Method::Post,
+                    &format!("/api/0/buckets/{bucket_id}/events"),
+                    Some(token),
+                    Some(json!([event("main.rs", team_id)])),
+                )
+                .0
+        };
+        assert_eq!(post(&owner, owner_bucket, 1), Status::Ok);
+        assert_eq!(post(&member, member_bucket, 0), Status::Ok);
+        // The member is not in team 1
+        assert_eq!(post(&member, member_bucket, 1), Status::Forbidden);
+        let (status, _) = server.call(
+            Method::Post,
+            &format!("/api/0/buckets/{member_bucket}/heartbeat?pulsetime=0"),
+            Some(&member),
+            Some(event("main.rs", 1)),
+        );
+        assert_eq!(status, Status::Forbidden);
+    }
+
+    #[test]
+    fn test_buckets_of_other_users_forbidden() {
+        let server = TestServer::new();
+        let (alice_id, alice) = server.create_user("alice", "member");
+        let (_, mallory) = server.create_user("mallory", "member");
+        let bucket_id = server.create_bucket(&alice, "currentwindow");
+
+        for url in [
+            format!("/api/0/buckets/{alice_id}"),
+            format!("/api/0/buckets/{bucket_id}/info"),
+            format!("/api/0/buckets/{bucket_id}/events"),
+            format!("/api/0/buckets/{bucket_id}/events/count"),
+            format!("/api/0/buckets/{bucket_id}/export"),
+        ] {
+            let (status, _) = server.call(Method::Get, &url, Some(&mallory), None);
+            assert_eq!(status, Status::Forbidden, "{url}");
+            let (status, _) = server.call(Method::Get, &url, Some(&alice), None);
+            assert_eq!(status, Status::Ok, "{url}");
+        }
+        let url = format!("/api/0/buckets/{bucket_id}");
+        let (status, _) = server.call(Method::Delete, &url, Some(&mallory), None);
+        assert_eq!(status, Status::Forbidden);
+        let (status, _) = server.call(Method::Delete, &url, Some(&alice), None);
+        assert_eq!(status, Status::Ok);
+    }
+}
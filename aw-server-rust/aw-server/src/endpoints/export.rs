@@ -5,16 +5,21 @@ use rocket::State;
 use aw_models::BucketsExport;
 use aw_models::TryVec;
 
+use crate::endpoints::auth::AuthenticatedUser;
 use crate::endpoints::util::BucketsExportRocket;
 use crate::endpoints::{HttpErrorJson, ServerState};
 
+/// Exports the buckets of the user with all their events
 #[get("/")]
-pub fn buckets_export(state: &State<ServerState>) -> Result<BucketsExportRocket, HttpErrorJson> {
+pub fn buckets_export(
+    state: &State<ServerState>,
+    auth: AuthenticatedUser,
+) -> Result<BucketsExportRocket, HttpErrorJson> {
     let datastore = endpoints_get_lock!(state.datastore);
     let mut export = BucketsExport {
         buckets: HashMap::new(),
     };
-    let mut buckets = match datastore.get_buckets(0) {
+    let mut buckets = match datastore.get_buckets(auth.id) {
         Ok(buckets) => buckets,
         Err(err) => return Err(err.into()),
     };
@@ -29,3 +34,47 @@ pub fn buckets_export(state: &State<ServerState>) -> Result<BucketsExportRocket,
 
     Ok(export.into())
 }
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{Method, Status};
+    use serde_json::json;
+
+    use crate::endpoints::test_client::TestServer;
+
+    #[test]
+    fn test_export_own_buckets() {
+        let server = TestServer::new();
+        let (_, alice) = server.create_user("alice", "member");
+        let (_, bob) = server.create_user("bob", "member");
+        let alice_bucket = server.create_bucket(&alice, "currentwindow");
+        let bob_bucket = server.create_bucket(&bob, "currentwindow");
+        let (status, _) = server.call(
+            Method::Post,
+            &format!("/api/0/buckets/{alice_bucket}/events"),
+            Some(&alice),
+            Some(json!([{
+                "timestamp": "2024-01-01T10:00:00Z",
+                "duration": 60,
+                "data": {"app": "Code", "title": "secret.rs"},
+                "team_id": 0,
+            }])),
+        );
+        assert_eq!(status, Status::Ok);
+
+        let (status, _) = server.call(Method::Get, "/api/0/export/", None, None);
+        assert_eq!(status, Status::Unauthorized);
+
+        let (status, export) = server.call(Method::Get, "/api/0/export/", Some(&alice), None);
+        assert_eq!(status, Status::Ok);
+        let buckets = export["buckets"].as_object().unwrap();
+        assert_eq!(buckets.len(), 1);
+        let bucket = &buckets[&alice_bucket.to_string()];
+        assert_eq!(bucket["events"][0]["data"]["title"], "secret.rs");
+
+        let (_, export) = server.call(Method::Get, "/api/0/export/", Some(&bob), None);
+        let buckets = export["buckets"].as_object().unwrap();
+        assert_eq!(buckets.len(), 1);
+        assert!(buckets.contains_key(&bob_bucket.to_string()));
+    }
+}
@@ -3,19 +3,46 @@ use rocket::http::Status;
 use rocket::serde::json::Json;
 use rocket::State;
 
+use std::net::IpAddr;
 use std::sync::Mutex;
 
-use aw_models::BucketsExport;
+use aw_models::{BucketsExport, TryVec};
 
 use aw_datastore::Datastore;
 
+use crate::endpoints::audit::{self, target};
+use crate::endpoints::auth::BucketWriter;
+use crate::endpoints::bucket::authorize_event_teams;
 use crate::endpoints::{HttpErrorJson, ServerState};
 
-fn import(datastore_mutex: &Mutex<Datastore>, import: BucketsExport) -> Result<(), HttpErrorJson> {
+/// Creates the buckets of the export for the authenticated user, whichever user they were
+/// exported from
+fn import(
+    datastore_mutex: &Mutex<Datastore>,
+    import: BucketsExport,
+    auth: &BucketWriter,
+    ip: Option<IpAddr>,
+) -> Result<(), HttpErrorJson> {
     let datastore = endpoints_get_lock!(datastore_mutex);
-    for (_bucketname, bucket) in import.buckets {
+    for (_bucketname, mut bucket) in import.buckets {
+        bucket.user_id = auth.user_id();
+        if let Some(events) = bucket.events.take() {
+            // The ids of exported events belong to the bucket they were exported from
+            let mut events = events.take_inner();
+            for event in &mut events {
+                event.id = None;
+            }
+            authorize_event_teams(&datastore, &bucket, &events)?;
+            bucket.events = Some(TryVec::new(events));
+        }
         match datastore.create_bucket(&bucket) {
-            Ok(_) => (),
+            Ok(bucket_id) => audit::record(
+                &datastore,
+                Some(auth.user_id()),
+                ip,
+                "bucket.import",
+                target("bucket", bucket_id),
+            )?,
             Err(e) => {
                 let err_msg = format!("Failed to import bucket: {e:?}");
                 warn!("{}", err_msg);
@@ -30,8 +57,10 @@ fn import(datastore_mutex: &Mutex<Datastore>, import: BucketsExport) -> Result<(
 pub fn bucket_import_json(
     state: &State<ServerState>,
     json_data: Json<BucketsExport>,
+    auth: BucketWriter,
+    ip: Option<IpAddr>,
 ) -> Result<(), HttpErrorJson> {
-    import(&state.datastore, json_data.into_inner())
+    import(&state.datastore, json_data.into_inner(), &auth, ip)
 }
 
 #[derive(FromForm)]
@@ -48,6 +77,82 @@ pub struct ImportForm {
 pub fn bucket_import_form(
     state: &State<ServerState>,
     form: Form<ImportForm>,
+    auth: BucketWriter,
+    ip: Option<IpAddr>,
 ) -> Result<(), HttpErrorJson> {
-    import(&state.datastore, form.into_inner().import.into_inner())
+    import(
+        &state.datastore,
+        form.into_inner().import.into_inner(),
+        &auth,
+        ip,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{Method, Status};
+    use serde_json::json;
+
+    use crate::endpoints::test_client::TestServer;
+
+    #[test]
+    fn test_import_export() {
+        let server = TestServer::new();
+        let (_, owner) = server.create_user("owner", "team_owner");
+        let (_, alice) = server.create_user("alice", "member");
+        let (bob_id, bob) = server.create_user("bob", "member");
+        let team_id = server.create_team(&owner, "team");
+        server.join_team(&owner, &alice, team_id);
+        let bucket_id = server.create_bucket(&alice, "currentwindow");
+        let event = |title: &str, team_id: i32| {
+            json!({
+                "timestamp": "2024-01-01T10:00:00Z",
+                "duration": 60,
+                "data": {"app": "Code", "title": title},
+                "team_id": team_id,
+            })
+        };
+        let (status, _) = server.call(
+            Method::Post,
+            &format!("/api/0/buckets/{bucket_id}/events"),
+            Some(&alice),
+            Some(json!([event("main.rs", 0)])),
+        );
+        assert_eq!(status, Status::Ok);
+        let (_, export) = server.call(Method::Get, "/api/0/export/", Some(&alice), None);
+
+        // The events are imported as new events of a new bucket
+        let (status, error) = server.call(Method::Post, "/api/0/import/", Some(&bob), Some(export));
+        assert_eq!(status, Status::Ok, "{error}");
+        let (_, buckets) = server.call(
+            Method::Get,
+            &format!("/api/0/buckets/{bob_id}"),
+            Some(&bob),
+            None,
+        );
+        let imported = buckets[0]["bid"].as_i64().unwrap();
+        assert_ne!(imported, bucket_id);
+        let (_, events) = server.call(
+            Method::Get,
+            &format!("/api/0/buckets/{imported}/events?team_id=0"),
+            Some(&bob),
+            None,
+        );
+        assert_eq!(events.as_array().unwrap().len(), 1);
+        assert_eq!(events[0]["data"]["title"], "main.rs");
+
+        // Events can only be imported for teams of the importing user
+        let (status, _) = server.call(
+            Method::Post,
+            &format!("/api/0/buckets/{bucket_id}/events"),
+            Some(&alice),
+            Some(json!([event("team.rs", team_id)])),
+        );
+        assert_eq!(status, Status::Ok);
+        let (_, mut export) = server.call(Method::Get, "/api/0/export/", Some(&alice), None);
+        let bucket = &mut export["buckets"][bucket_id.to_string()];
+        bucket["type"] = json!("afkstatus");
+        let (status, _) = server.call(Method::Post, "/api/0/import/", Some(&bob), Some(export));
+        assert_eq!(status, Status::Forbidden);
+    }
 }
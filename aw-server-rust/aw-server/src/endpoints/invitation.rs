@@ -0,0 +1,318 @@
+use std::net::IpAddr;
+
+use chrono::{DateTime, Duration, Utc};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::{Deserialize, Serialize};
+
+use aw_datastore::{Datastore, DatastoreError};
+use aw_models::{Invitation, Permission};
+
+use crate::config::AWConfig;
+use crate::endpoints::audit::{self, team_target};
+use crate::endpoints::auth::AuthenticatedUser;
+use crate::endpoints::jwt::{hash_token, random_token};
+use crate::endpoints::{HttpErrorJson, ServerState};
+
+// Stored in the key_value table, next to the settings
+const POLICY_KEY: &str = "policy.open_registration";
+const DEFAULT_EXPIRY: i64 = 7 * 24 * 60 * 60;
+const MAX_EXPIRY: i64 = 90 * 24 * 60 * 60;
+
+/// Whether anyone can sign up, otherwise signing up requires an invitation
+pub fn is_open_registration(datastore: &Datastore) -> Result<bool, HttpErrorJson> {
+    match datastore.get_key_value(POLICY_KEY) {
+        Ok(value) => Ok(value == "true"),
+        Err(DatastoreError::NoSuchKey(_)) => Ok(true),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Looks up an invitation which can still be used
+pub fn find_invitation(datastore: &Datastore, code: &str) -> Result<Invitation, HttpErrorJson> {
+    match datastore.get_invitation(&hash_token(code)) {
+        Ok(invitation) => Ok(invitation),
+        Err(DatastoreError::NoSuchKey(_)) => Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "Invalid or expired invitation".to_string(),
+        )),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct InvitationModel {
+    // Unlimited if not set
+    max_uses: Option<i32>,
+    // Seconds until the invitation expires, a week if not set
+    expires_in: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct NewInvitationResponse {
+    #[serde(flatten)]
+    invitation: Invitation,
+    // Only returned here, the server does not keep the code itself
+    code: String,
+    link: String,
+}
+
+#[derive(Serialize)]
+pub struct InvitationInfo {
+    team_id: i32,
+    team_name: String,
+    expires: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RegistrationPolicyModel {
+    open_registration: bool,
+}
+
+// Ranked below /team/<id> and /configuration/<team_id> of the team routes, which they overlap
+#[get("/<team_id>/invitations", rank = 2)]
+pub fn team_invitations_get(
+    state: &State<ServerState>,
+    team_id: i32,
+    auth: AuthenticatedUser,
+) -> Result<Json<Vec<Invitation>>, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    auth.require_team(&datastore, team_id, Permission::ManageTeam)?;
+    Ok(Json(datastore.get_team_invitations(team_id)?))
+}
+
+/// Creates an invitation to join the team
+///
+/// The link in the response can be sent to the invited people, new users can sign up with it
+/// even when open registration is disabled.
+#[post(
+    "/<team_id>/invitations",
+    data = "<input>",
+    format = "application/json",
+    rank = 2
+)]
+pub fn team_invitation_new(
+    state: &State<ServerState>,
+    config: &State<AWConfig>,
+    team_id: i32,
+    input: Json<InvitationModel>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<NewInvitationResponse>, HttpErrorJson> {
+    if matches!(input.max_uses, Some(max_uses) if max_uses < 1) {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "An invitation has to be usable at least once".to_string(),
+        ));
+    }
+    let expires_in = input.expires_in.unwrap_or(DEFAULT_EXPIRY);
+    if !(1..=MAX_EXPIRY).contains(&expires_in) {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            format!(
+                "Invitations have to expire within {} days",
+                MAX_EXPIRY / 86400
+            ),
+        ));
+    }
+    let datastore = endpoints_get_lock!(state.datastore);
+    auth.require_team(&datastore, team_id, Permission::ManageTeam)?;
+    let code = random_token();
+    let invitation = datastore.add_invitation(
+        team_id,
+        auth.id,
+        &hash_token(&code),
+        input.max_uses,
+        Utc::now() + Duration::seconds(expires_in),
+    )?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "team.invitation_create",
+        team_target(team_id, "invitation", invitation.id),
+    )?;
+    Ok(Json(NewInvitationResponse {
+        invitation,
+        link: format!("{}/join?code={}", config.public_url(), code),
+        code,
+    }))
+}
+
+#[delete("/<team_id>/invitations/<invitation_id>")]
+pub fn team_invitation_delete(
+    state: &State<ServerState>,
+    team_id: i32,
+    invitation_id: i64,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<(), HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    auth.require_team(&datastore, team_id, Permission::ManageTeam)?;
+    datastore.delete_invitation(team_id, invitation_id)?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "team.invitation_delete",
+        team_target(team_id, "invitation", invitation_id),
+    )?;
+    Ok(())
+}
+
+/// Shows which team an invitation is for, before signing up or accepting it
+#[get("/<code>")]
+pub fn invitation_get(
+    state: &State<ServerState>,
+    code: &str,
+) -> Result<Json<InvitationInfo>, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    let invitation = find_invitation(&datastore, code)?;
+    let team = datastore.get_team(invitation.team_id)?;
+    Ok(Json(InvitationInfo {
+        team_id: team.id,
+        team_name: team.name,
+        expires: invitation.expires,
+    }))
+}
+
+/// Joins the team of an invitation with an existing account
+#[post("/<code>/accept")]
+pub fn invitation_accept(
+    state: &State<ServerState>,
+    code: &str,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<Invitation>, HttpErrorJson> {
+    if !auth.user.email_verified {
+        return Err(HttpErrorJson::new(
+            Status::Forbidden,
+            "The email address has to be verified first".to_string(),
+        ));
+    }
+    let datastore = endpoints_get_lock!(state.datastore);
+    find_invitation(&datastore, code)?;
+    let invitation = datastore.use_invitation(&hash_token(code), auth.id)?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "team.invitation_accept",
+        team_target(invitation.team_id, "invitation", invitation.id),
+    )?;
+    Ok(Json(invitation))
+}
+
+/// Whether anyone can sign up, public so the signup page can tell an invitation is needed
+#[get("/policy")]
+pub fn registration_policy_get(
+    state: &State<ServerState>,
+) -> Result<Json<RegistrationPolicyModel>, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    Ok(Json(RegistrationPolicyModel {
+        open_registration: is_open_registration(&datastore)?,
+    }))
+}
+
+/// Enables or disables signing up without an invitation
+#[put("/policy", data = "<input>")]
+pub fn registration_policy_set(
+    state: &State<ServerState>,
+    input: Json<RegistrationPolicyModel>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    auth.require(Permission::ManageUsers)?;
+    let datastore = endpoints_get_lock!(state.datastore);
+    datastore.set_key_value(POLICY_KEY, &input.open_registration.to_string())?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "policy.set",
+        Some(POLICY_KEY.to_string()),
+    )?;
+    Ok(Json(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{Method, Status};
+    use serde_json::json;
+
+    use crate::endpoints::test_client::{TestServer, PASSWORD};
+
+    fn signup(server: &TestServer, name: &str, invitation: Option<&str>) -> Status {
+        let (status, _) = server.call(
+            Method::Post,
+            "/api/user/signup",
+            None,
+            Some(json!({
+                "email": format!("{name}@example.com"),
+                "password": PASSWORD,
+                "name": name,
+                "lastname": name,
+                "username": name,
+                "invitation": invitation,
+            })),
+        );
+        status
+    }
+
+    #[test]
+    fn test_signup_only_with_invitation() {
+        let server = TestServer::new();
+        let (_, owner) = server.create_user("owner", "team_owner");
+        let team_id = server.create_team(&owner, "team");
+        let (status, _) = server.call(
+            Method::Put,
+            "/api/invitations/policy",
+            Some(&server.admin),
+            Some(json!({"open_registration": false})),
+        );
+        assert_eq!(status, Status::Ok);
+        let (_, policy) = server.call(Method::Get, "/api/invitations/policy", None, None);
+        assert_eq!(policy["open_registration"], false);
+
+        assert_eq!(signup(&server, "alice", None), Status::Forbidden);
+        let (status, invitation) = server.call(
+            Method::Post,
+            &format!("/api/teams/{team_id}/invitations"),
+            Some(&owner),
+            Some(json!({"max_uses": 1})),
+        );
+        assert_eq!(status, Status::Ok, "{invitation}");
+        let code = invitation["code"].as_str().unwrap();
+        let (_, info) = server.call(Method::Get, &format!("/api/invitations/{code}"), None, None);
+        assert_eq!(info["team_name"], "team");
+
+        assert_eq!(signup(&server, "alice", Some(code)), Status::Ok);
+        // The invitation is used up
+        assert_eq!(signup(&server, "bob", Some(code)), Status::BadRequest);
+        assert_eq!(signup(&server, "bob", Some("unknown")), Status::BadRequest);
+        // Members are listed once they verified their email address
+        let token = server.mailed_token("alice@example.com");
+        let (status, _) = server.call(
+            Method::Post,
+            "/api/user/email/verify",
+            None,
+            Some(json!({ "token": token })),
+        );
+        assert_eq!(status, Status::Ok);
+        let (_, team) = server.call(
+            Method::Get,
+            &format!("/api/teams/team/{team_id}"),
+            Some(&owner),
+            None,
+        );
+        let members = team["members"].as_array().unwrap();
+        assert_eq!(members.len(), 1, "{team}");
+        assert_eq!(members[0]["email"], "alice@example.com");
+        assert_eq!(server.login("alice@example.com", PASSWORD).0, Status::Ok);
+        assert_eq!(
+            server.login("bob@example.com", PASSWORD).0,
+            Status::BadRequest
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use std::sync::Mutex;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    errors::{ErrorKind, Result},
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use rocket::http::Status;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use aw_datastore::{Datastore, DatastoreError};
+
+use crate::config::{AuthConfig, JwtKeyConfig};
+use crate::endpoints::HttpErrorJson;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    #[serde(rename = "userId")]
+    pub user_id: i32,
+    pub exp: usize,
+    // Unique id of the token, used to revoke it
+    pub jti: String,
+    // Token generation of the user when the token was issued, see `Datastore::revoke_user_tokens`
+    #[serde(rename = "gen")]
+    pub generation: i64,
+}
+
+impl Claims {
+    pub fn expires(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.exp as i64, 0).unwrap()
+    }
+}
+
+// Key in the key_value table where the key generated on first start is stored
+static GENERATED_KEY: &str = "auth.jwt_key";
+
+/// The set of keys tokens are signed and validated with
+///
+/// New tokens are always signed with the signing key and carry its id in the `kid` header,
+/// tokens signed with any of the other keys stay valid until they expire.
+pub struct JwtKeys {
+    keys: Vec<JwtKeyConfig>,
+    signing_kid: String,
+    expiry: i64,
+}
+
+impl JwtKeys {
+    pub fn new(config: &AuthConfig, keys: Vec<JwtKeyConfig>) -> JwtKeys {
+        assert!(!keys.is_empty(), "At least one JWT key is required");
+        let signing_kid = match &config.jwt_signing_kid {
+            Some(kid) => {
+                if !keys.iter().any(|key| &key.kid == kid) {
+                    panic!("jwt_signing_kid '{kid}' does not match any of the configured jwt_keys");
+                }
+                kid.clone()
+            }
+            None => keys[0].kid.clone(),
+        };
+        JwtKeys {
+            keys,
+            signing_kid,
+            expiry: config.jwt_expiry,
+        }
+    }
+
+    /// Uses the keys from the config, or the key stored in the datastore if none are configured
+    /// (generating and storing one if this is the first start)
+    pub fn load(config: &AuthConfig, datastore: &Mutex<Datastore>) -> JwtKeys {
+        if !config.jwt_keys.is_empty() {
+            return JwtKeys::new(config, config.jwt_keys.clone());
+        }
+
+        let datastore = datastore.lock().unwrap();
+        let key: JwtKeyConfig = match datastore.get_key_value(GENERATED_KEY) {
+            Ok(value) => serde_json::from_str(&value).expect("Stored JWT key is corrupt"),
+            Err(DatastoreError::NoSuchKey(_)) => {
+                info!("No JWT key configured, generating a new one");
+                let key = generate_key();
+                datastore
+                    .set_key_value(GENERATED_KEY, &serde_json::to_string(&key).unwrap())
+                    .expect("Failed to store generated JWT key");
+                datastore
+                    .force_commit()
+                    .expect("Failed to store generated JWT key");
+                key
+            }
+            Err(err) => panic!("Failed to load JWT key: {err:?}"),
+        };
+        JwtKeys::new(config, vec![key])
+    }
+
+    pub fn create_jwt(&self, user_id: i32, generation: i64) -> Result<String> {
+        let key = self.get_key(&self.signing_kid).unwrap();
+        let claims = Claims {
+            user_id,
+            exp: (Utc::now().timestamp() + self.expiry) as usize,
+            jti: uuid::Uuid::new_v4().to_string(),
+            generation,
+        };
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(key.kid.clone());
+        encode(
+            &header,
+            &claims,
+            &EncodingKey::from_secret(key.secret.as_bytes()),
+        )
+    }
+
+    pub fn validate_jwt(&self, token: &str) -> Result<Claims> {
+        let kid = match decode_header(token)?.kid {
+            Some(kid) => kid,
+            None => return Err(ErrorKind::InvalidToken.into()),
+        };
+        let key = match self.get_key(&kid) {
+            Some(key) => key,
+            None => return Err(ErrorKind::InvalidToken.into()),
+        };
+        let decoding_key = DecodingKey::from_secret(key.secret.as_bytes());
+        let validation = Validation::new(Algorithm::HS256);
+        let token_data = decode::<Claims>(token, &decoding_key, &validation)?;
+        Ok(token_data.claims)
+    }
+
+    /// Validates the token and checks that it has not been revoked, by a logout or by ending all
+    /// sessions of the user
+    pub fn authenticate(
+        &self,
+        datastore: &Datastore,
+        token: &str,
+    ) -> std::result::Result<Claims, HttpErrorJson> {
+        let claims = match self.validate_jwt(token) {
+            Ok(claims) => claims,
+            Err(err) => {
+                return Err(HttpErrorJson::new(
+                    Status::Unauthorized,
+                    format!("Invalid token: {err}"),
+                ))
+            }
+        };
+        let generation = match datastore.get_token_generation(claims.user_id) {
+            Ok(generation) => generation,
+            Err(DatastoreError::NoUser()) => {
+                return Err(HttpErrorJson::new(
+                    Status::Unauthorized,
+                    "User does not exist".to_string(),
+                ))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if claims.generation != generation || datastore.is_token_revoked(&claims.jti)? {
+            return Err(HttpErrorJson::new(
+                Status::Unauthorized,
+                "Token has been revoked".to_string(),
+            ));
+        }
+        Ok(claims)
+    }
+
+    pub fn expiry(&self) -> i64 {
+        self.expiry
+    }
+
+    fn get_key(&self, kid: &str) -> Option<&JwtKeyConfig> {
+        self.keys.iter().find(|key| key.kid == kid)
+    }
+}
+
+fn generate_key() -> JwtKeyConfig {
+    JwtKeyConfig {
+        kid: uuid::Uuid::new_v4().to_string(),
+        secret: random_token(),
+    }
+}
+
+/// Generates a random, hex encoded 256-bit token
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Hashes a random token before it is stored in the datastore
+///
+/// The tokens have enough entropy that a plain SHA-256 is sufficient, unlike passwords.
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JwtKeys;
+    use crate::config::{AuthConfig, JwtKeyConfig};
+
+    fn key(kid: &str) -> JwtKeyConfig {
+        JwtKeyConfig {
+            kid: kid.to_string(),
+            secret: format!("{kid}-secret"),
+        }
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let mut config = AuthConfig::default();
+        let old_keys = JwtKeys::new(&config, vec![key("old")]);
+        let token = old_keys.create_jwt(3, 0).unwrap();
+
+        // Rotate: sign with the new key, but keep accepting the old one
+        config.jwt_signing_kid = Some("new".to_string());
+        let keys = JwtKeys::new(&config, vec![key("old"), key("new")]);
+        assert_eq!(keys.validate_jwt(&token).unwrap().user_id, 3);
+        let new_token = keys.create_jwt(4, 0).unwrap();
+        assert_eq!(keys.validate_jwt(&new_token).unwrap().user_id, 4);
+        assert!(old_keys.validate_jwt(&new_token).is_err());
+
+        // Retire the old key
+        let keys = JwtKeys::new(&config, vec![key("new")]);
+        assert!(keys.validate_jwt(&token).is_err());
+    }
+
+    #[test]
+    fn test_expired_token() {
+        let config = AuthConfig {
+            jwt_expiry: -120,
+            ..AuthConfig::default()
+        };
+        let keys = JwtKeys::new(&config, vec![key("a")]);
+        let token = keys.create_jwt(1, 0).unwrap();
+        assert!(keys.validate_jwt(&token).is_err());
+    }
+}
@@ -46,14 +46,26 @@ pub struct ServerState {
 
 #[macro_use]
 mod util;
+mod api_key;
+mod audit;
+mod auth;
 mod bucket;
 mod cors;
 mod export;
 mod hostcheck;
 mod import;
+mod invitation;
+mod jwt;
+mod oidc;
 mod query;
+mod report;
 mod settings;
 mod team;
+#[cfg(test)]
+mod test_client;
+mod throttle;
+mod totp;
+mod two_factor;
 mod user;
 
 pub use util::HttpErrorJson;
@@ -137,12 +149,18 @@ pub fn build_rocket(server_state: ServerState, config: AWConfig) -> rocket::Rock
     let cors = cors::cors(&config);
     let hostcheck = hostcheck::HostCheck::new(&config);
     let custom_static = config.custom_static.clone();
+    let jwt_keys = jwt::JwtKeys::load(&config.auth, &server_state.datastore);
+    let notifier = crate::notifier::from_config(&config.notifier);
+    let oidc = oidc::OidcProvider::new(&config);
 
     let mut rocket = rocket::custom(config.to_rocket_config())
         .attach(cors.clone())
         .attach(hostcheck)
         .manage(cors)
         .manage(server_state)
+        .manage(jwt_keys)
+        .manage(notifier)
+        .manage(oidc)
         .manage(config)
         .mount(
             "/",
@@ -193,7 +211,50 @@ pub fn build_rocket(server_state: ServerState, config: AWConfig) -> rocket::Rock
         )
         .mount(
             "/api/user",
-            routes![user::login, user::signup, user::getUser, user::getAllUsers],
+            routes![
+                user::login,
+                user::loginTwoFactor,
+                user::refresh,
+                user::logout,
+                user::signup,
+                user::getUser,
+                user::changePassword,
+                user::requestPasswordReset,
+                user::confirmPasswordReset,
+                user::getOutbox,
+                user::getLockouts,
+                user::clearLockout,
+                user::getAllUsers,
+                user::setRole,
+                user::updateProfile,
+                user::verifyEmail,
+                user::resendEmailVerification,
+                user::deleteAccount,
+                user::setActive,
+                user::deleteUser,
+                user::getUserDirectory,
+                user::createUser,
+                user::resetUserPassword
+            ],
+        )
+        .mount(
+            "/api/user/keys",
+            routes![
+                api_key::api_keys_get,
+                api_key::api_key_new,
+                api_key::api_key_delete
+            ],
+        )
+        .mount(
+            "/api/user/2fa",
+            routes![
+                two_factor::two_factor_enroll,
+                two_factor::two_factor_confirm,
+                two_factor::two_factor_disable,
+                two_factor::two_factor_reset,
+                two_factor::two_factor_policy_get,
+                two_factor::two_factor_policy_set
+            ],
         )
         .mount(
             "/api/teams",
@@ -201,14 +262,41 @@ pub fn build_rocket(server_state: ServerState, config: AWConfig) -> rocket::Rock
                 team::getOwnerTeams,
                 team::addTeam,
                 team::getTeam,
+                team::updateTeam,
+                team::transferTeam,
+                team::deleteTeam,
                 team::addMembers,
                 team::removeMember,
+                team::setMemberRole,
+                team::setMemberSchedule,
                 team::getUserTeams,
                 team::addConfiguration,
-                team::getTeamConfiguration
+                team::getTeamConfiguration,
+                team::getConfigurationRevisions,
+                team::diffConfigurationRevisions,
+                team::rollbackConfiguration,
+                invitation::team_invitations_get,
+                invitation::team_invitation_new,
+                invitation::team_invitation_delete,
+                report::team_report
+            ],
+        )
+        .mount(
+            "/api/invitations",
+            routes![
+                invitation::invitation_get,
+                invitation::invitation_accept,
+                invitation::registration_policy_get,
+                invitation::registration_policy_set
             ],
         )
-        .mount("/", rocket_cors::catch_all_options_routes());
+        .mount(
+            "/api/oidc",
+            routes![oidc::oidc_info, oidc::oidc_authorize, oidc::oidc_callback],
+        )
+        .mount("/api/audit", routes![audit::audit_get, audit::audit_export])
+        .mount("/", rocket_cors::catch_all_options_routes())
+        .register("/", catchers![auth::unauthorized, auth::forbidden]);
 
     // for each custom static directory, mount it at the given name
     for (name, dir) in custom_static {
@@ -2,19 +2,30 @@ use rocket::http::Status;
 use rocket::serde::json::{json, Json, Value};
 use rocket::State;
 
-use aw_models::Query;
+use aw_models::{Permission, Query};
+use aw_query::QueryError;
 
+use crate::endpoints::auth::AuthenticatedUser;
 use crate::endpoints::{HttpErrorJson, ServerState};
 
 #[post("/", data = "<query_req>", format = "application/json")]
-pub fn query(query_req: Json<Query>, state: &State<ServerState>) -> Result<Value, HttpErrorJson> {
+pub fn query(
+    query_req: Json<Query>,
+    state: &State<ServerState>,
+    auth: AuthenticatedUser,
+) -> Result<Value, HttpErrorJson> {
+    auth.require(Permission::Query)?;
     let query_code = query_req.0.query.join("\n");
     let intervals = &query_req.0.timeperiods;
     let mut results = Vec::new();
+    let caller = auth.query_caller();
     let datastore = endpoints_get_lock!(state.datastore);
     for interval in intervals {
-        let result = match aw_query::query(&query_code, interval, &datastore) {
+        let result = match aw_query::query_as(&query_code, interval, &datastore, caller) {
             Ok(data) => data,
+            Err(QueryError::AccessDenied(message)) => {
+                return Err(HttpErrorJson::new(Status::Forbidden, message));
+            }
             Err(e) => {
                 warn!("Query failed: {:?}", e);
                 return Err(HttpErrorJson::new(
@@ -0,0 +1,526 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde_json::json;
+
+use aw_models::{
+    AppMatcher, AppTime, CategoryTime, ConfigurationHistory, Event, MemberReport, Permission,
+    PrivacyLevel, ReportGranularity, ReportPeriod, Role, TeamReport, WorkSchedule,
+};
+use aw_query::load_categories;
+use aw_transform::classify::{self, Rule};
+
+use crate::endpoints::auth::AuthenticatedUser;
+use crate::endpoints::util::parse_time;
+use crate::endpoints::{HttpErrorJson, ServerState};
+
+const DEFAULT_REPORT_DAYS: i64 = 7;
+const MAX_REPORT_DAYS: i64 = 366;
+
+/// Compiles the app rules of every revision, `None` being the default configuration which is in
+/// effect before the first revision
+fn compile_matchers(
+    history: &ConfigurationHistory,
+) -> Result<HashMap<Option<i32>, AppMatcher>, HttpErrorJson> {
+    let compile = |config: &aw_models::TeamConfiguration| {
+        config
+            .matcher()
+            .map_err(|msg| HttpErrorJson::new(Status::InternalServerError, msg))
+    };
+    let mut matchers = HashMap::new();
+    matchers.insert(None, compile(&Default::default())?);
+    for revision in history.revisions() {
+        matchers.insert(Some(revision.revision), compile(&revision.configuration)?);
+    }
+    Ok(matchers)
+}
+
+/// Splits the range at the start of every day or week in the time zone of the schedule
+fn report_periods(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    granularity: ReportGranularity,
+    schedule: &WorkSchedule,
+) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    let mut periods = Vec::new();
+    let mut period_start = start;
+    while period_start < end {
+        let next = match granularity {
+            ReportGranularity::Day => schedule.next_day(period_start),
+            ReportGranularity::Week => schedule.next_week(period_start),
+        };
+        let period_end = next.min(end);
+        periods.push((period_start, period_end));
+        period_start = period_end;
+    }
+    periods
+}
+
+/// Cuts the events down to the working hours of the schedule
+fn clip_to_working_hours(
+    events: Vec<Event>,
+    schedule: &WorkSchedule,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Vec<Event> {
+    let working_periods = schedule
+        .working_periods(start, end)
+        .into_iter()
+        .map(|(from, to)| Event::new(from, to - from, Default::default(), 0))
+        .collect();
+    aw_transform::filter_period_intersect(events, working_periods)
+}
+
+/// Removes the apps and categories of the periods which the privacy level doesn't show
+fn restrict_periods(periods: &mut [ReportPeriod], level: PrivacyLevel) {
+    for period in periods {
+        if level >= PrivacyLevel::CategoryOnly {
+            period.apps.clear();
+        }
+        if level >= PrivacyLevel::TotalsOnly {
+            period.categories.clear();
+        }
+    }
+}
+
+fn data_str<'a>(event: &'a Event, key: &str) -> &'a str {
+    event
+        .data
+        .get(key)
+        .and_then(|value| value.as_str())
+        .unwrap_or("")
+}
+
+/// Sums up the active time of a member per period
+///
+/// Window events only count while the member was not AFK and if their app was tracked by the
+/// configuration in effect when they started. Members without an AFK bucket are never AFK.
+fn member_periods(
+    window_events: Vec<Event>,
+    afk_events: Option<Vec<Event>>,
+    history: &ConfigurationHistory,
+    matchers: &HashMap<Option<i32>, AppMatcher>,
+    categories: &[(Vec<String>, Rule)],
+    periods: &[(DateTime<Utc>, DateTime<Utc>)],
+) -> Vec<ReportPeriod> {
+    let events = match afk_events {
+        Some(afk_events) => {
+            let not_afk = aw_transform::filter_keyvals(afk_events, "status", &[json!("not-afk")]);
+            aw_transform::filter_period_intersect(window_events, not_afk)
+        }
+        None => window_events,
+    };
+    let events: Vec<Event> = events
+        .into_iter()
+        .filter(|event| {
+            let revision = history
+                .revision_at(event.timestamp)
+                .map(|revision| revision.revision);
+            matchers[&revision].matches(data_str(event, "app"), data_str(event, "title"))
+        })
+        .collect();
+    let events = classify::categorize(events, categories);
+
+    let mut totals: Vec<(f64, HashMap<String, f64>, HashMap<Vec<String>, f64>)> =
+        vec![Default::default(); periods.len()];
+    for event in &events {
+        let event_start = event.timestamp;
+        let event_end = event.calculate_endtime();
+        let category: Vec<String> = event
+            .data
+            .get("$category")
+            .and_then(|category| serde_json::from_value(category.clone()).ok())
+            .unwrap_or_default();
+        let first = periods.partition_point(|(_, end)| *end <= event_start);
+        for (index, (start, end)) in periods.iter().enumerate().skip(first) {
+            if *start >= event_end {
+                break;
+            }
+            let overlap = event_end.min(*end) - event_start.max(*start);
+            let seconds = overlap.num_nanoseconds().unwrap() as f64 / 1_000_000_000.0;
+            let (active, apps, categories) = &mut totals[index];
+            *active += seconds;
+            *apps.entry(data_str(event, "app").to_string()).or_default() += seconds;
+            *categories.entry(category.clone()).or_default() += seconds;
+        }
+    }
+
+    periods
+        .iter()
+        .zip(totals)
+        .map(|((start, end), (active, apps, categories))| {
+            let mut apps: Vec<AppTime> = apps
+                .into_iter()
+                .map(|(app, duration)| AppTime { app, duration })
+                .collect();
+            apps.sort_by(|a, b| b.duration.total_cmp(&a.duration).then(a.app.cmp(&b.app)));
+            let mut categories: Vec<CategoryTime> = categories
+                .into_iter()
+                .map(|(category, duration)| CategoryTime { category, duration })
+                .collect();
+            categories.sort_by(|a, b| {
+                b.duration
+                    .total_cmp(&a.duration)
+                    .then(a.category.cmp(&b.category))
+            });
+            ReportPeriod {
+                start: *start,
+                end: *end,
+                active,
+                apps,
+                categories,
+            }
+        })
+        .collect()
+}
+
+/// Reports the active time of every member of a team per day or week
+///
+/// The range defaults to the last week. Days and weeks start at midnight in the time zone of each
+/// member, with `working_hours` only the activity within their working hours is counted. Viewers
+/// are left out, they only observe the team. The apps and categories of other members are shown
+/// as far as the privacy level of the team allows.
+// Ranked below /team/<id> of the team routes, which it overlaps
+#[get(
+    "/<team_id>/report?<start>&<end>&<granularity>&<working_hours>",
+    rank = 2
+)]
+pub fn team_report(
+    state: &State<ServerState>,
+    team_id: i32,
+    start: Option<&str>,
+    end: Option<&str>,
+    granularity: Option<&str>,
+    working_hours: Option<bool>,
+    auth: AuthenticatedUser,
+) -> Result<Json<TeamReport>, HttpErrorJson> {
+    let end = parse_time("end", end)?.unwrap_or_else(Utc::now);
+    let start =
+        parse_time("start", start)?.unwrap_or_else(|| end - Duration::days(DEFAULT_REPORT_DAYS));
+    if end <= start {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "The end of the report must be after its start".to_string(),
+        ));
+    }
+    if end - start > Duration::days(MAX_REPORT_DAYS) {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            format!("A report can span at most {MAX_REPORT_DAYS} days"),
+        ));
+    }
+    let granularity = match granularity.unwrap_or("day") {
+        "day" => ReportGranularity::Day,
+        "week" => ReportGranularity::Week,
+        other => {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                format!("Unknown granularity '{other}', expected 'day' or 'week'"),
+            ))
+        }
+    };
+
+    let datastore = endpoints_get_lock!(state.datastore);
+    auth.require_team(&datastore, team_id, Permission::ReadUserData)?;
+    let history = ConfigurationHistory::new(datastore.get_configuration_revisions(team_id)?);
+    let matchers = compile_matchers(&history)?;
+    let categories = load_categories(&datastore)?;
+    let configuration = datastore.get_configuration(team_id)?;
+    let working_hours = working_hours.unwrap_or(false);
+
+    let mut members = Vec::new();
+    for member in datastore.get_team_members(team_id)? {
+        if member.role == Role::Viewer {
+            continue;
+        }
+        let schedule = WorkSchedule::new(&configuration, member.schedule.as_ref())
+            .map_err(|msg| HttpErrorJson::new(Status::InternalServerError, msg))?;
+        let mut window_events = Vec::new();
+        let mut afk_events: Option<Vec<Event>> = None;
+        for bucket in datastore.get_buckets(member.user_id)?.into_values() {
+            let events = match bucket._type.as_str() {
+                "currentwindow" => &mut window_events,
+                "afkstatus" => afk_events.get_or_insert_with(Vec::new),
+                _ => continue,
+            };
+            events.extend(datastore.get_user_events(
+                bucket.bid,
+                Some(start),
+                Some(end),
+                None,
+                Some(team_id),
+            )?);
+        }
+        if working_hours {
+            window_events = clip_to_working_hours(window_events, &schedule, start, end);
+        }
+        let privacy_level = match auth.privacy_filter(&datastore, member.user_id)? {
+            Some(filter) => filter.level(team_id),
+            None => PrivacyLevel::RawEvents,
+        };
+        let periods = report_periods(start, end, granularity, &schedule);
+        let mut periods = member_periods(
+            window_events,
+            afk_events,
+            &history,
+            &matchers,
+            &categories,
+            &periods,
+        );
+        restrict_periods(&mut periods, privacy_level);
+        members.push(MemberReport {
+            user_id: member.user_id,
+            name: member.name,
+            lastname: member.lastname,
+            time_zone: schedule.time_zone.name().to_string(),
+            privacy_level,
+            periods,
+        });
+    }
+
+    Ok(Json(TeamReport {
+        team_id,
+        start,
+        end,
+        granularity,
+        working_hours,
+        members,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{DateTime, Duration, Utc};
+    use serde_json::json;
+
+    use aw_models::{
+        AppRule, AppTime, CategoryTime, ConfigurationHistory, Event, PrivacyLevel,
+        ReportGranularity, ReportPeriod, TeamConfiguration, TeamConfigurationRevision,
+        WorkSchedule, WorkingHours,
+    };
+    use aw_transform::classify::{RegexRule, Rule};
+
+    use super::{
+        clip_to_working_hours, compile_matchers, member_periods, report_periods, restrict_periods,
+    };
+
+    fn time(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn schedule(time_zone: &str) -> WorkSchedule {
+        let config = TeamConfiguration {
+            time_zone: time_zone.to_string(),
+            ..Default::default()
+        };
+        WorkSchedule::new(&config, None).unwrap()
+    }
+
+    fn event(start: &str, seconds: i64, data: serde_json::Value) -> Event {
+        Event::new(
+            time(start),
+            Duration::seconds(seconds),
+            data.as_object().unwrap().clone(),
+            1,
+        )
+    }
+
+    #[test]
+    fn test_report_periods() {
+        // 2024-01-03 is a Wednesday
+        let periods = report_periods(
+            time("2024-01-03T12:00:00Z"),
+            time("2024-01-05T06:00:00Z"),
+            ReportGranularity::Day,
+            &schedule("UTC"),
+        );
+        assert_eq!(
+            periods,
+            vec![
+                (time("2024-01-03T12:00:00Z"), time("2024-01-04T00:00:00Z")),
+                (time("2024-01-04T00:00:00Z"), time("2024-01-05T00:00:00Z")),
+                (time("2024-01-05T00:00:00Z"), time("2024-01-05T06:00:00Z")),
+            ]
+        );
+        let periods = report_periods(
+            time("2024-01-03T12:00:00Z"),
+            time("2024-01-16T00:00:00Z"),
+            ReportGranularity::Week,
+            &schedule("UTC"),
+        );
+        assert_eq!(
+            periods,
+            vec![
+                (time("2024-01-03T12:00:00Z"), time("2024-01-08T00:00:00Z")),
+                (time("2024-01-08T00:00:00Z"), time("2024-01-15T00:00:00Z")),
+                (time("2024-01-15T00:00:00Z"), time("2024-01-16T00:00:00Z")),
+            ]
+        );
+        // Days start at midnight in the time zone of the member
+        let periods = report_periods(
+            time("2024-01-03T12:00:00Z"),
+            time("2024-01-05T06:00:00Z"),
+            ReportGranularity::Day,
+            &schedule("Asia/Tokyo"),
+        );
+        assert_eq!(
+            periods,
+            vec![
+                (time("2024-01-03T12:00:00Z"), time("2024-01-03T15:00:00Z")),
+                (time("2024-01-03T15:00:00Z"), time("2024-01-04T15:00:00Z")),
+                (time("2024-01-04T15:00:00Z"), time("2024-01-05T06:00:00Z")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clip_to_working_hours() {
+        let config = TeamConfiguration {
+            time_zone: "America/New_York".to_string(),
+            working_hours: Some(WorkingHours {
+                days: vec![chrono::Weekday::Wed],
+                start: chrono::NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                end: chrono::NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+            }),
+            ..Default::default()
+        };
+        let schedule = WorkSchedule::new(&config, None).unwrap();
+        // From 8:00 to 10:00 in New York, of which the first hour is before work
+        let events = vec![event(
+            "2024-01-03T13:00:00Z",
+            7200,
+            json!({"app": "Code", "title": "main.rs"}),
+        )];
+        let events = clip_to_working_hours(
+            events,
+            &schedule,
+            time("2024-01-01T00:00:00Z"),
+            time("2024-01-08T00:00:00Z"),
+        );
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].timestamp, time("2024-01-03T14:00:00Z"));
+        assert_eq!(events[0].duration, Duration::seconds(3600));
+    }
+
+    #[test]
+    fn test_member_periods() {
+        // All apps are tracked from 08:00 on, only Code from 10:00 on
+        let revision = |revision, created, app: &str| TeamConfigurationRevision {
+            revision,
+            author: None,
+            created: time(created),
+            restored_from: None,
+            configuration: TeamConfiguration {
+                tracked_apps: vec![AppRule {
+                    app: app.to_string(),
+                    title: None,
+                }],
+                ..Default::default()
+            },
+        };
+        let history = ConfigurationHistory::new(vec![
+            revision(1, "2024-01-03T08:00:00Z", ".*"),
+            revision(2, "2024-01-03T10:00:00Z", "^Code$"),
+        ]);
+        let matchers = compile_matchers(&history).unwrap();
+        let categories = vec![(
+            vec!["Work".to_string(), "Programming".to_string()],
+            Rule::Regex(RegexRule::new("\\.rs", false).unwrap()),
+        )];
+        let periods = report_periods(
+            time("2024-01-03T00:00:00Z"),
+            time("2024-01-05T00:00:00Z"),
+            ReportGranularity::Day,
+            &schedule("UTC"),
+        );
+        let window_events = vec![
+            // Before the first configuration no app is tracked
+            event(
+                "2024-01-03T07:00:00Z",
+                600,
+                json!({"app": "Slack", "title": "chat"}),
+            ),
+            event(
+                "2024-01-03T09:00:00Z",
+                600,
+                json!({"app": "Slack", "title": "chat"}),
+            ),
+            event(
+                "2024-01-03T11:00:00Z",
+                600,
+                json!({"app": "Slack", "title": "chat"}),
+            ),
+            // Crosses midnight, the member is AFK for its first 5 minutes
+            event(
+                "2024-01-03T23:50:00Z",
+                1200,
+                json!({"app": "Code", "title": "main.rs"}),
+            ),
+        ];
+        let afk_events = vec![
+            event("2024-01-03T07:00:00Z", 600, json!({"status": "not-afk"})),
+            event("2024-01-03T09:00:00Z", 600, json!({"status": "not-afk"})),
+            event("2024-01-03T11:00:00Z", 600, json!({"status": "not-afk"})),
+            event("2024-01-03T23:50:00Z", 300, json!({"status": "afk"})),
+            event("2024-01-03T23:55:00Z", 900, json!({"status": "not-afk"})),
+        ];
+        let result = member_periods(
+            window_events,
+            Some(afk_events),
+            &history,
+            &matchers,
+            &categories,
+            &periods,
+        );
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].active, 900.0);
+        let apps: HashMap<_, _> = result[0]
+            .apps
+            .iter()
+            .map(|app| (app.app.as_str(), app.duration))
+            .collect();
+        assert_eq!(apps, HashMap::from([("Slack", 600.0), ("Code", 300.0)]));
+        assert_eq!(result[1].active, 600.0);
+        assert_eq!(
+            result[1].categories[0].category,
+            vec!["Work".to_string(), "Programming".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_restrict_periods() {
+        let period = ReportPeriod {
+            start: time("2024-01-01T00:00:00Z"),
+            end: time("2024-01-02T00:00:00Z"),
+            active: 600.0,
+            apps: vec![AppTime {
+                app: "Code".to_string(),
+                duration: 600.0,
+            }],
+            categories: vec![CategoryTime {
+                category: vec!["Work".to_string()],
+                duration: 600.0,
+            }],
+        };
+        let restricted = |level| {
+            let mut periods = vec![period.clone()];
+            restrict_periods(&mut periods, level);
+            periods.pop().unwrap()
+        };
+
+        assert_eq!(restricted(PrivacyLevel::RawEvents), period);
+        assert_eq!(restricted(PrivacyLevel::AppOnly), period);
+        let categories = restricted(PrivacyLevel::CategoryOnly);
+        assert!(categories.apps.is_empty());
+        assert_eq!(categories.categories, period.categories);
+        let totals = restricted(PrivacyLevel::TotalsOnly);
+        assert!(totals.apps.is_empty() && totals.categories.is_empty());
+        assert_eq!(totals.active, 600.0);
+    }
+}
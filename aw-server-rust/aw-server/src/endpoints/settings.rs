@@ -1,11 +1,15 @@
+use crate::endpoints::audit;
+use crate::endpoints::auth::AuthenticatedUser;
 use crate::endpoints::ServerState;
 use rocket::http::Status;
 use rocket::serde::json::Json;
 use rocket::State;
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::MutexGuard;
 
 use aw_datastore::{Datastore, DatastoreError};
+use aw_models::Permission;
 
 use crate::endpoints::HttpErrorJson;
 
@@ -24,7 +28,9 @@ fn parse_key(key: String) -> Result<String, HttpErrorJson> {
 #[get("/")]
 pub fn settings_get(
     state: &State<ServerState>,
+    auth: AuthenticatedUser,
 ) -> Result<Json<HashMap<String, serde_json::Value>>, HttpErrorJson> {
+    auth.require(Permission::ReadSettings)?;
     let datastore = endpoints_get_lock!(state.datastore);
     let queryresults = match datastore.get_key_values("settings.%") {
         Ok(result) => Ok(result),
@@ -51,7 +57,9 @@ pub fn settings_get(
 pub fn setting_get(
     state: &State<ServerState>,
     key: String,
+    auth: AuthenticatedUser,
 ) -> Result<Json<serde_json::Value>, HttpErrorJson> {
+    auth.require(Permission::ReadSettings)?;
     let setting_key = parse_key(key)?;
     let datastore = endpoints_get_lock!(state.datastore);
 
@@ -67,7 +75,10 @@ pub fn setting_set(
     state: &State<ServerState>,
     key: String,
     value: Json<serde_json::Value>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
 ) -> Result<Status, HttpErrorJson> {
+    auth.require(Permission::WriteSettings)?;
     let setting_key = parse_key(key)?;
     let value_str = match serde_json::to_string(&value.0) {
         Ok(value) => value,
@@ -83,20 +94,41 @@ pub fn setting_set(
     let result = datastore.set_key_value(&setting_key, &value_str);
 
     match result {
-        Ok(_) => Ok(Status::Created),
+        Ok(_) => {
+            audit::record(
+                &datastore,
+                Some(auth.id),
+                ip,
+                "settings.set",
+                Some(setting_key),
+            )?;
+            Ok(Status::Created)
+        }
         Err(err) => Err(err.into()),
     }
 }
 
 #[delete("/<key>")]
-pub fn setting_delete(state: &State<ServerState>, key: String) -> Result<(), HttpErrorJson> {
+pub fn setting_delete(
+    state: &State<ServerState>,
+    key: String,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<(), HttpErrorJson> {
+    auth.require(Permission::WriteSettings)?;
     let setting_key = parse_key(key)?;
 
     let datastore = endpoints_get_lock!(state.datastore);
     let result = datastore.delete_key_value(&setting_key);
 
     match result {
-        Ok(_) => Ok(()),
+        Ok(_) => audit::record(
+            &datastore,
+            Some(auth.id),
+            ip,
+            "settings.delete",
+            Some(setting_key),
+        ),
         Err(err) => Err(err.into()),
     }
 }
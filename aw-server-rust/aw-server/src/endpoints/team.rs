@@ -1,15 +1,15 @@
-use jsonwebtoken::{decode, encode, Header, Validation};
-use jwt::{create_jwt, validate_jwt, Claims};
-use rocket::outcome::Outcome;
-use rocket::request::{self, FromRequest, Request};
 use rocket::response::status::BadRequest;
 
+use crate::endpoints::audit::{self, target, team_target};
+use crate::endpoints::auth::{AuthenticatedUser, BucketWriter};
 use crate::endpoints::{HttpErrorJson, ServerState};
+use aw_datastore::Datastore;
+use aw_models::{MemberSchedule, Permission, Role};
 use aw_models::TeamRequestModel;
 use aw_models::TeamResponseModel;
 use aw_models::User;
 use aw_models::{Team, TeamUserModel};
-use aw_models::{TeamConfiguration, TeamDetailModel};
+use aw_models::{TeamConfiguration, TeamConfigurationDiff, TeamConfigurationRevision, TeamDetailModel};
 use rocket::http::Status;
 use rocket::serde::json::Json;
 use rocket::State;
@@ -18,7 +18,7 @@ use serde::Serialize;
 
 use std::array;
 use std::collections::HashMap;
-mod jwt;
+use std::net::IpAddr;
 
 #[derive(Deserialize, Clone, Copy)]
 pub struct TeamModel<'r> {
@@ -26,49 +26,23 @@ pub struct TeamModel<'r> {
     description: &'r str,
 }
 
-#[derive(Deserialize, Clone)]
-pub struct Token(String);
-#[rocket::async_trait]
-impl<'r> FromRequest<'r> for Token {
-    type Error = ();
-
-    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
-        // Look for the "Authorization" header
-        if let Some(token_header) = request.headers().get_one("Authorization") {
-            if let Some(token) = token_header.strip_prefix("Bearer ") {
-                return Outcome::Success(Token(token.to_string()));
-            }
-        }
-
-        Outcome::Forward(Status::Unauthorized)
-    }
-}
-
 #[get("/")]
 pub fn getOwnerTeams(
     state: &State<ServerState>,
-    token: Token,
+    auth: AuthenticatedUser,
 ) -> Result<Json<Vec<TeamResponseModel>>, HttpErrorJson> {
-    let tokenString = token.clone().0;
-    let userId = match validate_jwt(&tokenString) {
-        Ok(userId) => userId,
-        Err(_) => todo!(),
-    };
+    auth.require(Permission::ViewTeam)?;
     let datastore = endpoints_get_lock!(state.datastore);
+    let userId = auth.id;
     let mut response: Vec<TeamResponseModel> = Vec::new();
-    match datastore.get_owner_teams(userId) {
-        Ok(teams) => {
-            for team in teams {
-                let count = datastore.get_team_members_count(team.id)?;
-                response.push(TeamResponseModel {
-                    id: team.id,
-                    name: team.name,
-                    description: team.description,
-                    count,
-                })
-            }
-        }
-        Err(_) => todo!(),
+    for team in datastore.get_owner_teams(userId)? {
+        let count = datastore.get_team_members_count(team.id)?;
+        response.push(TeamResponseModel {
+            id: team.id,
+            name: team.name,
+            description: team.description,
+            count,
+        })
     }
     Ok(Json(response))
 }
@@ -77,26 +51,33 @@ pub fn getOwnerTeams(
 pub fn addTeam(
     state: &State<ServerState>,
     team: Json<TeamModel>,
-    token: Token,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
 ) -> Result<Json<bool>, HttpErrorJson> {
+    auth.require(Permission::CreateTeam)?;
     let name = team.name.to_string();
     if name.is_empty() {
         let err_msg = format!("No name was provided");
         return Err(HttpErrorJson::new(Status::BadRequest, err_msg));
     }
-    let tokenString = token.clone().0;
-    let ownerId = match validate_jwt(&tokenString) {
-        Ok(ownerId) => ownerId,
-        Err(_) => todo!(),
-    };
     let datastore = endpoints_get_lock!(state.datastore);
+    let ownerId = auth.id;
     let teamModel = TeamRequestModel {
         description: team.description.to_string(),
         name: team.name.to_string(),
         ownerId: ownerId,
     };
     match datastore.add_team(teamModel, ownerId) {
-        Ok(team) => Ok(Json(true)),
+        Ok(team) => {
+            audit::record(
+                &datastore,
+                Some(auth.id),
+                ip,
+                "team.create",
+                target("team", team.id),
+            )?;
+            Ok(Json(true))
+        }
         Err(_) => Ok(Json(false)),
     }
     // match datastore.get_user_by_email(input.email.to_string()) {
@@ -128,27 +109,20 @@ pub fn addTeam(
 #[get("/team/<id>")]
 pub fn getTeam(
     state: &State<ServerState>,
-    token: Token,
+    auth: AuthenticatedUser,
     id: i32,
 ) -> Result<Json<TeamDetailModel>, HttpErrorJson> {
-    // let tokenString = token.clone().0;
-    // let userId = match validate_jwt(&tokenString) {
-    //     Ok(userId) => userId,
-    //     Err(_) => todo!(),
-    // };
     let datastore = endpoints_get_lock!(state.datastore);
-    let members = datastore.get_team_members(id).unwrap();
-    let configuration = match datastore.get_configuration(id){
-        Ok(config)=>config.apps,
-        Err(err) => Vec::new()
-    };
+    auth.require_team(&datastore, id, Permission::ViewTeam)?;
+    let members = datastore.get_team_members(id)?;
+    let configuration = datastore.get_configuration(id)?;
     match datastore.get_team(id) {
         Ok(team) => Ok(Json(TeamDetailModel {
             id: team.id,
             description: team.description,
             name: team.name,
             members: members,
-            apps: configuration
+            configuration: configuration
         })),
         Err(err) => Err(HttpErrorJson::new(
             Status::BadRequest,
@@ -157,24 +131,140 @@ pub fn getTeam(
     }
 }
 
+/// Renames a team and changes its description
+#[put("/<team_id>", data = "<team>")]
+pub fn updateTeam(
+    state: &State<ServerState>,
+    team_id: i32,
+    team: Json<TeamModel>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    if team.name.is_empty() {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "No name was provided".to_string(),
+        ));
+    }
+    let datastore = endpoints_get_lock!(state.datastore);
+    auth.require_team(&datastore, team_id, Permission::ManageTeam)?;
+    if !datastore.update_team(team_id, team.name, team.description)? {
+        return Err(no_team_error(team_id));
+    }
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "team.update",
+        target("team", team_id),
+    )?;
+    Ok(Json(true))
+}
+
+/// Makes a member the owner of the team, the previous owner stays in the team as a manager
+#[put("/<team_id>/owner", data = "<owner_id>")]
+pub fn transferTeam(
+    state: &State<ServerState>,
+    team_id: i32,
+    owner_id: Json<i32>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    let owner_id = owner_id.0;
+    let datastore = endpoints_get_lock!(state.datastore);
+    auth.require_team(&datastore, team_id, Permission::TransferTeam)?;
+    let is_member = datastore
+        .get_team_members(team_id)?
+        .iter()
+        .any(|member| member.user_id == owner_id);
+    if !is_member {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            format!("User {owner_id} is not a member of the team"),
+        ));
+    }
+    datastore.transfer_team(team_id, owner_id)?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "team.transfer",
+        team_target(team_id, "user", owner_id),
+    )?;
+    Ok(Json(true))
+}
+
+/// Deletes a team with its memberships, configuration and invitations
+///
+/// The events of the members are kept, they are only no longer tagged with the team.
+#[delete("/<team_id>")]
+pub fn deleteTeam(
+    state: &State<ServerState>,
+    team_id: i32,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    auth.require_team(&datastore, team_id, Permission::DeleteTeam)?;
+    if !datastore.delete_team(team_id)? {
+        return Err(no_team_error(team_id));
+    }
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "team.delete",
+        target("team", team_id),
+    )?;
+    Ok(Json(true))
+}
+
+fn no_team_error(team_id: i32) -> HttpErrorJson {
+    HttpErrorJson::new(Status::NotFound, format!("No team with id {team_id}"))
+}
+
+/// The user a membership of the team belongs to, member routes take the id of the membership
+fn member_user(datastore: &Datastore, team_id: i32, member_id: i32) -> Result<i32, HttpErrorJson> {
+    match datastore.get_member_user(team_id, member_id)? {
+        Some(user_id) => Ok(user_id),
+        None => Err(HttpErrorJson::new(
+            Status::NotFound,
+            format!("No member with id {member_id} in team {team_id}"),
+        )),
+    }
+}
+
 #[post("/<teamId>/members", data = "<members>")]
 pub fn addMembers(
     state: &State<ServerState>,
     teamId: i32,
     members: Json<Vec<i32>>,
-    token: Token,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
 ) -> Result<Json<bool>, HttpErrorJson> {
-    let tokenString = token.clone().0;
-    let ownerId = match validate_jwt(&tokenString) {
-        Ok(ownerId) => ownerId,
-        Err(_) => todo!(),
-    };
     let datastore = endpoints_get_lock!(state.datastore);
+    auth.require_team(&datastore, teamId, Permission::ManageTeam)?;
     let memberIds = members.0;
-    match datastore.add_members(teamId, memberIds) {
-        Ok(team) => Ok(Json(true)),
-        Err(_) => Ok(Json(false)),
+    // Users who have not verified their email address can not join teams
+    for memberId in &memberIds {
+        if !datastore.get_user(*memberId)?.email_verified {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                format!("User {} has not verified their email address", memberId),
+            ));
+        }
+    }
+    datastore.add_members(teamId, memberIds.clone())?;
+    for member_id in memberIds {
+        audit::record(
+            &datastore,
+            Some(auth.id),
+            ip,
+            "team.member_add",
+            team_target(teamId, "user", member_id),
+        )?;
     }
+    Ok(Json(true))
 }
 
 #[delete("/<teamId>/member/<memberId>")]
@@ -182,31 +272,92 @@ pub fn removeMember(
     state: &State<ServerState>,
     teamId: i32,
     memberId: i32,
-    token: Token,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
 ) -> Result<Json<bool>, HttpErrorJson> {
-    let tokenString = token.clone().0;
-    let ownerId = match validate_jwt(&tokenString) {
-        Ok(ownerId) => ownerId,
-        Err(_) => todo!(),
-    };
     let datastore = endpoints_get_lock!(state.datastore);
-    match datastore.remove_member(teamId, memberId) {
-        Ok(team) => Ok(Json(true)),
-        Err(_) => Ok(Json(false)),
+    auth.require_team(&datastore, teamId, Permission::ManageTeam)?;
+    let user_id = member_user(&datastore, teamId, memberId)?;
+    datastore.remove_member(teamId, memberId)?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "team.member_remove",
+        team_target(teamId, "user", user_id),
+    )?;
+    Ok(Json(true))
+}
+
+/// Changes the role of a member within the team to manager, member or viewer
+///
+/// The owner of a team is set on the team itself, so the owner role can not be given to members.
+#[put("/<team_id>/member/<member_id>/role", data = "<role>")]
+pub fn setMemberRole(
+    state: &State<ServerState>,
+    team_id: i32,
+    member_id: i32,
+    role: Json<Role>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    auth.require_team(&datastore, team_id, Permission::ManageTeam)?;
+    if !role.0.is_team_role() {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            format!("Members can not be given the {:?} role", role.0),
+        ));
+    }
+    let user_id = member_user(&datastore, team_id, member_id)?;
+    datastore.set_member_role(team_id, member_id, role.0)?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "team.member_role_set",
+        team_target(team_id, "user", user_id),
+    )?;
+    Ok(Json(true))
+}
+
+/// Sets the time zone, working hours and days off of a member where they differ from the team
+///
+/// `null` resets the member to the schedule of the team.
+#[put("/<team_id>/member/<member_id>/schedule", data = "<schedule>")]
+pub fn setMemberSchedule(
+    state: &State<ServerState>,
+    team_id: i32,
+    member_id: i32,
+    schedule: Json<Option<MemberSchedule>>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    auth.require_team(&datastore, team_id, Permission::ManageTeam)?;
+    if let Some(Err(msg)) = schedule.0.as_ref().map(MemberSchedule::validate) {
+        return Err(HttpErrorJson::new(Status::BadRequest, msg));
     }
+    let user_id = member_user(&datastore, team_id, member_id)?;
+    datastore.set_member_schedule(team_id, member_id, schedule.0)?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "team.member_schedule_set",
+        team_target(team_id, "user", user_id),
+    )?;
+    Ok(Json(true))
 }
 
 #[get("/user")]
 pub fn getUserTeams(
     state: &State<ServerState>,
-    token: Token,
+    auth: AuthenticatedUser,
 ) -> Result<Json<Vec<TeamUserModel>>, HttpErrorJson> {
-    let token_string = token.clone().0;
-    let user_id = match validate_jwt(&token_string) {
-        Ok(user_id) => user_id,
-        Err(_) => todo!(),
-    };
+    auth.require(Permission::ViewTeam)?;
     let datastore = endpoints_get_lock!(state.datastore);
+    let user_id = auth.id;
     match datastore.get_user_teams(user_id) {
         Ok(teams) => Ok(Json(teams)),
         Err(err) => return Err(err.into()),
@@ -214,50 +365,251 @@ pub fn getUserTeams(
     // Ok(Json(response))
 }
 
+/// The configuration of a team, also readable with the API keys of its members for the watchers
 #[get("/configuration/<team_id>")]
 pub fn getTeamConfiguration(
     state: &State<ServerState>,
+    auth: BucketWriter,
     team_id: i32,
-) -> Result<Json<Vec<String>>, HttpErrorJson> {
+) -> Result<Json<TeamConfiguration>, HttpErrorJson> {
     let datastore = endpoints_get_lock!(state.datastore);
-    let configuration = match datastore.get_configuration(team_id){
-        Ok(config)=>{
-            if config.apps.len() > 0 {
-                return Ok(Json(config.apps))
-            }
-            else{
-                return Ok(Json(Vec::new()))
-            }
-        },
-        Err(err) => Ok(Json(Vec::new()))
-    };
-    return configuration
+    auth.require_team(&datastore, team_id)?;
+    Ok(Json(datastore.get_configuration(team_id)?))
 }
 
 #[post("/<team_id>/configuration", data = "<team_configuration>")]
 pub fn addConfiguration(
     state: &State<ServerState>,
-    token: Token,
+    auth: AuthenticatedUser,
     team_id:i32,
-    team_configuration: Json<Vec<String>>
-) -> Result<Json<()>, HttpErrorJson> {
-    let token_string = token.clone().0;
-    let user_id = validate_jwt(&token_string).unwrap(); 
+    team_configuration: Json<TeamConfiguration>,
+    ip: Option<IpAddr>,
+) -> Result<Json<TeamConfigurationRevision>, HttpErrorJson> {
     let datastore = endpoints_get_lock!(state.datastore);
-    let team = datastore.get_team(team_id).unwrap();
-    if team.ownerId != user_id {
-        return Err(HttpErrorJson::new(Status::Forbidden, "You are not the team owner!".to_string()))
+    auth.require_team(&datastore, team_id, Permission::ManageTeam)?;
+    if let Err(msg) = team_configuration.validate() {
+        return Err(HttpErrorJson::new(Status::BadRequest, msg));
     }
+    let revision =
+        datastore.set_configuration(team_id, team_configuration.into_inner(), Some(auth.id), None)?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "team.configuration_set",
+        team_target(team_id, "revision", revision.revision),
+    )?;
+    return Ok(Json(revision))
+}
+
+/// Lists the revisions of the configuration of a team, newest first
+#[get("/<team_id>/configuration/revisions")]
+pub fn getConfigurationRevisions(
+    state: &State<ServerState>,
+    auth: AuthenticatedUser,
+    team_id: i32,
+) -> Result<Json<Vec<TeamConfigurationRevision>>, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    auth.require_team(&datastore, team_id, Permission::ViewTeam)?;
+    Ok(Json(datastore.get_configuration_revisions(team_id)?))
+}
+
+#[get("/<team_id>/configuration/diff?<from>&<to>")]
+pub fn diffConfigurationRevisions(
+    state: &State<ServerState>,
+    auth: AuthenticatedUser,
+    team_id: i32,
+    from: i32,
+    to: i32,
+) -> Result<Json<TeamConfigurationDiff>, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    auth.require_team(&datastore, team_id, Permission::ViewTeam)?;
+    let revisions = datastore.get_configuration_revisions(team_id)?;
+    let find = |number: i32| {
+        revisions
+            .iter()
+            .find(|revision| revision.revision == number)
+            .ok_or_else(|| no_revision_error(number))
+    };
+    Ok(Json(TeamConfigurationDiff::new(find(from)?, find(to)?)))
+}
 
-    let configuration: TeamConfiguration = match datastore.get_configuration(team_id){
-        Ok(config)=> config,
-        Err(err) => TeamConfiguration { id: -1, team_id: -1, apps: Vec::new() }
+/// Makes an earlier revision the current configuration again
+///
+/// The rollback is stored as a new revision, so the revisions in between stay in the history.
+#[post("/<team_id>/configuration/rollback", data = "<revision>")]
+pub fn rollbackConfiguration(
+    state: &State<ServerState>,
+    auth: AuthenticatedUser,
+    team_id: i32,
+    revision: Json<i32>,
+    ip: Option<IpAddr>,
+) -> Result<Json<TeamConfigurationRevision>, HttpErrorJson> {
+    let restored = revision.0;
+    let datastore = endpoints_get_lock!(state.datastore);
+    auth.require_team(&datastore, team_id, Permission::ManageTeam)?;
+    let configuration = match datastore
+        .get_configuration_revisions(team_id)?
+        .into_iter()
+        .find(|revision| revision.revision == restored)
+    {
+        Some(revision) => revision.configuration,
+        None => return Err(no_revision_error(restored)),
     };
-    if configuration.id == -1{
-        datastore.add_configuration(team_id,team_configuration.join(",").clone()).unwrap();
+    let revision =
+        datastore.set_configuration(team_id, configuration, Some(auth.id), Some(restored))?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "team.configuration_rollback",
+        team_target(team_id, "revision", restored),
+    )?;
+    Ok(Json(revision))
+}
+
+fn no_revision_error(revision: i32) -> HttpErrorJson {
+    HttpErrorJson::new(
+        Status::NotFound,
+        format!("No configuration revision {revision}"),
+    )
+}
+#[cfg(test)]
+mod tests {
+    use rocket::http::{Method, Status};
+    use serde_json::json;
+
+    use crate::endpoints::test_client::TestServer;
+
+    #[test]
+    fn test_managers_add_members() {
+        let server = TestServer::new();
+        let (_, owner) = server.create_user("owner", "team_owner");
+        let (member_id, member) = server.create_user("member", "member");
+        let (other_id, other) = server.create_user("other", "member");
+        let team_id = server.create_team(&owner, "team");
+        let bucket_id = server.create_bucket(&member, "currentwindow");
+        server.join_team(&owner, &member, team_id);
+
+        // Sharing a team lets the owner read the data of the member, but not change it
+        let (status, _) = server.call(
+            Method::Get,
+            &format!("/api/0/buckets/{bucket_id}/events"),
+            Some(&owner),
+            None,
+        );
+        assert_eq!(status, Status::Ok);
+        let (status, _) = server.call(
+            Method::Delete,
+            &format!("/api/0/buckets/{bucket_id}"),
+            Some(&owner),
+            None,
+        );
+        assert_eq!(status, Status::Forbidden);
+
+        // Managing the team lets the owner add users directly, but members can't
+        let (status, _) = server.call(
+            Method::Post,
+            &format!("/api/teams/{team_id}/members"),
+            Some(&other),
+            Some(json!([other_id])),
+        );
+        assert_eq!(status, Status::Forbidden);
+        let (status, added) = server.call(
+            Method::Post,
+            &format!("/api/teams/{team_id}/members"),
+            Some(&owner),
+            Some(json!([other_id])),
+        );
+        assert_eq!(status, Status::Ok);
+        assert_eq!(added, json!(true));
+
+        // Adding members again keeps their membership
+        let (status, _) = server.call(
+            Method::Post,
+            &format!("/api/teams/{team_id}/members"),
+            Some(&owner),
+            Some(json!([member_id, other_id])),
+        );
+        assert_eq!(status, Status::Ok);
+        let (_, team) = server.call(
+            Method::Get,
+            &format!("/api/teams/team/{team_id}"),
+            Some(&owner),
+            None,
+        );
+        assert_eq!(team["members"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_configuration_with_api_key() {
+        let server = TestServer::new();
+        let (_, owner) = server.create_user("owner", "team_owner");
+        let (_, other) = server.create_user("other", "member");
+        let team_id = server.create_team(&owner, "team");
+        let url = format!("/api/teams/configuration/{team_id}");
+
+        // The watchers read the configuration with the API key of the user
+        let key = server.create_api_key(&owner);
+        let (status, configuration) = server.call(Method::Get, &url, Some(&key), None);
+        assert_eq!(status, Status::Ok, "{configuration}");
+        assert_eq!(configuration["tracked_apps"], json!([]));
+
+        let key = server.create_api_key(&other);
+        let (status, _) = server.call(Method::Get, &url, Some(&key), None);
+        assert_eq!(status, Status::Forbidden);
     }
-    else{
-        datastore.update_configuration(team_id,team_configuration.join(",").clone()).unwrap();
+
+    #[test]
+    fn test_update_transfer_and_delete() {
+        let server = TestServer::new();
+        let (_, owner) = server.create_user("owner", "team_owner");
+        let (member_id, member) = server.create_user("member", "member");
+        let (outsider_id, _) = server.create_user("outsider", "member");
+        let team_id = server.create_team(&owner, "team");
+        server.join_team(&owner, &member, team_id);
+        let url = format!("/api/teams/{team_id}");
+        let update = |token: &str, name: &str| {
+            server
+                .call(
+                    Method::Put,
+                    &url,
+                    Some(token),
+                    Some(json!({"name": name, "description": "renamed"})),
+                )
+                .0
+        };
+        let transfer = |token: &str, user_id: i32| {
+            server
+                .call(
+                    Method::Put,
+                    &format!("{url}/owner"),
+                    Some(token),
+                    Some(json!(user_id)),
+                )
+                .0
+        };
+        let delete = |token: &str| server.call(Method::Delete, &url, Some(token), None).0;
+
+        assert_eq!(update(&member, "taken"), Status::Forbidden);
+        assert_eq!(update(&owner, ""), Status::BadRequest);
+        assert_eq!(update(&owner, "renamed"), Status::Ok);
+        let (_, team) = server.call(
+            Method::Get,
+            &format!("/api/teams/team/{team_id}"),
+            Some(&owner),
+            None,
+        );
+        assert_eq!(team["name"], "renamed");
+
+        assert_eq!(transfer(&member, member_id), Status::Forbidden);
+        assert_eq!(transfer(&owner, outsider_id), Status::BadRequest);
+        assert_eq!(transfer(&owner, member_id), Status::Ok);
+        // The previous owner manages the team but can no longer delete it
+        assert_eq!(update(&owner, "team"), Status::Ok);
+        assert_eq!(delete(&owner), Status::Forbidden);
+        assert_eq!(delete(&member), Status::Ok);
+        let (_, teams) = server.call(Method::Get, "/api/teams/user", Some(&member), None);
+        assert_eq!(teams, json!([]));
     }
-    return Ok(Json(()))
-}
\ No newline at end of file
+}
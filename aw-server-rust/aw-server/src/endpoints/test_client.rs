@@ -0,0 +1,201 @@
+//! Helpers for the tests of the endpoints which send requests through a Rocket [Client]
+
+use std::sync::Mutex;
+
+use rocket::http::{ContentType, Header, Method, Status};
+use rocket::local::blocking::Client;
+use serde_json::{json, Value};
+
+use crate::config::AWConfig;
+use crate::endpoints;
+
+/// Password of the users created by [TestServer::create_user]
+pub const PASSWORD: &str = "password1234";
+
+/// A server with an in-memory datastore and a logged in admin
+pub struct TestServer {
+    pub client: Client,
+    /// Access token of the seeded admin, whose password has been changed
+    pub admin: String,
+}
+
+pub fn host() -> Header<'static> {
+    Header::new("Host", "127.0.0.1:5600")
+}
+
+pub fn bearer(token: &str) -> Header<'static> {
+    Header::new("Authorization", format!("Bearer {token}"))
+}
+
+impl TestServer {
+    pub fn new() -> TestServer {
+        let mut config = AWConfig::default();
+        config.login_throttle.backoff_base = 0;
+        TestServer::with_config(config)
+    }
+
+    pub fn with_config(config: AWConfig) -> TestServer {
+        let state = endpoints::ServerState {
+            datastore: Mutex::new(aw_datastore::Datastore::new_in_memory(false)),
+            asset_resolver: endpoints::AssetResolver::new(None),
+            device_id: "test_id".to_string(),
+        };
+        let client =
+            Client::tracked(endpoints::build_rocket(state, config)).expect("valid instance");
+        let mut server = TestServer {
+            client,
+            admin: String::new(),
+        };
+        let (status, tokens) = server.login("admin@admin.com", "admin");
+        assert_eq!(status, Status::Ok, "{tokens}");
+        server.admin = server.change_password(&tokens["access_token"], "admin");
+        server
+    }
+
+    /// Sends a JSON request, returns the status and the JSON body, `null` if there is none
+    pub fn call(
+        &self,
+        method: Method,
+        url: &str,
+        token: Option<&str>,
+        body: Option<Value>,
+    ) -> (Status, Value) {
+        let mut request = self
+            .client
+            .req(method, url.to_string())
+            .header(host())
+            .header(ContentType::JSON);
+        if let Some(token) = token {
+            request = request.header(bearer(token));
+        }
+        if let Some(body) = body {
+            request = request.body(body.to_string());
+        }
+        let response = request.dispatch();
+        let status = response.status();
+        (status, response.into_json().unwrap_or(Value::Null))
+    }
+
+    pub fn login(&self, email: &str, password: &str) -> (Status, Value) {
+        self.call(
+            Method::Post,
+            "/api/user/login",
+            None,
+            Some(json!({"email": email, "password": password})),
+        )
+    }
+
+    /// Replaces a temporary password with [PASSWORD], returns the new access token
+    pub fn change_password(&self, token: &Value, old_password: &str) -> String {
+        let (status, tokens) = self.call(
+            Method::Post,
+            "/api/user/password",
+            token.as_str(),
+            Some(json!({"old_password": old_password, "new_password": PASSWORD})),
+        );
+        assert_eq!(status, Status::Ok, "{tokens}");
+        tokens["access_token"].as_str().unwrap().to_string()
+    }
+
+    /// Creates a user with a global role as the admin and logs them in, returns their id and
+    /// access token
+    pub fn create_user(&self, name: &str, role: &str) -> (i32, String) {
+        let (status, created) = self.call(
+            Method::Post,
+            "/api/user/users",
+            Some(&self.admin),
+            Some(json!({
+                "email": format!("{name}@example.com"),
+                "username": name,
+                "name": name,
+                "lastname": name,
+                "role": role,
+            })),
+        );
+        assert_eq!(status, Status::Ok, "{created}");
+        let temporary = created["temporary_password"].as_str().unwrap();
+        let (status, tokens) = self.login(&format!("{name}@example.com"), temporary);
+        assert_eq!(status, Status::Ok, "{tokens}");
+        let token = self.change_password(&tokens["access_token"], temporary);
+        (created["user"]["id"].as_i64().unwrap() as i32, token)
+    }
+
+    /// Creates a team owned by the user, returns its id
+    pub fn create_team(&self, token: &str, name: &str) -> i32 {
+        let (status, created) = self.call(
+            Method::Post,
+            "/api/teams/",
+            Some(token),
+            Some(json!({"name": name, "description": ""})),
+        );
+        assert_eq!(status, Status::Ok, "{created}");
+        let (_, teams) = self.call(Method::Get, "/api/teams/", Some(token), None);
+        let team = teams
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|team| team["name"] == name);
+        team.unwrap()["id"].as_i64().unwrap() as i32
+    }
+
+    /// Invites a user to a team and lets them accept the invitation
+    pub fn join_team(&self, manager: &str, member: &str, team_id: i32) {
+        let (status, invitation) = self.call(
+            Method::Post,
+            &format!("/api/teams/{team_id}/invitations"),
+            Some(manager),
+            Some(json!({})),
+        );
+        assert_eq!(status, Status::Ok, "{invitation}");
+        let code = invitation["code"].as_str().unwrap();
+        let (status, accepted) = self.call(
+            Method::Post,
+            &format!("/api/invitations/{code}/accept"),
+            Some(member),
+            None,
+        );
+        assert_eq!(status, Status::Ok, "{accepted}");
+    }
+
+    /// Creates an API key of the user, returns the key
+    pub fn create_api_key(&self, token: &str) -> String {
+        let (status, created) = self.call(
+            Method::Post,
+            "/api/user/keys/",
+            Some(token),
+            Some(json!({"name": "laptop"})),
+        );
+        assert_eq!(status, Status::Ok, "{created}");
+        created["key"].as_str().unwrap().to_string()
+    }
+
+    /// Creates a bucket of the user, returns its id
+    pub fn create_bucket(&self, token: &str, bucket_type: &str) -> i64 {
+        let (status, bid) = self.call(
+            Method::Post,
+            "/api/0/buckets/",
+            Some(token),
+            Some(
+                json!({"type": bucket_type, "created": null, "events": null, "last_updated": null}),
+            ),
+        );
+        assert_eq!(status, Status::Ok, "{bid}");
+        bid.as_i64().unwrap()
+    }
+
+    /// Returns the token of the link in the last notification the `database` notifier stored for
+    /// the email address
+    pub fn mailed_token(&self, email: &str) -> String {
+        let (status, outbox) = self.call(Method::Get, "/api/user/outbox", Some(&self.admin), None);
+        assert_eq!(status, Status::Ok, "{outbox}");
+        let message = outbox
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|message| message["recipient"] == email)
+            .unwrap();
+        let body = message["body"].as_str().unwrap();
+        let token = body.split("token=").nth(1).unwrap();
+        token.split_whitespace().next().unwrap().to_string()
+    }
+}
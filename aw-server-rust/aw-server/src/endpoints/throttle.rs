@@ -0,0 +1,111 @@
+use std::net::IpAddr;
+
+use chrono::{DateTime, Duration, Utc};
+use rocket::http::Status;
+
+use aw_datastore::Datastore;
+use aw_models::LoginAttempts;
+
+use crate::config::LoginThrottleConfig;
+use crate::endpoints::HttpErrorJson;
+
+pub fn account_subject(email: &str) -> String {
+    format!("account:{}", email.to_lowercase())
+}
+
+pub fn ip_subject(ip: IpAddr) -> String {
+    format!("ip:{ip}")
+}
+
+/// Refuses the login while the subject has to wait after failed attempts
+pub fn check(datastore: &Datastore, subject: &str) -> Result<(), HttpErrorJson> {
+    match datastore.get_login_attempts(subject)? {
+        Some(attempts) if attempts.locked_until > Utc::now() => {
+            let wait = (attempts.locked_until - Utc::now()).num_seconds() + 1;
+            Err(HttpErrorJson::new(
+                Status::TooManyRequests,
+                format!("Too many failed login attempts, try again in {wait} seconds"),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn record_failure(
+    datastore: &Datastore,
+    config: &LoginThrottleConfig,
+    subject: &str,
+    max_failures: i32,
+) -> Result<(), HttpErrorJson> {
+    let previous = datastore.get_login_attempts(subject)?;
+    let attempts = next_attempts(previous, config, subject, max_failures, Utc::now());
+    if attempts.failures == max_failures {
+        warn!(
+            "Locking {} for {} seconds after {} failed logins",
+            subject, config.lockout_duration, attempts.failures
+        );
+    }
+    datastore.set_login_attempts(&attempts)?;
+    Ok(())
+}
+
+fn next_attempts(
+    previous: Option<LoginAttempts>,
+    config: &LoginThrottleConfig,
+    subject: &str,
+    max_failures: i32,
+    now: DateTime<Utc>,
+) -> LoginAttempts {
+    let lockout = Duration::seconds(config.lockout_duration);
+    let failures = match previous {
+        Some(previous) if previous.last_failure + lockout > now => previous.failures + 1,
+        _ => 1,
+    };
+    let wait = if failures >= max_failures {
+        lockout
+    } else {
+        // Capped, the shift would overflow long before a sensible maximum is reached
+        let backoff = config
+            .backoff_base
+            .saturating_mul(1 << (failures - 1).min(30));
+        Duration::seconds(backoff).min(lockout)
+    };
+    LoginAttempts {
+        subject: subject.to_string(),
+        failures,
+        last_failure: now,
+        locked_until: now + wait,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::next_attempts;
+    use crate::config::LoginThrottleConfig;
+
+    #[test]
+    fn test_backoff_and_lockout() {
+        let config = LoginThrottleConfig {
+            max_failures_per_account: 4,
+            max_failures_per_ip: 10,
+            backoff_base: 1,
+            lockout_duration: 600,
+        };
+        let now = Utc::now();
+        let mut attempts = None;
+        let mut waits = Vec::new();
+        for _ in 0..4 {
+            let next = next_attempts(attempts, &config, "account:a", 4, now);
+            waits.push((next.locked_until - now).num_seconds());
+            attempts = Some(next);
+        }
+        assert_eq!(waits, vec![1, 2, 4, 600]);
+
+        // Failures are forgotten once the lockout duration has passed
+        let later = now + Duration::seconds(601);
+        let next = next_attempts(attempts, &config, "account:a", 4, later);
+        assert_eq!(next.failures, 1);
+    }
+}
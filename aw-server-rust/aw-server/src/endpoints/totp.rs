@@ -0,0 +1,150 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Utc};
+use ring::hmac;
+
+/// Length of a time step in seconds
+const PERIOD: i64 = 30;
+const DIGITS: u32 = 6;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Generates a random 160-bit secret, base32 encoded as expected by authenticator apps
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    OsRng.fill_bytes(&mut bytes);
+    base32_encode(&bytes)
+}
+
+/// The `otpauth://` URI authenticator apps are set up with, usually shown as a QR code
+pub fn provisioning_uri(secret: &str, account: &str, issuer: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(issuer),
+        percent_encode(account),
+        secret,
+        percent_encode(issuer),
+        DIGITS,
+        PERIOD
+    )
+}
+
+pub fn time_step(time: DateTime<Utc>) -> i64 {
+    time.timestamp().div_euclid(PERIOD)
+}
+
+fn code_at(secret: &[u8], step: i64) -> u32 {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let tag = hmac::sign(&key, &step.to_be_bytes());
+    let hash = tag.as_ref();
+    // Dynamic truncation as described in RFC 4226
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 10u32.pow(DIGITS)
+}
+
+/// Checks a code against the secret, returns the time step it is valid for
+///
+/// Codes of the previous and next step are accepted as well to allow for clock drift. Steps up to
+/// `last_step` are refused, so that a code can not be used twice.
+pub fn verify(secret: &str, code: &str, time: DateTime<Utc>, last_step: i64) -> Option<i64> {
+    let secret = base32_decode(secret)?;
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let current = time_step(time);
+    (current - 1..=current + 1)
+        .filter(|step| *step > last_step)
+        .find(|step| code_at(&secret, *step) == code)
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in encoded.trim_end_matches('=').chars() {
+        let c = c.to_ascii_uppercase() as u8;
+        let value = BASE32_ALPHABET.iter().position(|a| *a == c)? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::{base32_decode, base32_encode, code_at, verify};
+
+    // The SHA-1 test secret of RFC 6238
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_base32() {
+        let encoded = base32_encode(SECRET);
+        assert_eq!(encoded, "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(base32_decode(&encoded).unwrap(), SECRET);
+        assert!(base32_decode("not base32!").is_none());
+    }
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        // The RFC lists 8 digit codes, these are their last 6 digits
+        assert_eq!(code_at(SECRET, 59 / 30), 287082);
+        assert_eq!(code_at(SECRET, 1111111109 / 30), 81804);
+        assert_eq!(code_at(SECRET, 1234567890 / 30), 5924);
+        assert_eq!(code_at(SECRET, 2000000000 / 30), 279037);
+    }
+
+    #[test]
+    fn test_verify() {
+        let secret = base32_encode(SECRET);
+        let time = DateTime::from_timestamp(1111111109, 0).unwrap();
+        let step = 1111111109 / 30;
+        assert_eq!(verify(&secret, "081804", time, 0), Some(step));
+        // Replayed codes are refused
+        assert_eq!(verify(&secret, "081804", time, step), None);
+        assert_eq!(verify(&secret, "000000", time, 0), None);
+        assert_eq!(verify(&secret, "81804", time, 0), None);
+    }
+}
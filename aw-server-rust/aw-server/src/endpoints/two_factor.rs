@@ -0,0 +1,295 @@
+use std::net::IpAddr;
+
+use chrono::Utc;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::{Deserialize, Serialize};
+
+use aw_datastore::{Datastore, DatastoreError};
+use aw_models::{Permission, PublicUser, Role};
+
+use crate::endpoints::audit::{self, target};
+use crate::endpoints::auth::{AnyAuthenticatedUser, AuthenticatedUser};
+use crate::endpoints::jwt::{hash_token, random_token};
+use crate::endpoints::totp;
+use crate::endpoints::{HttpErrorJson, ServerState};
+
+// Stored in the key_value table, next to the settings
+const POLICY_KEY: &str = "policy.require_two_factor_for_team_owners";
+const ISSUER: &str = "ActivityWatch";
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Whether admins require team owners and managers to use two-factor authentication
+pub fn is_required_for_team_owners(datastore: &Datastore) -> Result<bool, HttpErrorJson> {
+    match datastore.get_key_value(POLICY_KEY) {
+        Ok(value) => Ok(value == "true"),
+        Err(DatastoreError::NoSuchKey(_)) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Whether the user has to enable two-factor authentication before doing anything else
+///
+/// The policy covers everyone who owns or manages a team, whatever their system wide role.
+pub fn must_enroll(datastore: &Datastore, user: &PublicUser) -> Result<bool, HttpErrorJson> {
+    if user.two_factor_enabled || !is_required_for_team_owners(datastore)? {
+        return Ok(false);
+    }
+    if user.role == Role::TeamOwner || !datastore.get_owner_teams(user.id)?.is_empty() {
+        return Ok(true);
+    }
+    for team in datastore.get_user_teams(user.id)? {
+        if datastore.get_team_role(team.id, user.id)? == Some(Role::TeamManager) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Checks a code from the authenticator app, or a recovery code, of a user with 2FA enabled
+///
+/// Recovery codes can only be used once.
+pub fn verify_code(datastore: &Datastore, user_id: i32, code: &str) -> Result<bool, HttpErrorJson> {
+    let two_factor = match datastore.get_two_factor(user_id)? {
+        Some(two_factor) if two_factor.enabled => two_factor,
+        _ => return Ok(false),
+    };
+    if let Some(step) = totp::verify(&two_factor.secret, code, Utc::now(), two_factor.last_step) {
+        datastore.set_two_factor_step(user_id, step)?;
+        return Ok(true);
+    }
+    let recovery_code = hash_token(&normalize_recovery_code(code));
+    Ok(datastore.use_recovery_code(user_id, &recovery_code)?)
+}
+
+#[derive(Serialize)]
+pub struct EnrollResponse {
+    secret: String,
+    provisioning_uri: String,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct CodeModel<'r> {
+    code: &'r str,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PolicyModel {
+    require_for_team_owners: bool,
+}
+
+/// Starts the enrollment by generating a new secret
+///
+/// Two-factor authentication is only enabled once a code for the secret is confirmed.
+#[post("/enroll")]
+pub fn two_factor_enroll(
+    state: &State<ServerState>,
+    auth: AnyAuthenticatedUser,
+) -> Result<Json<EnrollResponse>, HttpErrorJson> {
+    let auth = auth.0;
+    if auth.user.two_factor_enabled {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+    let secret = totp::generate_secret();
+    let datastore = endpoints_get_lock!(state.datastore);
+    datastore.set_two_factor_secret(auth.id, &secret)?;
+    Ok(Json(EnrollResponse {
+        provisioning_uri: totp::provisioning_uri(&secret, &auth.user.email, ISSUER),
+        secret,
+    }))
+}
+
+/// Enables two-factor authentication with a code for the secret from the enrollment
+///
+/// Responds with the recovery codes, which are not shown again.
+#[post("/confirm", data = "<input>")]
+pub fn two_factor_confirm(
+    state: &State<ServerState>,
+    input: Json<CodeModel>,
+    auth: AnyAuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<Vec<String>>, HttpErrorJson> {
+    let auth = auth.0;
+    let datastore = endpoints_get_lock!(state.datastore);
+    let two_factor = match datastore.get_two_factor(auth.id)? {
+        Some(two_factor) if !two_factor.enabled => two_factor,
+        _ => {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                "No two-factor enrollment is pending".to_string(),
+            ))
+        }
+    };
+    let step = match totp::verify(&two_factor.secret, input.code, Utc::now(), 0) {
+        Some(step) => step,
+        None => {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                "Invalid code".to_string(),
+            ))
+        }
+    };
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let token = random_token();
+            format!("{}-{}", &token[0..5], &token[5..10])
+        })
+        .collect();
+    let hashes = recovery_codes
+        .iter()
+        .map(|code| hash_token(&normalize_recovery_code(code)))
+        .collect();
+    datastore.enable_two_factor(auth.id, step, hashes)?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "two_factor.enable",
+        target("user", auth.id),
+    )?;
+    Ok(Json(recovery_codes))
+}
+
+/// Turns off two-factor authentication, confirmed with a current or recovery code
+#[post("/disable", data = "<input>")]
+pub fn two_factor_disable(
+    state: &State<ServerState>,
+    input: Json<CodeModel>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    if !verify_code(&datastore, auth.id, input.code)? {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "Invalid code".to_string(),
+        ));
+    }
+    datastore.delete_two_factor(auth.id)?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "two_factor.disable",
+        target("user", auth.id),
+    )?;
+    Ok(Json(true))
+}
+
+/// Removes the second factor of a user who lost both their device and recovery codes
+#[delete("/<user_id>")]
+pub fn two_factor_reset(
+    state: &State<ServerState>,
+    user_id: i32,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    auth.require(Permission::ManageUsers)?;
+    let datastore = endpoints_get_lock!(state.datastore);
+    datastore.delete_two_factor(user_id)?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "two_factor.reset",
+        target("user", user_id),
+    )?;
+    Ok(Json(true))
+}
+
+#[get("/policy")]
+pub fn two_factor_policy_get(
+    state: &State<ServerState>,
+    auth: AuthenticatedUser,
+) -> Result<Json<PolicyModel>, HttpErrorJson> {
+    auth.require(Permission::ViewUsers)?;
+    let datastore = endpoints_get_lock!(state.datastore);
+    Ok(Json(PolicyModel {
+        require_for_team_owners: is_required_for_team_owners(&datastore)?,
+    }))
+}
+
+/// Sets whether team owners and managers have to use two-factor authentication
+///
+/// Those without it are asked to enable it on their next request.
+#[put("/policy", data = "<input>")]
+pub fn two_factor_policy_set(
+    state: &State<ServerState>,
+    input: Json<PolicyModel>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    auth.require(Permission::ManageUsers)?;
+    let datastore = endpoints_get_lock!(state.datastore);
+    datastore.set_key_value(POLICY_KEY, &input.require_for_team_owners.to_string())?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "policy.set",
+        Some(POLICY_KEY.to_string()),
+    )?;
+    Ok(Json(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{Method, Status};
+    use serde_json::json;
+
+    use crate::endpoints::test_client::TestServer;
+
+    #[test]
+    fn test_policy_covers_team_managers() {
+        let server = TestServer::new();
+        let (_, owner) = server.create_user("owner", "team_owner");
+        let (manager_id, manager) = server.create_user("manager", "member");
+        let (_, member) = server.create_user("member", "member");
+        let team_id = server.create_team(&owner, "team");
+        server.join_team(&owner, &manager, team_id);
+        server.join_team(&owner, &member, team_id);
+        // Members are changed by the id of their membership
+        let (_, team) = server.call(
+            Method::Get,
+            &format!("/api/teams/team/{team_id}"),
+            Some(&owner),
+            None,
+        );
+        let members = team["members"].as_array().unwrap();
+        let membership = members.iter().find(|m| m["user_id"] == manager_id).unwrap();
+        let (status, changed) = server.call(
+            Method::Put,
+            &format!("/api/teams/{team_id}/member/{}/role", membership["id"]),
+            Some(&owner),
+            Some(json!("team_manager")),
+        );
+        assert_eq!(status, Status::Ok, "{changed}");
+
+        let (status, _) = server.call(
+            Method::Put,
+            "/api/user/2fa/policy",
+            Some(&server.admin),
+            Some(json!({"require_for_team_owners": true})),
+        );
+        assert_eq!(status, Status::Ok);
+        let (status, error) = server.call(Method::Get, "/api/teams/user", Some(&manager), None);
+        assert_eq!(status, Status::Forbidden);
+        assert_eq!(
+            error["message"],
+            "Two-factor authentication has to be enabled first"
+        );
+        let (status, _) = server.call(Method::Get, "/api/teams/user", Some(&member), None);
+        assert_eq!(status, Status::Ok);
+    }
+}
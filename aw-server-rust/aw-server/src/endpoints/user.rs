@@ -1,14 +1,27 @@
-use hash::{generate_hash, verify_password};
-use jwt::{create_jwt, validate_jwt, Claims};
-use rocket::outcome::Outcome;
-use rocket::request::{self, FromRequest, Request};
+pub use hash::{generate_hash, verify_password};
+use crate::config::AWConfig;
+use crate::endpoints::audit::{self, account_target, target, team_target};
+use crate::endpoints::auth::{deactivated_error, AnyAuthenticatedUser, AuthenticatedUser};
+use crate::endpoints::jwt::{hash_token, random_token, JwtKeys};
+use crate::endpoints::invitation;
+use crate::endpoints::throttle;
+use crate::endpoints::two_factor;
+use crate::endpoints::util::page_offset;
 use crate::endpoints::{HttpErrorJson, ServerState};
-use aw_models::{PublicUser, User};
+use crate::notifier::{Notification, Notifier};
+use aw_datastore::{Datastore, DatastoreError};
+use aw_models::{
+    LoginAttempts, OutboxMessage, Permission, PublicUser, Role, TokenPurpose, User, UserFilter,
+    UserPage, UserProfile, UserSort,
+};
+use chrono::{Duration, Utc};
 use rocket::http::Status;
 use rocket::serde::json::Json;
 use rocket::State;
-use serde::Deserialize;
-mod jwt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::OnceLock;
 mod hash;
 
 #[derive(Deserialize, Clone, Copy)]
@@ -24,67 +37,366 @@ pub struct SignupModel<'r> {
     name: &'r str,
     lastname: &'r str,
     username: &'r str,
+    // Code of a team invitation, the new user joins the team
+    invitation: Option<&'r str>,
+}
+#[derive(Deserialize, Clone, Copy)]
+pub struct RefreshModel<'r> {
+    refresh_token: &'r str,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct PasswordModel<'r> {
+    old_password: &'r str,
+    new_password: &'r str,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct ResetRequestModel<'r> {
+    email: &'r str,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct VerifyEmailModel<'r> {
+    token: &'r str,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct ResetConfirmModel<'r> {
+    token: &'r str,
+    new_password: &'r str,
 }
-#[derive(Deserialize, Clone)]
-pub struct Token(String);
-#[rocket::async_trait]
-impl<'r> FromRequest<'r> for Token {
-    type Error = ();
-
-    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
-        // Look for the "Authorization" header
-        if let Some(token_header) = request.headers().get_one("Authorization") {
-            if let Some(token) = token_header.strip_prefix("Bearer ") {
-                return Outcome::Success(Token(token.to_string()));
-            }
-        }
 
-        Outcome::Forward(Status::Unauthorized)
+#[derive(Deserialize, Clone, Copy)]
+pub struct DeleteAccountModel<'r> {
+    password: &'r str,
+}
+
+#[derive(FromForm)]
+pub struct DirectoryQuery<'r> {
+    // Starts at 1
+    page: Option<i64>,
+    per_page: Option<i64>,
+    sort: Option<&'r str>,
+    // "asc" or "desc"
+    order: Option<&'r str>,
+    search: Option<&'r str>,
+    role: Option<&'r str>,
+    team_id: Option<i32>,
+    active: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct DirectoryResponse {
+    #[serde(flatten)]
+    page: UserPage,
+    page_number: i64,
+    per_page: i64,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct NewUserModel<'r> {
+    email: &'r str,
+    username: &'r str,
+    name: &'r str,
+    lastname: &'r str,
+    role: Option<Role>,
+}
+
+#[derive(Serialize)]
+pub struct TemporaryPasswordResponse {
+    user: PublicUser,
+    // Has to be changed at the first login
+    temporary_password: String,
+}
+
+#[derive(Serialize)]
+pub struct TokenResponse {
+    access_token: String,
+    refresh_token: String,
+    // Lifetime of the access token in seconds
+    expires_in: i64,
+    // If set, all other endpoints are refused until the password is changed
+    must_change_password: bool,
+    // If set, all other endpoints are refused until two-factor authentication is enabled
+    must_enable_two_factor: bool,
+}
+
+#[derive(Serialize)]
+pub struct TwoFactorChallenge {
+    two_factor_required: bool,
+    // Sent to /login/2fa together with the code
+    challenge: String,
+    expires_in: i64,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Tokens(TokenResponse),
+    TwoFactor(TwoFactorChallenge),
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct TwoFactorLoginModel<'r> {
+    challenge: &'r str,
+    code: &'r str,
+}
+
+// Lifetime of the challenge between checking the password and the second factor, in seconds
+const TWO_FACTOR_CHALLENGE_EXPIRY: i64 = 5 * 60;
+
+// Logins of unknown accounts check the password against this hash, so they take as long as
+// logins with a wrong password
+static UNKNOWN_ACCOUNT_HASH: OnceLock<String> = OnceLock::new();
+
+const DIRECTORY_DEFAULT_PAGE_SIZE: i64 = 50;
+const DIRECTORY_MAX_PAGE_SIZE: i64 = 200;
+
+/// Issues a new access token together with a refresh token that can be used to get the next one
+pub fn issue_tokens(
+    datastore: &Datastore,
+    config: &AWConfig,
+    jwt_keys: &JwtKeys,
+    user: &PublicUser,
+) -> Result<TokenResponse, HttpErrorJson> {
+    if !user.active {
+        return Err(deactivated_error());
     }
+    let generation = datastore.get_token_generation(user.id)?;
+    let access_token = match jwt_keys.create_jwt(user.id, generation) {
+        Ok(token) => token,
+        Err(_) => {
+            return Err(HttpErrorJson::new(
+                Status::InternalServerError,
+                "could not generate token".to_string(),
+            ))
+        }
+    };
+    let refresh_token = random_token();
+    let expires = Utc::now() + Duration::seconds(config.auth.refresh_token_expiry);
+    datastore.add_refresh_token(user.id, &hash_token(&refresh_token), expires)?;
+    Ok(TokenResponse {
+        access_token,
+        refresh_token,
+        expires_in: jwt_keys.expiry(),
+        must_change_password: user.must_change_password,
+        must_enable_two_factor: two_factor::must_enroll(datastore, user)?,
+    })
+}
+
+fn unknown_account_hash() -> &'static str {
+    UNKNOWN_ACCOUNT_HASH.get_or_init(|| generate_hash(&random_token()))
 }
 
 #[post("/login", data = "<input>")]
 pub fn login(
     state: &State<ServerState>,
+    config: &State<AWConfig>,
+    jwt_keys: &State<JwtKeys>,
     input: Json<LoginModel>,
-) -> Result<Json<String>, HttpErrorJson> {
+    ip: Option<IpAddr>,
+) -> Result<Json<LoginResponse>, HttpErrorJson> {
     let email = input.email.to_string();
     let password = input.password.to_string();
     if (email.is_empty() || password.is_empty()) {
         let err_msg = format!("No user");
         return Err(HttpErrorJson::new(Status::BadRequest, err_msg));
     }
+    let throttle_config = &config.login_throttle;
+    let mut subjects = vec![(
+        throttle::account_subject(&email),
+        throttle_config.max_failures_per_account,
+    )];
+    if let Some(ip) = ip {
+        subjects.push((throttle::ip_subject(ip), throttle_config.max_failures_per_ip));
+    }
     let datastore = endpoints_get_lock!(state.datastore);
-    match datastore.get_user_by_email(input.email.to_string()) {
-        Ok(user) => {
-            if verify_password(&password, &user.password) {
-                let claims = Claims {
-                    userId: user.id,
-                    exp: 10000000000, // Set your expiration logic
-                };
-
-                match create_jwt(&claims) {
-                    Ok(token) => Ok(Json(token)),
-                    Err(_) => Err(HttpErrorJson::new(
-                        Status::BadRequest,
-                        "could not generate token".to_string(),
-                    )),
-                }
-            } else {
-                return Err(HttpErrorJson::new(
-                    Status::BadRequest,
-                    "No user with this password found".to_string(),
-                ));
-            }
+    for (subject, _) in &subjects {
+        throttle::check(&datastore, subject)?;
+    }
+    let user = match datastore.get_user_by_email(input.email.to_string()) {
+        Ok(user) => Some(user),
+        Err(DatastoreError::NoUser()) => None,
+        Err(err) => return Err(err.into()),
+    };
+    let password_valid = match &user {
+        Some(user) => verify_password(&password, &user.password),
+        None => verify_password(&password, unknown_account_hash()),
+    };
+    if let (Some(user), true) = (&user, password_valid) {
+        let user = datastore.get_user(user.id)?;
+        if !user.active {
+            return Err(deactivated_error());
+        }
+        if user.two_factor_enabled {
+            // The failed attempts are only cleared once the second factor is checked too
+            let challenge = random_token();
+            let expires = Utc::now() + Duration::seconds(TWO_FACTOR_CHALLENGE_EXPIRY);
+            datastore.add_one_time_token(
+                user.id,
+                TokenPurpose::TwoFactorChallenge,
+                &hash_token(&challenge),
+                expires,
+            )?;
+            return Ok(Json(LoginResponse::TwoFactor(TwoFactorChallenge {
+                two_factor_required: true,
+                challenge,
+                expires_in: TWO_FACTOR_CHALLENGE_EXPIRY,
+            })));
         }
-        Err(err) => Err(err.into()),
+        datastore.clear_login_attempts(&subjects[0].0)?;
+        let tokens = issue_tokens(&datastore, config, jwt_keys, &user)?;
+        audit::record(
+            &datastore,
+            Some(user.id),
+            ip,
+            "user.login",
+            target("user", user.id),
+        )?;
+        return Ok(Json(LoginResponse::Tokens(tokens)));
+    }
+    // The response does not tell whether the account exists, only the audit log knows the actor
+    let actor = user.map(|user| user.id);
+    for (subject, max_failures) in &subjects {
+        throttle::record_failure(&datastore, throttle_config, subject, *max_failures)?;
+    }
+    audit::record(
+        &datastore,
+        actor,
+        ip,
+        "user.login_failed",
+        account_target(&datastore, actor, &email)?,
+    )?;
+    Err(HttpErrorJson::new(
+        Status::BadRequest,
+        "No user with this password found".to_string(),
+    ))
+}
+
+/// Second step of the login for users with two-factor authentication
+///
+/// Takes the challenge returned by the login and a code from the authenticator app or a recovery
+/// code. The challenge can only be used once, after a wrong code the login has to be repeated.
+#[post("/login/2fa", data = "<input>")]
+pub fn loginTwoFactor(
+    state: &State<ServerState>,
+    config: &State<AWConfig>,
+    jwt_keys: &State<JwtKeys>,
+    input: Json<TwoFactorLoginModel>,
+    ip: Option<IpAddr>,
+) -> Result<Json<TokenResponse>, HttpErrorJson> {
+    let datastore = endpoints_get_lock!(state.datastore);
+    let challenge = match datastore.take_one_time_token(
+        TokenPurpose::TwoFactorChallenge,
+        &hash_token(input.challenge),
+    ) {
+        Ok(challenge) if challenge.expires > Utc::now() => challenge,
+        Ok(_) | Err(DatastoreError::NoSuchKey(_)) => {
+            return Err(HttpErrorJson::new(
+                Status::Unauthorized,
+                "Invalid or expired login challenge".to_string(),
+            ))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let user = datastore.get_user(challenge.user_id)?;
+    let subject = throttle::account_subject(&user.email);
+    throttle::check(&datastore, &subject)?;
+    if !two_factor::verify_code(&datastore, user.id, input.code)? {
+        throttle::record_failure(
+            &datastore,
+            &config.login_throttle,
+            &subject,
+            config.login_throttle.max_failures_per_account,
+        )?;
+        audit::record(
+            &datastore,
+            Some(user.id),
+            ip,
+            "user.login_failed",
+            target("user", user.id),
+        )?;
+        return Err(HttpErrorJson::new(
+            Status::Unauthorized,
+            "Invalid two-factor code".to_string(),
+        ));
+    }
+    datastore.clear_login_attempts(&subject)?;
+    let tokens = issue_tokens(&datastore, config, jwt_keys, &user)?;
+    audit::record(
+        &datastore,
+        Some(user.id),
+        ip,
+        "user.login",
+        target("user", user.id),
+    )?;
+    Ok(Json(tokens))
+}
+
+/// Exchanges a refresh token for a new access token and refresh token
+///
+/// The used refresh token is consumed, so a leaked refresh token can only be used once.
+#[post("/refresh", data = "<input>")]
+pub fn refresh(
+    state: &State<ServerState>,
+    config: &State<AWConfig>,
+    jwt_keys: &State<JwtKeys>,
+    input: Json<RefreshModel>,
+) -> Result<Json<TokenResponse>, HttpErrorJson> {
+    let token_hash = hash_token(input.refresh_token);
+    let datastore = endpoints_get_lock!(state.datastore);
+    let refresh_token = match datastore.get_refresh_token(&token_hash) {
+        Ok(refresh_token) => refresh_token,
+        Err(DatastoreError::NoSuchKey(_)) => {
+            return Err(HttpErrorJson::new(
+                Status::Unauthorized,
+                "Invalid refresh token".to_string(),
+            ))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    datastore.delete_refresh_token(&token_hash)?;
+    if refresh_token.expires < Utc::now() {
+        return Err(HttpErrorJson::new(
+            Status::Unauthorized,
+            "Refresh token has expired".to_string(),
+        ));
     }
+    let user = datastore.get_user(refresh_token.user_id)?;
+    Ok(Json(issue_tokens(&datastore, config, jwt_keys, &user)?))
+}
+
+/// Revokes the access token used for the request and the given refresh token
+///
+/// With `all=true` every refresh and access token of the user is revoked, which ends the
+/// sessions on all devices.
+#[post("/logout?<all>", data = "<input>")]
+pub fn logout(
+    state: &State<ServerState>,
+    auth: AnyAuthenticatedUser,
+    all: Option<bool>,
+    input: Option<Json<RefreshModel>>,
+) -> Result<(), HttpErrorJson> {
+    let auth = auth.0;
+    let datastore = endpoints_get_lock!(state.datastore);
+    datastore.revoke_token(&auth.claims.jti, auth.claims.expires())?;
+    if let Some(input) = input {
+        datastore.delete_refresh_token(&hash_token(input.refresh_token))?;
+    }
+    if all.unwrap_or(false) {
+        datastore.revoke_user_tokens(auth.id)?;
+    }
+    Ok(())
 }
 
 #[post("/signup", data = "<input>")]
 pub fn signup(
     state: &State<ServerState>,
+    config: &State<AWConfig>,
+    notifier: &State<Box<dyn Notifier>>,
     input: Json<SignupModel>,
+    ip: Option<IpAddr>,
 ) -> Result<Json<bool>, HttpErrorJson> {
     let password = input.password.to_string();
     let email = input.email.to_string();
@@ -102,58 +414,443 @@ pub fn signup(
         password: generate_hash(&password),
         name: name,
         lastname: lastname,
-        role: 1,
+        role: Role::Member,
+        must_change_password: false,
+        email_verified: false,
     };
 
-    let datastore = endpoints_get_lock!(state.datastore);
-    let isUserExisted = match datastore.get_user_by_email(input.email.to_string()) {
-        Ok(user) => true,
-        Err(_) => false,
+    let user = {
+        let datastore = endpoints_get_lock!(state.datastore);
+        // The invitation is checked before the account is created, it is only used up afterwards
+        match input.invitation {
+            Some(code) => {
+                invitation::find_invitation(&datastore, code)?;
+            }
+            None if !invitation::is_open_registration(&datastore)? => {
+                return Err(HttpErrorJson::new(
+                    Status::Forbidden,
+                    "Signing up requires an invitation".to_string(),
+                ))
+            }
+            None => (),
+        }
+        let isUserExisted = match datastore.get_user_by_email(input.email.to_string()) {
+            Ok(user) => true,
+            Err(_) => false,
+        };
+        if (isUserExisted == true) {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                "Email is used".to_string(),
+            ));
+        }
+        let user = datastore.add_user(user)?;
+        audit::record(
+            &datastore,
+            Some(user.id),
+            ip,
+            "user.signup",
+            target("user", user.id),
+        )?;
+        if let Some(code) = input.invitation {
+            let invitation = datastore.use_invitation(&hash_token(code), user.id)?;
+            audit::record(
+                &datastore,
+                Some(user.id),
+                ip,
+                "team.invitation_accept",
+                team_target(invitation.team_id, "invitation", invitation.id),
+            )?;
+        }
+        user
     };
-    if (isUserExisted == true) {
+    // The account exists at this point, so a failure is only logged and the user can ask for
+    // the email again
+    send_email_verification(state, config, notifier, &user).ok();
+    Ok(Json(true))
+}
+
+#[get("/getuser")]
+pub fn getUser(auth: AnyAuthenticatedUser) -> Result<Json<PublicUser>, HttpErrorJson> {
+    Ok(Json(auth.0.user))
+}
+
+/// Changes the name, username and email of the user
+///
+/// A changed email address has to be verified again, a verification link is sent to it.
+#[put("/profile", data = "<profile>")]
+pub fn updateProfile(
+    state: &State<ServerState>,
+    config: &State<AWConfig>,
+    notifier: &State<Box<dyn Notifier>>,
+    profile: Json<UserProfile>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<PublicUser>, HttpErrorJson> {
+    if profile.email.is_empty() || profile.username.is_empty() {
         return Err(HttpErrorJson::new(
             Status::BadRequest,
-            "Email is used".to_string(),
+            "Email and username can not be empty".to_string(),
         ));
     }
-    match datastore.add_user(user) {
-        Ok(user) => Ok(Json(true)),
-        Err(err) => Err(err.into()),
+    let user = {
+        let datastore = endpoints_get_lock!(state.datastore);
+        if !datastore.update_profile(auth.id, profile.0)? {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                "Email or username is used".to_string(),
+            ));
+        }
+        audit::record(
+            &datastore,
+            Some(auth.id),
+            ip,
+            "user.profile_update",
+            target("user", auth.id),
+        )?;
+        datastore.get_user(auth.id)?
+    };
+    if user.email != auth.user.email {
+        send_email_verification(state, config, notifier, &user)?;
     }
+    Ok(Json(user))
 }
 
-#[get("/getuser")]
-pub fn getUser(
+/// Creates a verification token for the email address of a user and sends the link to it
+///
+/// Takes the datastore lock itself, so it must not be held by the caller.
+fn send_email_verification(
     state: &State<ServerState>,
-    token: Token,
-) -> Result<Json<PublicUser>, HttpErrorJson> {
-    let tokenString = token.clone().0;
-    let userId = match validate_jwt(&tokenString) {
-        Ok(userId) => userId,
-        Err(_) => todo!(),
+    config: &AWConfig,
+    notifier: &State<Box<dyn Notifier>>,
+    user: &PublicUser,
+) -> Result<(), HttpErrorJson> {
+    let token = random_token();
+    {
+        let datastore = endpoints_get_lock!(state.datastore);
+        let expires = Utc::now() + Duration::seconds(config.auth.email_verification_expiry);
+        datastore.add_one_time_token(
+            user.id,
+            TokenPurpose::EmailVerification,
+            &hash_token(&token),
+            expires,
+        )?;
+    }
+    let notification = Notification {
+        to: user.email.clone(),
+        subject: "Verify your ActivityWatch email address".to_string(),
+        body: format!(
+            "Hi {},\n\nOpen the link below to verify your email address, it is valid for {} \
+            hours:\n\n{}/verify-email?token={}\n\n\
+            If you did not create an account you can ignore this message.",
+            user.name,
+            config.auth.email_verification_expiry / 3600,
+            config.public_url(),
+            token
+        ),
     };
-    let userId = 1;
+    if let Err(err) = notifier.send(&notification, &state.datastore) {
+        error!(
+            "Failed to send email verification to {}: {}",
+            notification.to, err
+        );
+        return Err(HttpErrorJson::new(
+            Status::InternalServerError,
+            "Failed to send the email verification".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Verifies the email address of a user using the token from a verification link
+#[post("/email/verify", data = "<input>")]
+pub fn verifyEmail(
+    state: &State<ServerState>,
+    input: Json<VerifyEmailModel>,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
     let datastore = endpoints_get_lock!(state.datastore);
+    let token_hash = hash_token(input.token);
+    let token = match datastore.take_one_time_token(TokenPurpose::EmailVerification, &token_hash) {
+        Ok(token) => token,
+        Err(DatastoreError::NoSuchKey(_)) => {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                "Invalid email verification token".to_string(),
+            ))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if token.expires < Utc::now() {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "Email verification token has expired".to_string(),
+        ));
+    }
+    datastore.set_email_verified(token.user_id)?;
+    audit::record(
+        &datastore,
+        Some(token.user_id),
+        ip,
+        "user.email_verify",
+        target("user", token.user_id),
+    )?;
+    Ok(Json(true))
+}
 
-    match datastore.get_user(userId) {
-        Ok(user) => Ok(Json(user)),
-        Err(_) => Err(HttpErrorJson::new(
+/// Sends a new verification link to the email address of the user
+#[post("/email/verify/resend")]
+pub fn resendEmailVerification(
+    state: &State<ServerState>,
+    config: &State<AWConfig>,
+    notifier: &State<Box<dyn Notifier>>,
+    auth: AuthenticatedUser,
+) -> Result<Json<bool>, HttpErrorJson> {
+    if auth.user.email_verified {
+        return Err(HttpErrorJson::new(
             Status::BadRequest,
-            "Email is used".to_string(),
-        )),
+            "The email address is already verified".to_string(),
+        ));
+    }
+    send_email_verification(state, config, notifier, &auth.user)?;
+    Ok(Json(true))
+}
+
+/// Deletes the account of the user and all data stored about them
+///
+/// Requires the password of the user. Teams owned by the user have to be transferred or deleted
+/// first.
+#[delete("/account", data = "<input>")]
+pub fn deleteAccount(
+    state: &State<ServerState>,
+    input: Json<DeleteAccountModel>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    if auth.role() == Role::Admin {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "Admins can not delete their own account".to_string(),
+        ));
+    }
+    let datastore = endpoints_get_lock!(state.datastore);
+    let user = datastore.get_user_by_email(auth.user.email.clone())?;
+    if !verify_password(input.password, &user.password) {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "The password is incorrect".to_string(),
+        ));
+    }
+    datastore.delete_user(auth.id)?;
+    datastore.revoke_token(&auth.claims.jti, auth.claims.expires())?;
+    datastore.clear_login_attempts(&throttle::account_subject(&auth.user.email))?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "user.delete",
+        target("user", auth.id),
+    )?;
+    Ok(Json(true))
+}
+
+/// Changes the password of the user
+///
+/// All other sessions of the user are ended, the response contains new tokens for the current one.
+#[post("/password", data = "<input>")]
+pub fn changePassword(
+    state: &State<ServerState>,
+    config: &State<AWConfig>,
+    jwt_keys: &State<JwtKeys>,
+    input: Json<PasswordModel>,
+    auth: AnyAuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<TokenResponse>, HttpErrorJson> {
+    let auth = auth.0;
+    if input.new_password.is_empty() {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "The new password can not be empty".to_string(),
+        ));
+    }
+    if input.new_password == input.old_password {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "The new password has to differ from the old one".to_string(),
+        ));
+    }
+    let datastore = endpoints_get_lock!(state.datastore);
+    let user = datastore.get_user_by_email(auth.user.email.clone())?;
+    if !verify_password(input.old_password, &user.password) {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "The old password is incorrect".to_string(),
+        ));
+    }
+    datastore.set_password(auth.id, &generate_hash(input.new_password), false)?;
+    datastore.revoke_user_tokens(auth.id)?;
+    datastore.revoke_token(&auth.claims.jti, auth.claims.expires())?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "user.password_change",
+        target("user", auth.id),
+    )?;
+    let user = datastore.get_user(auth.id)?;
+    Ok(Json(issue_tokens(&datastore, config, jwt_keys, &user)?))
+}
+
+/// Sends a link to reset the password to the email address of a user
+///
+/// Always succeeds, so the response does not tell whether an account with the address exists.
+#[post("/password/reset", data = "<input>")]
+pub fn requestPasswordReset(
+    state: &State<ServerState>,
+    config: &State<AWConfig>,
+    notifier: &State<Box<dyn Notifier>>,
+    input: Json<ResetRequestModel>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    let token = random_token();
+    let user = {
+        let datastore = endpoints_get_lock!(state.datastore);
+        let user = match datastore.get_user_by_email(input.email.to_string()) {
+            Ok(user) => user,
+            Err(DatastoreError::NoUser()) => return Ok(Json(true)),
+            Err(err) => return Err(err.into()),
+        };
+        let expires = Utc::now() + Duration::seconds(config.auth.password_reset_expiry);
+        datastore.add_one_time_token(
+            user.id,
+            TokenPurpose::PasswordReset,
+            &hash_token(&token),
+            expires,
+        )?;
+        user
+    };
+
+    // The database notifier takes the datastore lock itself, so it is only sent once the lock
+    // above is released
+    let notification = Notification {
+        to: user.email,
+        subject: "Reset your ActivityWatch password".to_string(),
+        body: format!(
+            "Hi {},\n\nA password reset was requested for your account. Open the link below to \
+            choose a new password, it is valid for {} minutes:\n\n{}/reset-password?token={}\n\n\
+            If you did not request this you can ignore this message.",
+            user.name,
+            config.auth.password_reset_expiry / 60,
+            config.public_url(),
+            token
+        ),
+    };
+    if let Err(err) = notifier.send(&notification, &state.datastore) {
+        error!("Failed to send password reset to {}: {}", notification.to, err);
+        return Err(HttpErrorJson::new(
+            Status::InternalServerError,
+            "Failed to send the password reset".to_string(),
+        ));
     }
+    Ok(Json(true))
 }
 
+/// Sets a new password using the token from a password reset link
+///
+/// All sessions of the user are ended and a lockout of the account after failed logins is lifted.
+#[post("/password/reset/confirm", data = "<input>")]
+pub fn confirmPasswordReset(
+    state: &State<ServerState>,
+    input: Json<ResetConfirmModel>,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    if input.new_password.is_empty() {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "The new password can not be empty".to_string(),
+        ));
+    }
+    let datastore = endpoints_get_lock!(state.datastore);
+    let token_hash = hash_token(input.token);
+    let token = match datastore.take_one_time_token(TokenPurpose::PasswordReset, &token_hash) {
+        Ok(token) => token,
+        Err(DatastoreError::NoSuchKey(_)) => {
+            return Err(HttpErrorJson::new(
+                Status::BadRequest,
+                "Invalid password reset token".to_string(),
+            ))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if token.expires < Utc::now() {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "Password reset token has expired".to_string(),
+        ));
+    }
+    datastore.set_password(token.user_id, &generate_hash(input.new_password), false)?;
+    datastore.revoke_user_tokens(token.user_id)?;
+    let user = datastore.get_user(token.user_id)?;
+    datastore.clear_login_attempts(&throttle::account_subject(&user.email))?;
+    audit::record(
+        &datastore,
+        Some(user.id),
+        ip,
+        "user.password_reset",
+        target("user", user.id),
+    )?;
+    Ok(Json(true))
+}
+
+/// Lists the notifications stored by the `database` notifier
+#[get("/outbox")]
+pub fn getOutbox(
+    state: &State<ServerState>,
+    auth: AuthenticatedUser,
+) -> Result<Json<Vec<OutboxMessage>>, HttpErrorJson> {
+    auth.require(Permission::ManageUsers)?;
+    let datastore = endpoints_get_lock!(state.datastore);
+    Ok(Json(datastore.get_outbox_messages()?))
+}
+
+/// Lists the accounts and client addresses with failed logins, including current lockouts
+#[get("/lockouts")]
+pub fn getLockouts(
+    state: &State<ServerState>,
+    auth: AuthenticatedUser,
+) -> Result<Json<Vec<LoginAttempts>>, HttpErrorJson> {
+    auth.require(Permission::ManageUsers)?;
+    let datastore = endpoints_get_lock!(state.datastore);
+    Ok(Json(datastore.get_all_login_attempts()?))
+}
+
+/// Lifts the lockout of an account (`account:<email>`) or client address (`ip:<address>`)
+#[delete("/lockouts?<subject>")]
+pub fn clearLockout(
+    state: &State<ServerState>,
+    subject: &str,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    auth.require(Permission::ManageUsers)?;
+    let datastore = endpoints_get_lock!(state.datastore);
+    datastore.clear_login_attempts(subject)?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "user.lockout_clear",
+        Some(subject.to_string()),
+    )?;
+    Ok(Json(true))
+}
+
+/// Lists all users, only for admins as it includes the email addresses
 #[get("/users")]
 pub fn getAllUsers(
     state: &State<ServerState>,
-    token: Token,
+    auth: AuthenticatedUser,
 ) -> Result<Json<Vec<PublicUser>>, HttpErrorJson> {
-    let tokenString = token.clone().0;
-    let userId = match validate_jwt(&tokenString) {
-        Ok(userId) => userId,
-        Err(_) => todo!(),
-    };
+    auth.require(Permission::ManageUsers)?;
     let datastore = endpoints_get_lock!(state.datastore);
 
     match datastore.get_all_users() {
@@ -164,3 +861,732 @@ pub fn getAllUsers(
         )),
     }
 }
+
+/// Makes sure a role can be the system wide role of a user
+fn check_system_role(role: Role) -> Result<(), HttpErrorJson> {
+    if role == Role::Viewer {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "The viewer role only exists within teams".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Changes the system wide role of a user
+#[put("/<user_id>/role", data = "<role>")]
+pub fn setRole(
+    state: &State<ServerState>,
+    user_id: i32,
+    role: Json<Role>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    auth.require(Permission::ManageUsers)?;
+    if user_id == auth.id {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "You can not change your own role".to_string(),
+        ));
+    }
+    check_system_role(role.0)?;
+    let datastore = endpoints_get_lock!(state.datastore);
+    datastore.set_user_role(user_id, role.0)?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "user.role_set",
+        target("user", user_id),
+    )?;
+    Ok(Json(true))
+}
+
+/// Deactivates or reactivates a user
+///
+/// Deactivated users can not log in or use their API keys, their sessions end immediately.
+#[put("/<user_id>/active", data = "<active>")]
+pub fn setActive(
+    state: &State<ServerState>,
+    user_id: i32,
+    active: Json<bool>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    auth.require(Permission::ManageUsers)?;
+    if user_id == auth.id {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "You can not deactivate your own account".to_string(),
+        ));
+    }
+    let datastore = endpoints_get_lock!(state.datastore);
+    datastore.set_user_active(user_id, active.0)?;
+    if !active.0 {
+        datastore.revoke_user_tokens(user_id)?;
+    }
+    let action = if active.0 {
+        "user.activate"
+    } else {
+        "user.deactivate"
+    };
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        action,
+        target("user", user_id),
+    )?;
+    Ok(Json(true))
+}
+
+/// Deletes a user and all data stored about them, for erasure requests
+#[delete("/<user_id>")]
+pub fn deleteUser(
+    state: &State<ServerState>,
+    user_id: i32,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<bool>, HttpErrorJson> {
+    auth.require(Permission::ManageUsers)?;
+    if user_id == auth.id {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "You can not delete your own account".to_string(),
+        ));
+    }
+    let datastore = endpoints_get_lock!(state.datastore);
+    let user = datastore.get_user(user_id)?;
+    datastore.delete_user(user_id)?;
+    datastore.clear_login_attempts(&throttle::account_subject(&user.email))?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "user.delete",
+        target("user", user_id),
+    )?;
+    Ok(Json(true))
+}
+
+// Parses a snake_case enum value given as query parameter
+fn parse_query_value<T: DeserializeOwned>(name: &str, value: &str) -> Result<T, HttpErrorJson> {
+    serde_json::from_value(serde_json::Value::String(value.to_string())).map_err(|_| {
+        HttpErrorJson::new(
+            Status::BadRequest,
+            format!("Invalid value for {name}: {value}"),
+        )
+    })
+}
+
+/// Searches the users of the server, a page at a time
+///
+/// `search` matches name, lastname, email and username, the other parameters filter by system
+/// wide role, team membership and whether the account is active.
+#[get("/directory?<query..>")]
+pub fn getUserDirectory(
+    state: &State<ServerState>,
+    query: DirectoryQuery,
+    auth: AuthenticatedUser,
+) -> Result<Json<DirectoryResponse>, HttpErrorJson> {
+    auth.require(Permission::ManageUsers)?;
+    let page_number = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DIRECTORY_DEFAULT_PAGE_SIZE)
+        .clamp(1, DIRECTORY_MAX_PAGE_SIZE);
+    let filter = UserFilter {
+        search: query
+            .search
+            .filter(|search| !search.is_empty())
+            .map(str::to_string),
+        role: match query.role {
+            Some(role) => Some(parse_query_value("role", role)?),
+            None => None,
+        },
+        team_id: query.team_id,
+        active: query.active,
+        sort: match query.sort {
+            Some(sort) => parse_query_value("sort", sort)?,
+            None => UserSort::default(),
+        },
+        descending: match query.order {
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(order) => {
+                return Err(HttpErrorJson::new(
+                    Status::BadRequest,
+                    format!("Invalid value for order: {order}"),
+                ))
+            }
+        },
+        offset: page_offset(page_number, per_page)?,
+        limit: per_page,
+    };
+    let datastore = endpoints_get_lock!(state.datastore);
+    Ok(Json(DirectoryResponse {
+        page: datastore.get_users_page(filter)?,
+        page_number,
+        per_page,
+    }))
+}
+
+// Short enough to be typed in, the user has to replace it at the first login anyway
+fn temporary_password() -> String {
+    random_token()[..16].to_string()
+}
+
+/// Creates an account with a temporary password, which has to be changed at the first login
+#[post("/users", data = "<input>")]
+pub fn createUser(
+    state: &State<ServerState>,
+    input: Json<NewUserModel>,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<TemporaryPasswordResponse>, HttpErrorJson> {
+    auth.require(Permission::ManageUsers)?;
+    if input.email.is_empty() || input.username.is_empty() {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "Email and username can not be empty".to_string(),
+        ));
+    }
+    let role = input.role.unwrap_or(Role::Member);
+    check_system_role(role)?;
+    let temporary_password = temporary_password();
+    let user = User {
+        id: 0,
+        email: input.email.to_string(),
+        username: input.username.to_string(),
+        password: generate_hash(&temporary_password),
+        name: input.name.to_string(),
+        lastname: input.lastname.to_string(),
+        role,
+        must_change_password: true,
+        // The address is given by an admin, so it does not have to be verified
+        email_verified: true,
+    };
+    let datastore = endpoints_get_lock!(state.datastore);
+    let user = datastore.add_user(user)?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "user.create",
+        target("user", user.id),
+    )?;
+    Ok(Json(TemporaryPasswordResponse {
+        user,
+        temporary_password,
+    }))
+}
+
+/// Replaces the password of a user with a temporary one, which has to be changed at the next
+/// login
+///
+/// All sessions of the user are ended and a lockout of the account is lifted.
+#[post("/<user_id>/password/reset")]
+pub fn resetUserPassword(
+    state: &State<ServerState>,
+    user_id: i32,
+    auth: AuthenticatedUser,
+    ip: Option<IpAddr>,
+) -> Result<Json<TemporaryPasswordResponse>, HttpErrorJson> {
+    auth.require(Permission::ManageUsers)?;
+    if user_id == auth.id {
+        return Err(HttpErrorJson::new(
+            Status::BadRequest,
+            "Use the change password form to change your own password".to_string(),
+        ));
+    }
+    let temporary_password = temporary_password();
+    let datastore = endpoints_get_lock!(state.datastore);
+    datastore.set_password(user_id, &generate_hash(&temporary_password), true)?;
+    datastore.revoke_user_tokens(user_id)?;
+    let user = datastore.get_user(user_id)?;
+    datastore.clear_login_attempts(&throttle::account_subject(&user.email))?;
+    audit::record(
+        &datastore,
+        Some(auth.id),
+        ip,
+        "user.password_reset",
+        target("user", user_id),
+    )?;
+    Ok(Json(TemporaryPasswordResponse {
+        user,
+        temporary_password,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::{Method, Status};
+    use serde_json::{json, Value};
+
+    use crate::config::AWConfig;
+    use crate::endpoints::test_client::{TestServer, PASSWORD};
+
+    #[test]
+    fn test_failed_logins_audited_without_email() {
+        let server = TestServer::new();
+        let (user_id, _) = server.create_user("alice", "member");
+        server.login("alice@example.com", "wrong password");
+        server.login("nobody@example.com", "wrong password");
+        server.login("Nobody@example.com", "wrong password");
+
+        let (status, page) = server.call(
+            Method::Get,
+            "/api/audit/?action=user.login_failed",
+            Some(&server.admin),
+            None,
+        );
+        assert_eq!(status, Status::Ok, "{page}");
+        let targets: Vec<&str> = page["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["target"].as_str().unwrap())
+            .collect();
+        assert_eq!(targets.len(), 3);
+        let user_target = format!("user:{user_id}");
+        assert!(targets.contains(&user_target.as_str()));
+        // Unknown accounts are only stored as a hash, which is the same for the same address
+        let unknown: Vec<&str> = targets
+            .into_iter()
+            .filter(|target| *target != user_target)
+            .collect();
+        assert_eq!(unknown[0], unknown[1]);
+        assert!(unknown[0].starts_with("account:"));
+        assert!(!unknown[0].contains("nobody"));
+    }
+
+    #[test]
+    fn test_team_owners_can_not_be_deleted() {
+        let server = TestServer::new();
+        let (owner_id, owner) = server.create_user("owner", "team_owner");
+        let team_id = server.create_team(&owner, "team");
+        let bucket_id = server.create_bucket(&owner, "currentwindow");
+        let delete_account = || {
+            server.call(
+                Method::Delete,
+                "/api/user/account",
+                Some(&owner),
+                Some(json!({"password": PASSWORD})),
+            )
+        };
+
+        assert_eq!(delete_account().0, Status::Conflict);
+        let (status, _) = server.call(
+            Method::Delete,
+            &format!("/api/user/{owner_id}"),
+            Some(&server.admin),
+            None,
+        );
+        assert_eq!(status, Status::Conflict);
+        // Nothing was deleted
+        let (status, _) = server.call(
+            Method::Get,
+            &format!("/api/0/buckets/{bucket_id}/info"),
+            Some(&owner),
+            None,
+        );
+        assert_eq!(status, Status::Ok);
+
+        let (status, _) = server.call(
+            Method::Delete,
+            &format!("/api/teams/{team_id}"),
+            Some(&owner),
+            None,
+        );
+        assert_eq!(status, Status::Ok);
+        assert_eq!(delete_account().0, Status::Ok);
+        assert_eq!(
+            server.login("owner@example.com", PASSWORD).0,
+            Status::BadRequest
+        );
+    }
+
+    #[test]
+    fn test_viewer_is_no_system_role() {
+        let server = TestServer::new();
+        let (user_id, _) = server.create_user("alice", "member");
+        let (status, _) = server.call(
+            Method::Post,
+            "/api/user/users",
+            Some(&server.admin),
+            Some(json!({
+                "email": "bob@example.com",
+                "username": "bob",
+                "name": "bob",
+                "lastname": "bob",
+                "role": "viewer",
+            })),
+        );
+        assert_eq!(status, Status::BadRequest);
+        let (status, _) = server.call(
+            Method::Put,
+            &format!("/api/user/{user_id}/role"),
+            Some(&server.admin),
+            Some(json!("viewer")),
+        );
+        assert_eq!(status, Status::BadRequest);
+        let (status, _) = server.call(
+            Method::Put,
+            &format!("/api/user/{user_id}/role"),
+            Some(&server.admin),
+            Some(json!("auditor")),
+        );
+        assert_eq!(status, Status::Ok);
+    }
+
+    #[test]
+    fn test_directory_page_out_of_range() {
+        let server = TestServer::new();
+        let (status, page) = server.call(
+            Method::Get,
+            "/api/user/directory?page=2&per_page=1",
+            Some(&server.admin),
+            None,
+        );
+        assert_eq!(status, Status::Ok, "{page}");
+        let (status, _) = server.call(
+            Method::Get,
+            &format!("/api/user/directory?page={}", i64::MAX),
+            Some(&server.admin),
+            None,
+        );
+        assert_eq!(status, Status::BadRequest);
+    }
+
+    #[test]
+    fn test_password_change_ends_other_sessions() {
+        let server = TestServer::new();
+        let (_, laptop) = server.create_user("alice", "member");
+        let (status, phone) = server.login("alice@example.com", PASSWORD);
+        assert_eq!(status, Status::Ok);
+        let phone_access = phone["access_token"].as_str().unwrap();
+        assert_eq!(
+            server.call(Method::Get, "/api/teams/user", Some(phone_access), None).0,
+            Status::Ok
+        );
+
+        let (status, tokens) = server.call(
+            Method::Post,
+            "/api/user/password",
+            Some(&laptop),
+            Some(json!({"old_password": PASSWORD, "new_password": "another password"})),
+        );
+        assert_eq!(status, Status::Ok, "{tokens}");
+        // The access and refresh tokens of the other device are revoked right away
+        assert_eq!(
+            server.call(Method::Get, "/api/teams/user", Some(phone_access), None).0,
+            Status::Unauthorized
+        );
+        let (status, _) = server.call(
+            Method::Post,
+            "/api/user/refresh",
+            None,
+            Some(json!({"refresh_token": phone["refresh_token"]})),
+        );
+        assert_ne!(status, Status::Ok);
+        let access = tokens["access_token"].as_str().unwrap();
+        assert_eq!(
+            server.call(Method::Get, "/api/teams/user", Some(access), None).0,
+            Status::Ok
+        );
+    }
+
+    #[test]
+    fn test_login_does_not_tell_accounts_apart() {
+        let mut config = AWConfig::default();
+        config.login_throttle.backoff_base = 0;
+        config.login_throttle.max_failures_per_account = 2;
+        config.login_throttle.max_failures_per_ip = 100;
+        let server = TestServer::with_config(config);
+        server.create_user("alice", "member");
+
+        let wrong_password = server.login("alice@example.com", "wrong password");
+        let unknown_account = server.login("nobody@example.com", "wrong password");
+        assert_eq!(wrong_password.0, Status::BadRequest);
+        assert_eq!(wrong_password, unknown_account);
+
+        // Both are locked out after the same number of failures
+        for email in ["alice@example.com", "nobody@example.com"] {
+            server.login(email, "wrong password");
+            let (status, _) = server.login(email, "wrong password");
+            assert_eq!(status, Status::TooManyRequests, "{email}");
+        }
+    }
+
+    #[test]
+    fn test_refresh_and_logout_revoke_tokens() {
+        let server = TestServer::new();
+        server.create_user("alice", "member");
+        let (_, tokens) = server.login("alice@example.com", PASSWORD);
+        let refresh = |refresh_token: &Value| {
+            server.call(
+                Method::Post,
+                "/api/user/refresh",
+                None,
+                Some(json!({ "refresh_token": refresh_token })),
+            )
+        };
+
+        // A refresh token can only be used once
+        let (status, refreshed) = refresh(&tokens["refresh_token"]);
+        assert_eq!(status, Status::Ok, "{refreshed}");
+        assert_eq!(refresh(&tokens["refresh_token"]).0, Status::Unauthorized);
+
+        let access = refreshed["access_token"].as_str().unwrap();
+        let (status, _) = server.call(
+            Method::Post,
+            "/api/user/logout",
+            Some(access),
+            Some(json!({"refresh_token": refreshed["refresh_token"]})),
+        );
+        assert_eq!(status, Status::Ok);
+        let (status, _) = server.call(Method::Get, "/api/user/getuser", Some(access), None);
+        assert_eq!(status, Status::Unauthorized);
+        assert_eq!(refresh(&refreshed["refresh_token"]).0, Status::Unauthorized);
+    }
+
+    #[test]
+    fn test_temporary_password_has_to_be_changed() {
+        let server = TestServer::new();
+        let (status, created) = server.call(
+            Method::Post,
+            "/api/user/users",
+            Some(&server.admin),
+            Some(json!({
+                "email": "alice@example.com",
+                "username": "alice",
+                "name": "alice",
+                "lastname": "alice",
+                "role": "member",
+            })),
+        );
+        assert_eq!(status, Status::Ok, "{created}");
+        let temporary = created["temporary_password"].as_str().unwrap();
+        let (status, tokens) = server.login("alice@example.com", temporary);
+        assert_eq!(status, Status::Ok, "{tokens}");
+        let access = tokens["access_token"].as_str().unwrap();
+
+        let (status, error) = server.call(Method::Get, "/api/teams/user", Some(access), None);
+        assert_eq!(status, Status::Forbidden);
+        assert_eq!(error["message"], "The password has to be changed first");
+        // The same password can not be kept
+        let (status, _) = server.call(
+            Method::Post,
+            "/api/user/password",
+            Some(access),
+            Some(json!({"old_password": temporary, "new_password": temporary})),
+        );
+        assert_eq!(status, Status::BadRequest);
+
+        let access = server.change_password(&tokens["access_token"], temporary);
+        let (status, _) = server.call(Method::Get, "/api/teams/user", Some(&access), None);
+        assert_eq!(status, Status::Ok);
+    }
+
+    #[test]
+    fn test_password_reset() {
+        let server = TestServer::new();
+        let (_, access) = server.create_user("alice", "member");
+        let (status, _) = server.call(
+            Method::Post,
+            "/api/user/password/reset",
+            None,
+            Some(json!({"email": "alice@example.com"})),
+        );
+        assert_eq!(status, Status::Ok);
+        let token = server.mailed_token("alice@example.com");
+        let confirm = |token: &str| {
+            server.call(
+                Method::Post,
+                "/api/user/password/reset/confirm",
+                None,
+                Some(json!({"token": token, "new_password": "another password"})),
+            )
+        };
+
+        let (status, confirmed) = confirm(&token);
+        assert_eq!(status, Status::Ok, "{confirmed}");
+        // The token can only be used once and the sessions of the user are ended
+        assert_eq!(confirm(&token).0, Status::BadRequest);
+        let (status, _) = server.call(Method::Get, "/api/teams/user", Some(&access), None);
+        assert_eq!(status, Status::Unauthorized);
+        assert_eq!(
+            server.login("alice@example.com", PASSWORD).0,
+            Status::BadRequest
+        );
+        assert_eq!(
+            server.login("alice@example.com", "another password").0,
+            Status::Ok
+        );
+
+        // Unknown addresses get the same response without a message being sent
+        let (status, _) = server.call(
+            Method::Post,
+            "/api/user/password/reset",
+            None,
+            Some(json!({"email": "nobody@example.com"})),
+        );
+        assert_eq!(status, Status::Ok);
+        let (_, outbox) = server.call(Method::Get, "/api/user/outbox", Some(&server.admin), None);
+        assert!(outbox
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|message| message["recipient"] != "nobody@example.com"));
+    }
+
+    #[test]
+    fn test_deactivated_user_rejected() {
+        let server = TestServer::new();
+        let (user_id, access) = server.create_user("alice", "member");
+        let key = server.create_api_key(&access);
+        let set_active = |active: bool| {
+            server
+                .call(
+                    Method::Put,
+                    &format!("/api/user/{user_id}/active"),
+                    Some(&server.admin),
+                    Some(json!(active)),
+                )
+                .0
+        };
+
+        assert_eq!(set_active(false), Status::Ok);
+        let (status, _) = server.call(Method::Get, "/api/teams/user", Some(&access), None);
+        assert_eq!(status, Status::Unauthorized);
+        let (status, error) = server.login("alice@example.com", PASSWORD);
+        assert_eq!(status, Status::Forbidden);
+        assert_eq!(error["message"], "The account has been deactivated");
+        let (status, _) = server.call(
+            Method::Post,
+            "/api/0/buckets/",
+            Some(&key),
+            Some(
+                json!({"type": "currentwindow", "created": null, "events": null, "last_updated": null}),
+            ),
+        );
+        assert_eq!(status, Status::Forbidden);
+
+        assert_eq!(set_active(true), Status::Ok);
+        assert_eq!(server.login("alice@example.com", PASSWORD).0, Status::Ok);
+        server.create_bucket(&key, "currentwindow");
+    }
+
+    #[test]
+    fn test_directory_search_and_pages() {
+        let server = TestServer::new();
+        let (_, member) = server.create_user("alice", "member");
+        let (_, owner) = server.create_user("bob", "team_owner");
+        server.create_user("carol", "auditor");
+        let directory = |query: &str, token: &str| {
+            server.call(
+                Method::Get,
+                &format!("/api/user/directory?{query}"),
+                Some(token),
+                None,
+            )
+        };
+        let names = |page: &Value| -> Vec<String> {
+            page["users"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|user| user["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let query = "search=example.com&sort=name&order=desc&per_page=2";
+        let (status, page) = directory(query, &server.admin);
+        assert_eq!(status, Status::Ok, "{page}");
+        assert_eq!(page["total"], 3);
+        assert_eq!(names(&page), ["carol", "bob"]);
+        let (_, page) = directory(&format!("{query}&page=2"), &server.admin);
+        assert_eq!(names(&page), ["alice"]);
+        let (_, page) = directory("role=auditor", &server.admin);
+        assert_eq!(names(&page), ["carol"]);
+
+        assert_eq!(directory("sort=password", &server.admin).0, Status::BadRequest);
+        // Only admins can list the users
+        for token in [&member, &owner] {
+            assert_eq!(directory("", token).0, Status::Forbidden);
+            let (status, _) = server.call(Method::Get, "/api/user/users", Some(token), None);
+            assert_eq!(status, Status::Forbidden);
+        }
+    }
+
+    #[test]
+    fn test_unverified_email_can_not_join_teams() {
+        let server = TestServer::new();
+        let (_, owner) = server.create_user("owner", "team_owner");
+        let team_id = server.create_team(&owner, "team");
+        let (status, _) = server.call(
+            Method::Post,
+            "/api/user/signup",
+            None,
+            Some(json!({
+                "email": "alice@example.com",
+                "password": PASSWORD,
+                "name": "alice",
+                "lastname": "alice",
+                "username": "alice",
+            })),
+        );
+        assert_eq!(status, Status::Ok);
+        let (_, tokens) = server.login("alice@example.com", PASSWORD);
+        let access = tokens["access_token"].as_str().unwrap();
+        let (_, invitation) = server.call(
+            Method::Post,
+            &format!("/api/teams/{team_id}/invitations"),
+            Some(&owner),
+            Some(json!({})),
+        );
+        let accept = format!("/api/invitations/{}/accept", invitation["code"].as_str().unwrap());
+
+        let (status, error) = server.call(Method::Post, &accept, Some(access), None);
+        assert_eq!(status, Status::Forbidden);
+        assert_eq!(error["message"], "The email address has to be verified first");
+
+        let verify = |token: &str| {
+            server
+                .call(
+                    Method::Post,
+                    "/api/user/email/verify",
+                    None,
+                    Some(json!({ "token": token })),
+                )
+                .0
+        };
+        let token = server.mailed_token("alice@example.com");
+        assert_eq!(verify("unknown"), Status::BadRequest);
+        assert_eq!(verify(&token), Status::Ok);
+        assert_eq!(verify(&token), Status::BadRequest);
+        let (status, _) = server.call(Method::Post, &accept, Some(access), None);
+        assert_eq!(status, Status::Ok);
+    }
+
+    #[test]
+    fn test_lockouts_only_for_admins() {
+        let server = TestServer::new();
+        let (_, owner) = server.create_user("owner", "team_owner");
+        let (_, manager) = server.create_user("manager", "team_manager");
+        server.login("nobody@example.com", "wrong password");
+
+        let (status, lockouts) = server.call(
+            Method::Get,
+            "/api/user/lockouts",
+            Some(&server.admin),
+            None,
+        );
+        assert_eq!(status, Status::Ok);
+        assert!(!lockouts.as_array().unwrap().is_empty());
+        for token in [&owner, &manager] {
+            let (status, _) = server.call(Method::Get, "/api/user/lockouts", Some(token), None);
+            assert_eq!(status, Status::Forbidden);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use std::io::Cursor;
 
+use chrono::{DateTime, Utc};
 use rocket::http::ContentType;
 use rocket::http::Header;
 use rocket::http::Status;
@@ -9,7 +10,7 @@ use serde::Serialize;
 
 use aw_models::BucketsExport;
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct HttpErrorJson {
     #[serde(skip_serializing)]
     status: Status,
@@ -23,6 +24,10 @@ impl HttpErrorJson {
             message: err,
         }
     }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
 }
 
 impl<'r> Responder<'r, 'static> for HttpErrorJson {
@@ -69,6 +74,30 @@ impl<'r> Responder<'r, 'static> for BucketsExportRocket {
 
 use aw_datastore::DatastoreError;
 
+/// Parses an optional rfc3339 query parameter, `name` is the parameter used in the error
+pub fn parse_time(name: &str, value: Option<&str>) -> Result<Option<DateTime<Utc>>, HttpErrorJson> {
+    match value {
+        Some(value) => match DateTime::parse_from_rfc3339(value) {
+            Ok(dt) => Ok(Some(dt.with_timezone(&Utc))),
+            Err(err) => Err(HttpErrorJson::new(
+                Status::BadRequest,
+                format!("Failed to parse {name}, datetime needs to be in rfc3339 format: {err}"),
+            )),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Number of entries before a page, pages start at 1
+pub fn page_offset(page_number: i64, per_page: i64) -> Result<i64, HttpErrorJson> {
+    (page_number - 1).checked_mul(per_page).ok_or_else(|| {
+        HttpErrorJson::new(
+            Status::BadRequest,
+            format!("The page {page_number} is out of range"),
+        )
+    })
+}
+
 impl From<DatastoreError> for HttpErrorJson {
     fn from(val: DatastoreError) -> Self {
         match val {
@@ -76,6 +105,10 @@ impl From<DatastoreError> for HttpErrorJson {
                 Status::NotFound,
                 format!("The requested bucket '{bucket_id}' does not exist"),
             ),
+            DatastoreError::NoSuchEvent(event_id) => HttpErrorJson::new(
+                Status::NotFound,
+                format!("The event {event_id} does not exist in the bucket"),
+            ),
             DatastoreError::BucketAlreadyExists(bucket_id) => HttpErrorJson::new(
                 Status::NotModified,
                 format!("Bucket '{bucket_id}' already exists"),
@@ -101,6 +134,11 @@ impl From<DatastoreError> for HttpErrorJson {
             DatastoreError::NoUser() => {
                 HttpErrorJson::new(Status::BadRequest, "No User found".to_string())
             }
+            DatastoreError::UserAlreadyExists(msg) => HttpErrorJson::new(Status::BadRequest, msg),
+            DatastoreError::UserOwnsTeams(_) => HttpErrorJson::new(
+                Status::Conflict,
+                "The teams owned by the user have to be transferred or deleted first".to_string(),
+            ),
         }
     }
 }
@@ -55,6 +55,15 @@ struct Opts {
     /// Don't import from aw-server-python if no aw-server-rust db found
     #[clap(long)]
     no_legacy_import: bool,
+
+    /// Email of the admin account, replaces the default admin@admin.com until the admin has
+    /// changed the password
+    #[clap(long, requires = "admin_password")]
+    admin_email: Option<String>,
+
+    /// Password of the admin account, replaces the default until the admin has changed it
+    #[clap(long, requires = "admin_email")]
+    admin_password: Option<String>,
 }
 
 #[rocket::main]
@@ -139,10 +148,22 @@ async fn main() -> Result<(), rocket::Error> {
         device_id::get_device_id()
     };
 
+    // Even if legacy_import is set to true it is disabled on Android so
+    // it will not happen there
+    let datastore = aw_datastore::Datastore::new(db_path, legacy_import);
+
+    if let (Some(email), Some(password)) = (opts.admin_email, opts.admin_password) {
+        match datastore.set_initial_admin(&email, &password) {
+            Ok(true) => info!("Set the admin credentials to {}", email),
+            Ok(false) => warn!(
+                "Admin password has already been changed, ignoring --admin-email and --admin-password"
+            ),
+            Err(err) => panic!("Failed to set the admin credentials: {err:?}"),
+        }
+    }
+
     let server_state = endpoints::ServerState {
-        // Even if legacy_import is set to true it is disabled on Android so
-        // it will not happen there
-        datastore: Mutex::new(aw_datastore::Datastore::new(db_path, legacy_import)),
+        datastore: Mutex::new(datastore),
         asset_resolver: endpoints::AssetResolver::new(asset_path),
         device_id,
     };
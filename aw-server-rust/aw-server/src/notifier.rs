@@ -0,0 +1,129 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+
+use chrono::Utc;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{SmtpTransport, Transport};
+use serde::Serialize;
+
+use aw_datastore::Datastore;
+
+use crate::config::NotifierConfig;
+
+/// A message to a user, like a password reset link
+#[derive(Serialize, Clone, Debug)]
+pub struct Notification {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers notifications to users
+///
+/// Which implementation is used is set by the `[notifier]` section of the config.
+pub trait Notifier: Send + Sync {
+    fn send(&self, notification: &Notification, datastore: &Mutex<Datastore>)
+        -> Result<(), String>;
+}
+
+pub fn from_config(config: &NotifierConfig) -> Box<dyn Notifier> {
+    match config {
+        NotifierConfig::Smtp {
+            host,
+            port,
+            username,
+            password,
+            tls,
+            from,
+        } => {
+            let builder = if *tls {
+                SmtpTransport::relay(host)
+            } else {
+                SmtpTransport::starttls_relay(host)
+            };
+            let mut builder = builder.expect("Invalid SMTP host in notifier config");
+            if let Some(port) = port {
+                builder = builder.port(*port);
+            }
+            if let (Some(username), Some(password)) = (username, password) {
+                builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+            }
+            Box::new(SmtpNotifier {
+                transport: builder.build(),
+                from: from.clone(),
+            })
+        }
+        NotifierConfig::File { path } => Box::new(FileNotifier { path: path.clone() }),
+        NotifierConfig::Database => Box::new(DatabaseNotifier),
+    }
+}
+
+pub struct SmtpNotifier {
+    transport: SmtpTransport,
+    from: String,
+}
+
+impl Notifier for SmtpNotifier {
+    fn send(&self, notification: &Notification, _: &Mutex<Datastore>) -> Result<(), String> {
+        let email = lettre::Message::builder()
+            .from(
+                self.from
+                    .parse()
+                    .map_err(|e| format!("Invalid sender: {e}"))?,
+            )
+            .to(notification
+                .to
+                .parse()
+                .map_err(|e| format!("Invalid recipient: {e}"))?)
+            .subject(notification.subject.clone())
+            .body(notification.body.clone())
+            .map_err(|e| e.to_string())?;
+        self.transport
+            .send(&email)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to send email: {e}"))
+    }
+}
+
+pub struct FileNotifier {
+    path: String,
+}
+
+#[derive(Serialize)]
+struct FileEntry<'a> {
+    created: chrono::DateTime<Utc>,
+    #[serde(flatten)]
+    notification: &'a Notification,
+}
+
+impl Notifier for FileNotifier {
+    fn send(&self, notification: &Notification, _: &Mutex<Datastore>) -> Result<(), String> {
+        let entry = FileEntry {
+            created: Utc::now(),
+            notification,
+        };
+        let line = serde_json::to_string(&entry).map_err(|e| e.to_string())?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("Failed to open outbox file {}: {e}", self.path))?;
+        writeln!(file, "{line}").map_err(|e| format!("Failed to write outbox file: {e}"))
+    }
+}
+
+pub struct DatabaseNotifier;
+
+impl Notifier for DatabaseNotifier {
+    fn send(
+        &self,
+        notification: &Notification,
+        datastore: &Mutex<Datastore>,
+    ) -> Result<(), String> {
+        let datastore = datastore.lock().map_err(|e| e.to_string())?;
+        datastore
+            .add_outbox_message(&notification.to, &notification.subject, &notification.body)
+            .map_err(|e| format!("Failed to store message in outbox: {e:?}"))
+    }
+}
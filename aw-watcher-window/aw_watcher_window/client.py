@@ -1,6 +1,10 @@
+import logging
+
 import requests
 import json
 
+logger = logging.getLogger(__name__)
+
 
 def singleton(cls):
     instances = {}
@@ -19,4 +23,6 @@ class LocalClient():
         headers = {'Authorization': self.token}
         response = requests.get(self.url+f'/api/teams/configuration/{team_id}', headers=headers)
         if(response.status_code == 200):
-            return response.json()
\ No newline at end of file
+            return response.json()
+        logger.error(f"Failed to get the configuration of team {team_id}: {response.status_code} {response.text}")
+        return None
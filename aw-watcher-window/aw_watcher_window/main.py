@@ -39,6 +39,32 @@ def try_compile_title_regex(title):
         exit(1)
 
 
+class TrackedApps:
+    """Matches windows against the tracked and excluded apps of a team configuration
+
+    No app is tracked without a configuration or without tracked apps.
+    """
+
+    def __init__(self, configuration):
+        configuration = configuration or {}
+        self.tracked = [
+            (
+                re.compile(rule["app"]),
+                re.compile(rule["title"]) if rule.get("title") is not None else None,
+            )
+            for rule in configuration.get("tracked_apps", [])
+        ]
+        self.excluded = [re.compile(app) for app in configuration.get("excluded_apps", [])]
+
+    def matches(self, app, title):
+        if any(pattern.search(app) for pattern in self.excluded):
+            return False
+        return any(
+            app_pattern.search(app) and (title_pattern is None or title_pattern.search(title))
+            for app_pattern, title_pattern in self.tracked
+        )
+
+
 def main():
     args = parse_args()
 
@@ -66,7 +92,10 @@ def main():
     if(args.testing):
         port = 5666
     local_client = LocalClient(f"http://{host}:{port}")
-    configuration_apps = local_client.get_team_configuration(team_id=team_id)
+    local_client.token = f"Bearer {args.token}"
+    configuration = local_client.get_team_configuration(team_id=team_id)
+    if configuration is None:
+        logger.warning("No team configuration, the titles and apps of all windows are hidden")
     bucket_id = 0
     event_type = "currentwindow"
 
@@ -105,13 +134,13 @@ def main():
                 poll_time=args.poll_time,
                 strategy=args.strategy,
                 team_id = team_id,
-                include_titles=configuration_apps,
+                tracked_apps=TrackedApps(configuration),
                 exclude_title=args.exclude_title,
                 exclude_titles=[try_compile_title_regex(title) for title in args.exclude_titles if title is not None]
             )
 
 
-def heartbeat_loop(client, bucket_id, poll_time, strategy, team_id:int, include_titles=[], exclude_title=False, exclude_titles=[]):
+def heartbeat_loop(client, bucket_id, poll_time, strategy, team_id:int, tracked_apps=TrackedApps(None), exclude_title=False, exclude_titles=[]):
     while True:
         if os.getppid() == 1:
             logger.info("window-watcher stopped because parent process died")
@@ -144,11 +173,7 @@ def heartbeat_loop(client, bucket_id, poll_time, strategy, team_id:int, include_
         if current_window is None:
             logger.debug("Unable to fetch window, trying again on next poll")
         else:
-            is_included = False
-            for app_name in include_titles:
-                if(app_name in current_window["title"]):
-                    is_included = True
-            if(is_included == False):
+            if not tracked_apps.matches(current_window["app"], current_window["title"]):
                 current_window["title"] = "Hidden By Privacy Configuration"
                 current_window["app"] = "Hidden By Privacy Configuration"
             for pattern in exclude_titles:
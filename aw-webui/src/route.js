@@ -27,9 +27,19 @@ const Graph = () => import('./views/Graph.vue');
 const NotFound = () => import('./views/NotFound.vue');
 const Login = () => import('./views/Login.vue');
 const Signup = () => import('./views/Signup.vue');
+const ChangePassword = () => import('./views/ChangePassword.vue');
+const ResetPassword = () => import('./views/ResetPassword.vue');
+const VerifyEmail = () => import('./views/VerifyEmail.vue');
+const OidcCallback = () => import('./views/OidcCallback.vue');
+const TwoFactorLogin = () => import('./views/TwoFactorLogin.vue');
+const TwoFactorSetup = () => import('./views/TwoFactorSetup.vue');
 const Teams = () => import('./views/Teams.vue');
 const TeamDetail = () => import('./views/TeamDetail.vue');
+const TeamReport = () => import('./views/TeamReport.vue');
 const UserActivity = () => import('./views/UserActivity.vue');
+const Users = () => import('./views/Users.vue');
+const AuditLog = () => import('./views/AuditLog.vue');
+const Join = () => import('./views/Join.vue');
 
 Vue.use(VueRouter);
 
@@ -43,9 +53,19 @@ const router = new VueRouter({
     },
     // { path: '/home', component: Home },
     { path: '/login', component: Login },
+    { path: '/login/2fa', component: TwoFactorLogin },
+    { path: '/oidc/callback', component: OidcCallback },
     { path: '/signup', component: Signup },
+    { path: '/join', component: Join },
+    { path: '/password', component: ChangePassword },
+    { path: '/reset-password', component: ResetPassword },
+    { path: '/verify-email', component: VerifyEmail },
+    { path: '/two-factor', component: TwoFactorSetup },
     { path: '/teams', component: Teams },
     { path: '/team/:id', component: TeamDetail },
+    { path: '/team/:id/report', component: TeamReport },
+    { path: '/users', component: Users },
+    { path: '/audit', component: AuditLog },
     { path: '/user/:userId/:teamId', component: UserActivity, meta: { fullContainer: true } },
     {
       path: '/activity/:host/:periodLength?/:date?',
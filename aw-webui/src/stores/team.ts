@@ -32,9 +32,23 @@ export const useTeamStore = defineStore('team', {
       await this.getTeams();
     },
 
-    editTeam(team: TeamModel) {
+    async editTeam(team: TeamModel) {
       const client = getClient();
-      client.editTeam(team);
+      const response = await client.editTeam(team);
+      return response.data;
+    },
+
+    async transferTeam(teamId: number, userId: number) {
+      const client = getClient();
+      const response = await client.transferTeam(teamId, userId);
+      return response.data;
+    },
+
+    async deleteTeam(teamId: number) {
+      const client = getClient();
+      const response = await client.deleteTeam(teamId);
+      await this.getTeams();
+      return response.data;
     },
 
     async getTeam(teamId: number) {
@@ -55,6 +69,18 @@ export const useTeamStore = defineStore('team', {
       return response.data;
     },
 
+    async setMemberRole(teamId: number, memberId: number, role: string) {
+      const client = getClient();
+      const response = await client.setMemberRole(teamId, memberId, role);
+      return response.data;
+    },
+
+    async setMemberSchedule(teamId: number, memberId: number, schedule: any) {
+      const client = getClient();
+      const response = await client.setMemberSchedule(teamId, memberId, schedule);
+      return response.data;
+    },
+
     async addConfiguration(teamId: number, config: any) {
       const client = getClient();
       const response = await client.addConfiguration(teamId, config);
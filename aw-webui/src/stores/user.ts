@@ -7,6 +7,10 @@ interface User {
   roles: number[];
   isLoggedIn: boolean;
   token: string;
+  refreshToken: string;
+  mustChangePassword: boolean;
+  mustEnableTwoFactor: boolean;
+  twoFactorChallenge?: string;
 }
 
 interface UserSignupModel {
@@ -15,6 +19,7 @@ interface UserSignupModel {
   name: string;
   lastname: string;
   password: string;
+  invitation?: string;
 }
 
 export const useUserStore = defineStore('user', {
@@ -26,6 +31,9 @@ export const useUserStore = defineStore('user', {
         userId: '',
         roles: [],
         token: '',
+        refreshToken: '',
+        mustChangePassword: false,
+        mustEnableTwoFactor: false,
       }
     );
   },
@@ -34,15 +42,82 @@ export const useUserStore = defineStore('user', {
       const client = getClient();
       const response = await client.login(email, password);
       if (response.status === 200) {
-        const token = response.data;
-        this.setLoginState(token);
-        this.setToken();
-        this.persistToLocalStorage();
-        this.getUser();
+        if (response.data.two_factor_required) {
+          this.twoFactorChallenge = response.data.challenge;
+          route.push('/login/2fa');
+        } else {
+          this.completeLogin(response.data);
+        }
+      }
+    },
+
+    async loginTwoFactor(code: string) {
+      const client = getClient();
+      const response = await client.loginTwoFactor(this.twoFactorChallenge, code);
+      // The challenge can only be used once, a wrong code means logging in again
+      this.twoFactorChallenge = undefined;
+      if (response.status === 200) {
+        this.completeLogin(response.data);
+      } else {
+        route.push('/login');
+      }
+    },
+
+    async loginWithOidc() {
+      const response = await getClient().oidcAuthorize();
+      if (response.status === 200) {
+        // The identity provider sends the user back to /oidc/callback
+        window.location.href = response.data.authorization_url;
+      }
+    },
+
+    async completeOidcLogin(code: string, state: string) {
+      const response = await getClient().oidcCallback(code, state);
+      if (response.status === 200) {
+        this.completeLogin(response.data);
+      } else {
+        route.push('/login');
+      }
+    },
+
+    completeLogin(tokens) {
+      const { access_token, refresh_token, must_change_password, must_enable_two_factor } = tokens;
+      this.setLoginState(access_token, refresh_token);
+      this.mustChangePassword = must_change_password;
+      this.mustEnableTwoFactor = must_enable_two_factor;
+      this.setToken();
+      this.persistToLocalStorage();
+      this.getUser();
+      if (must_change_password) {
+        route.push('/password');
+      } else if (must_enable_two_factor) {
+        route.push('/two-factor');
+      } else if (localStorage.getItem('pendingInvitation')) {
+        // Opened an invitation link before logging in
+        route.push('/join');
+      } else {
         route.push('/teams');
       }
     },
 
+    async changePassword(oldPassword: string, newPassword: string) {
+      const client = getClient();
+      const response = await client.changePassword(oldPassword, newPassword);
+      if (response.status === 200) {
+        this.completeLogin(response.data);
+      }
+    },
+
+    async confirmTwoFactor(code: string): Promise<string[] | null> {
+      const response = await getClient().confirmTwoFactor(code);
+      if (response.status !== 200) {
+        return null;
+      }
+      this.mustEnableTwoFactor = false;
+      this.persistToLocalStorage();
+      return response.data;
+    },
+
     setToken() {
       const client = getClient();
       client.setToken(this.token);
@@ -50,16 +125,33 @@ export const useUserStore = defineStore('user', {
 
     signup(user: UserSignupModel) {
       const client = getClient();
-      client.signup(user);
+      return client.signup(user);
     },
 
-    setLoginState(token: string) {
+    setLoginState(token: string, refreshToken: string) {
       this.token = token;
+      this.refreshToken = refreshToken;
       this.isLoggedIn = true;
     },
 
+    async refresh() {
+      const client = getClient();
+      const response = await client.refresh(this.refreshToken);
+      if (response.status === 200) {
+        const { access_token, refresh_token } = response.data;
+        this.setLoginState(access_token, refresh_token);
+        this.setToken();
+        this.persistToLocalStorage();
+      }
+    },
+
     logout() {
+      if (this.isLoggedIn) {
+        getClient().logout(this.refreshToken);
+      }
+      getClient().clearToken();
       this.token = '';
+      this.refreshToken = '';
       this.isLoggedIn = false;
       this.clearLocalStorage();
     },
@@ -72,6 +164,9 @@ export const useUserStore = defineStore('user', {
           roles: this.roles,
           teamIds: this.teamIds,
           token: this.token,
+          refreshToken: this.refreshToken,
+          mustChangePassword: this.mustChangePassword,
+          mustEnableTwoFactor: this.mustEnableTwoFactor,
           userId: this.userId,
         } as User)
       );
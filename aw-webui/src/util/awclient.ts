@@ -10,6 +10,14 @@ interface GetEventsOptions {
   teamId?: number;
 }
 
+export interface AuditLogParams {
+  actor?: number;
+  action?: string;
+  target?: string;
+  since?: string;
+  until?: string;
+}
+
 export class CustomAwClient extends AWClient {
   constructor(clientName: string, options?: AWReqOptions) {
     super(clientName, options);
@@ -33,8 +41,128 @@ export class CustomAwClient extends AWClient {
     return this.req.post('/user/login', { email, password: password });
   }
 
+  loginTwoFactor(challenge: string, code: string) {
+    return this.req.post('/user/login/2fa', { challenge, code });
+  }
+
+  async getOidcInfo() {
+    const response = await this.req.get('/oidc/');
+    return response.data;
+  }
+
+  oidcAuthorize() {
+    return this.req.post('/oidc/authorize');
+  }
+
+  oidcCallback(code: string, state: string) {
+    return this.req.post('/oidc/callback', { code, state });
+  }
+
+  enrollTwoFactor() {
+    return this.req.post('/user/2fa/enroll');
+  }
+
+  confirmTwoFactor(code: string) {
+    return this.req.post('/user/2fa/confirm', { code });
+  }
+
+  disableTwoFactor(code: string) {
+    return this.req.post('/user/2fa/disable', { code });
+  }
+
+  resetTwoFactor(userId: number) {
+    return this.req.delete(`/user/2fa/${userId}`);
+  }
+
+  async getTwoFactorPolicy() {
+    const response = await this.req.get('/user/2fa/policy');
+    return response.data;
+  }
+
+  setTwoFactorPolicy(requireForTeamOwners: boolean) {
+    return this.req.put('/user/2fa/policy', { require_for_team_owners: requireForTeamOwners });
+  }
+
+  refresh(refreshToken: string) {
+    return this.req.post('/user/refresh', { refresh_token: refreshToken });
+  }
+
+  logout(refreshToken: string) {
+    return this.req.post('/user/logout', { refresh_token: refreshToken });
+  }
+
+  changePassword(oldPassword: string, newPassword: string) {
+    return this.req.post('/user/password', {
+      old_password: oldPassword,
+      new_password: newPassword,
+    });
+  }
+
+  requestPasswordReset(email: string) {
+    return this.req.post('/user/password/reset', { email });
+  }
+
+  confirmPasswordReset(token: string, newPassword: string) {
+    return this.req.post('/user/password/reset/confirm', {
+      token,
+      new_password: newPassword,
+    });
+  }
+
+  verifyEmail(token: string) {
+    return this.req.post('/user/email/verify', { token });
+  }
+
+  resendEmailVerification() {
+    return this.req.post('/user/email/verify/resend');
+  }
+
+  async getApiKeys() {
+    const response = await this.req.get('/user/keys/');
+    return response.data;
+  }
+
+  createApiKey(name: string) {
+    return this.req.post('/user/keys/', { name });
+  }
+
+  deleteApiKey(keyId: number) {
+    return this.req.delete(`/user/keys/${keyId}`);
+  }
+
   signup(user) {
-    this.req.post('/user/signup', user);
+    return this.req.post('/user/signup', user);
+  }
+
+  async getTeamInvitations(teamId: number) {
+    const response = await this.req.get(`/teams/${teamId}/invitations`);
+    return response.data;
+  }
+
+  createTeamInvitation(teamId: number, invitation: { max_uses?: number; expires_in?: number }) {
+    return this.req.post(`/teams/${teamId}/invitations`, invitation);
+  }
+
+  deleteTeamInvitation(teamId: number, invitationId: number) {
+    return this.req.delete(`/teams/${teamId}/invitations/${invitationId}`);
+  }
+
+  async getInvitation(code: string) {
+    const response = await this.req.get(`/invitations/${code}`);
+    return response.data;
+  }
+
+  acceptInvitation(code: string) {
+    return this.req.post(`/invitations/${code}/accept`);
+  }
+
+  async getRegistrationPolicy() {
+    const response = await this.req.get('/invitations/policy');
+    return response.data;
+  }
+
+  setRegistrationPolicy(openRegistration: boolean) {
+    return this.req.put('/invitations/policy', { open_registration: openRegistration });
   }
 
   setToken(token: string) {
@@ -49,10 +177,71 @@ export class CustomAwClient extends AWClient {
     return this.req.get('/user/getuser');
   }
 
+  updateProfile(profile: { name: string; lastname: string; username: string; email: string }) {
+    return this.req.put('/user/profile', profile);
+  }
+
+  deleteAccount(password: string) {
+    return this.req.delete('/user/account', { data: { password } });
+  }
+
+  setUserActive(userId: number, active: boolean) {
+    return this.req.put(`/user/${userId}/active`, JSON.stringify(active), {
+      headers: { 'Content-Type': 'application/json' },
+    });
+  }
+
+  deleteUser(userId: number) {
+    return this.req.delete(`/user/${userId}`);
+  }
+
   getAllUsers() {
     return this.req.get('/user/users');
   }
 
+  async getUserDirectory(params: {
+    page?: number;
+    per_page?: number;
+    sort?: string;
+    order?: string;
+    search?: string;
+    role?: string;
+    team_id?: number;
+    active?: boolean;
+  }) {
+    const response = await this.req.get('/user/directory', { params });
+    return response.data;
+  }
+
+  createUser(user: {
+    email: string;
+    username: string;
+    name: string;
+    lastname: string;
+    role?: string;
+  }) {
+    return this.req.post('/user/users', user);
+  }
+
+  resetUserPassword(userId: number) {
+    return this.req.post(`/user/${userId}/password/reset`);
+  }
+
+  async getLockouts() {
+    const response = await this.req.get('/user/lockouts');
+    return response.data;
+  }
+
+  clearLockout(subject: string) {
+    return this.req.delete('/user/lockouts', { params: { subject } });
+  }
+
+  setRole(userId: number, role: string) {
+    return this.req.put(`/user/${userId}/role`, JSON.stringify(role), {
+      headers: { 'Content-Type': 'application/json' },
+    });
+  }
+
   getTeams() {
     return this.req.get('/teams');
   }
@@ -62,7 +251,17 @@ export class CustomAwClient extends AWClient {
   }
 
   editTeam(team) {
-    return this.req.put('/teams', team);
+    return this.req.put(`/teams/${team.id}`, { name: team.name, description: team.description });
+  }
+
+  transferTeam(teamId: number, userId: number) {
+    return this.req.put(`/teams/${teamId}/owner`, userId, {
+      headers: { 'Content-Type': 'application/json' },
+    });
+  }
+
+  deleteTeam(teamId: number) {
+    return this.req.delete(`/teams/${teamId}`);
   }
 
   getTeam(teamId: number) {
@@ -77,6 +276,18 @@ export class CustomAwClient extends AWClient {
     return this.req.delete(`/teams/${teamId}/member/${memberId}`);
   }
 
+  setMemberRole(teamId: number, memberId: number, role: string) {
+    return this.req.put(`/teams/${teamId}/member/${memberId}/role`, JSON.stringify(role), {
+      headers: { 'Content-Type': 'application/json' },
+    });
+  }
+
+  setMemberSchedule(teamId: number, memberId: number, schedule: any) {
+    return this.req.put(`/teams/${teamId}/member/${memberId}/schedule`, JSON.stringify(schedule), {
+      headers: { 'Content-Type': 'application/json' },
+    });
+  }
+
   getConfiguration(teamId: number) {
     return this.req.get(`/teams/configuration/${teamId}`);
   }
@@ -85,6 +296,29 @@ export class CustomAwClient extends AWClient {
     return this.req.post(`/teams/${teamId}/configuration`, configuration);
   }
 
+  async getConfigurationRevisions(teamId: number) {
+    const response = await this.req.get(`/teams/${teamId}/configuration/revisions`);
+    return response.data;
+  }
+
+  async diffConfigurationRevisions(teamId: number, from: number, to: number) {
+    const response = await this.req.get(`/teams/${teamId}/configuration/diff`, {
+      params: { from, to },
+    });
+    return response.data;
+  }
+
+  rollbackConfiguration(teamId: number, revision: number) {
+    return this.req.post(`/teams/${teamId}/configuration/rollback`, JSON.stringify(revision), {
+      headers: { 'Content-Type': 'application/json' },
+    });
+  }
+
+  async getTeamReport(teamId: number, params: { start?: string; end?: string; granularity?: string; working_hours?: boolean }) {
+    const response = await this.req.get(`/teams/${teamId}/report`, { params });
+    return response.data;
+  }
+
   override async getEvents(bucketId: string, params?: GetEventsOptions): Promise<IEvent[]> {
     const response = await this.req.get(`/0/buckets/${bucketId}/events?start=${params.start}&end=${params.end}&limit=${params.limit}`)
     return response.data;
@@ -95,6 +329,26 @@ export class CustomAwClient extends AWClient {
     return response.data;
   }
 
+  async getAuditLog(params: AuditLogParams & { page?: number; per_page?: number }) {
+    const response = await this.req.get('/audit/', { params });
+    return response.data;
+  }
+
+  async exportAuditLog(params: AuditLogParams, format: 'json' | 'csv'): Promise<Blob> {
+    const response = await this.req.get('/audit/export', {
+      params: { ...params, format },
+      responseType: 'blob',
+    });
+    return response.data;
+  }
+
+  /** Exports one bucket, or all buckets of the user, as a JSON file */
+  async exportBuckets(bucketId?: number): Promise<Blob> {
+    const url = bucketId === undefined ? '/0/export/' : `/0/buckets/${bucketId}/export`;
+    const response = await this.req.get(url, { responseType: 'blob' });
+    return response.data;
+  }
+
   async getBucket(bucketId: number): Promise<IBucket> {
     const response = await this.req.get(`/0/buckets/${bucketId}/info`);
     return response.data;